@@ -33,14 +33,17 @@ fn run(args: &Args) -> Result<()> {
     let s_be_going_to_verb = CohaSearch {
         label: "be-going-to-verb".to_owned(),
         filter_list: vec![&f_vb, &f_going, &f_to, &f_v_i],
+        ..Default::default()
     };
     let s_gonna_verb = CohaSearch {
         label: "gonna-verb".to_owned(),
         filter_list: vec![&f_gon, &f_na, &f_v_i],
+        ..Default::default()
     };
     let s_gonna_any = CohaSearch {
         label: "gonna-any".to_owned(),
         filter_list: vec![&f_gon, &f_na, &CohaFilter::Any],
+        ..Default::default()
     };
     coha.search(
         &args.result_dir,
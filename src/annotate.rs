@@ -0,0 +1,45 @@
+use crate::{Genre, PublicationDate, TextId, Word};
+
+/// A hit as seen by a [`HitAnnotator`].
+pub struct Hit<'a> {
+    pub label: &'a str,
+    pub text_id: TextId,
+    pub genre: Genre,
+    pub year: u16,
+    pub date: Option<PublicationDate>,
+    pub title: &'a str,
+    pub author: &'a str,
+    /// Position of the hit in the text, as in the "position" column.
+    pub position: usize,
+    /// The matched words; with slots, one word per slot (including hidden
+    /// slots).
+    pub words: Vec<&'a Word>,
+    /// The context before the hit.
+    pub before: Vec<&'a Word>,
+    /// The context after the hit.
+    pub after: Vec<&'a Word>,
+}
+
+/// Computed columns added to each row of the result files of a search
+/// while searching, e.g. a semantic class of the slot filler looked up in a
+/// user dictionary.
+pub struct HitAnnotator<'a> {
+    /// Names of the added columns, in this order after all other columns.
+    pub columns: Vec<String>,
+    /// Returns (column, value) pairs for a hit. Columns that are missing
+    /// are left empty, and pairs for other columns are ignored.
+    pub annotate: &'a (dyn Fn(&Hit) -> Vec<(String, String)> + Sync),
+}
+
+impl HitAnnotator<'_> {
+    /// The values of the columns for a hit.
+    pub(crate) fn values(&self, hit: &Hit) -> Vec<String> {
+        let mut values = vec![String::new(); self.columns.len()];
+        for (column, value) in (self.annotate)(hit) {
+            if let Some(i) = self.columns.iter().position(|c| *c == column) {
+                values[i] = value;
+            }
+        }
+        values
+    }
+}
@@ -0,0 +1,105 @@
+use crate::{Coha, Token};
+use anyhow::{bail, Result};
+use rustc_hash::FxHashMap;
+use std::ops::Deref;
+use std::sync::Arc;
+
+/// The values of the extra columns of a token, in the order of
+/// [`crate::LoadOptions::token_attributes`]; empty without extra columns.
+#[derive(Clone, Default)]
+pub(crate) struct AttributeValues(Option<Arc<[Box<str>]>>);
+
+impl Deref for AttributeValues {
+    type Target = [Box<str>];
+
+    fn deref(&self) -> &[Box<str>] {
+        self.0.as_deref().unwrap_or_default()
+    }
+}
+
+/// Parser of the extra columns of the lines of one corpus file. Tokens
+/// with the same values share them, so a layer with few distinct values
+/// takes little memory; each reader of a corpus file has its own parser,
+/// and the values are freed with the tokens.
+pub(crate) struct AttributeParser {
+    columns: usize,
+    interned: FxHashMap<Box<str>, AttributeValues>,
+}
+
+impl AttributeParser {
+    pub fn new(columns: usize) -> Self {
+        Self {
+            columns,
+            interned: FxHashMap::default(),
+        }
+    }
+
+    /// The values of the first `columns` tab-separated fields of `rest`,
+    /// the part of a corpus line after the word ID; `None` if there are
+    /// fewer fields. Further fields are ignored.
+    pub fn parse(&mut self, rest: Option<&str>) -> Option<AttributeValues> {
+        let columns = self.columns;
+        if columns == 0 {
+            return Some(AttributeValues::default());
+        }
+        let rest = rest?;
+        let values: Vec<&str> = rest.split('\t').take(columns).collect();
+        if values.len() < columns {
+            return None;
+        }
+        let key = &rest[..values.iter().map(|v| v.len()).sum::<usize>() + columns - 1];
+        if let Some(values) = self.interned.get(key) {
+            return Some(values.clone());
+        }
+        let values = AttributeValues(Some(values.into_iter().map(Box::from).collect()));
+        self.interned.insert(key.into(), values.clone());
+        Some(values)
+    }
+}
+
+/// A condition on one extra token attribute of a slot, see
+/// [`Coha::get_attribute_filter`].
+#[derive(Clone)]
+pub struct AttributeFilter {
+    name: String,
+    index: usize,
+    predicate: Arc<dyn Fn(&str) -> bool + Send + Sync>,
+}
+
+impl AttributeFilter {
+    /// The name of the attribute.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub(crate) fn matches(&self, token: &Token) -> bool {
+        token
+            .attributes
+            .get(self.index)
+            .is_some_and(|value| (self.predicate)(value))
+    }
+}
+
+impl Coha {
+    /// The names of the extra token attributes, see
+    /// [`crate::LoadOptions::token_attributes`].
+    pub fn token_attributes(&self) -> &[String] {
+        &self.load_options.token_attributes
+    }
+
+    /// Filter for tokens whose attribute `name` satisfies `p`, for
+    /// [`crate::CohaSearch::attribute_filters`].
+    pub fn get_attribute_filter<P>(&self, name: &str, p: P) -> Result<AttributeFilter>
+    where
+        P: Fn(&str) -> bool + Send + Sync + 'static,
+    {
+        let Some(index) = self.token_attributes().iter().position(|a| a == name) else {
+            bail!("unknown token attribute {name:?}");
+        };
+        Ok(AttributeFilter {
+            name: name.to_owned(),
+            index,
+            predicate: Arc::new(p),
+        })
+    }
+}
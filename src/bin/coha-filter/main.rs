@@ -0,0 +1,251 @@
+use anyhow::{bail, Result};
+use clap::{Parser, Subcommand};
+use clap_verbosity_flag::{InfoLevel, Verbosity};
+use coha_filter::{
+    write_word_count_mismatches, Coha, ExportFormat, Genre, Restrictions, SyntheticCorpus,
+};
+use log::info;
+use regex::Regex;
+use std::io::{self, Write};
+use std::path::PathBuf;
+
+mod repl;
+
+#[derive(Parser)]
+#[command(author, version, about)]
+struct Args {
+    /// Directory where the COHA corpus is located
+    corpus_dir: PathBuf,
+    #[command(subcommand)]
+    command: Command,
+    #[command(flatten)]
+    verbose: Verbosity<InfoLevel>,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Print the lexicon entries that match all given regular expressions
+    Lexicon {
+        /// Regular expression for the lowercase word form
+        #[arg(long)]
+        word_regex: Option<Regex>,
+        /// Regular expression for the case-sensitive word form
+        #[arg(long)]
+        word_cs_regex: Option<Regex>,
+        /// Regular expression for the lemma
+        #[arg(long)]
+        lemma_regex: Option<Regex>,
+        /// Regular expression for the POS tag; ambiguous tags match if any
+        /// alternative matches
+        #[arg(long)]
+        pos_regex: Option<Regex>,
+        /// Count the words in the corpus and print the most frequent first;
+        /// this reads all corpus files
+        #[arg(long)]
+        frequencies: bool,
+        /// Print at most this many entries
+        #[arg(long)]
+        limit: Option<usize>,
+    },
+    /// Print the sources table for the texts that match all given criteria
+    Sources {
+        /// Allowed genre, e.g. FIC; can be repeated
+        #[arg(long, value_parser = parse_genre)]
+        genre: Vec<Genre>,
+        /// Allowed publication years, e.g. 1850-1879, 1850-, or 1850
+        #[arg(long, value_parser = parse_years)]
+        years: Option<(Option<u16>, Option<u16>)>,
+        /// Regular expression for the author
+        #[arg(long)]
+        author_regex: Option<Regex>,
+        /// Print a JSON array instead of CSV
+        #[arg(long)]
+        json: bool,
+    },
+    /// Load the corpus once and run queries interactively
+    Repl,
+    /// Compare the number of tokens of each text in the corpus files with
+    /// the number of words in the sources table, and print the texts with
+    /// large differences
+    Verify {
+        /// Largest acceptable difference relative to the sources table
+        #[arg(long, default_value_t = 0.2)]
+        max_relative_difference: f64,
+    },
+    /// Write a small synthetic corpus in the COHA layout to the corpus
+    /// directory, for testing without the licensed corpus
+    Generate {
+        /// The same seed always gives the same corpus
+        #[arg(long, default_value_t = 1)]
+        seed: u64,
+        /// First decade
+        #[arg(long, default_value_t = 1810)]
+        first_decade: u16,
+        /// Number of decades
+        #[arg(long, default_value_t = 3)]
+        decades: u16,
+        /// Number of texts per decade
+        #[arg(long, default_value_t = 8)]
+        texts_per_decade: usize,
+    },
+}
+
+pub(crate) fn parse_genre(s: &str) -> Result<Genre> {
+    // the COHA genres may be given in any case
+    let upper = s.to_uppercase();
+    if let Some(genre) = Genre::WELL_KNOWN.iter().find(|g| g.as_str() == upper) {
+        return Ok(genre.clone());
+    }
+    match Genre::parse(s) {
+        Some(genre) => Ok(genre),
+        None => bail!("invalid genre {s:?}"),
+    }
+}
+
+fn parse_year(s: &str) -> Result<Option<u16>> {
+    if s.is_empty() {
+        Ok(None)
+    } else {
+        Ok(Some(s.parse()?))
+    }
+}
+
+pub(crate) fn parse_years(s: &str) -> Result<(Option<u16>, Option<u16>)> {
+    match s.split_once('-') {
+        Some((from, to)) => Ok((parse_year(from)?, parse_year(to)?)),
+        None => Ok((parse_year(s)?, parse_year(s)?)),
+    }
+}
+
+fn is_match(re: &Option<Regex>, s: &str) -> bool {
+    re.as_ref().is_none_or(|re| re.is_match(s))
+}
+
+fn lexicon(
+    coha: &mut Coha,
+    regexes: [&Option<Regex>; 4],
+    frequencies: bool,
+    limit: Option<usize>,
+) -> Result<()> {
+    let [word, word_cs, lemma, pos] = regexes;
+    if frequencies {
+        coha.compute_frequencies()?;
+    }
+    let filter = coha.get_filter(|w| {
+        is_match(word, &w.word)
+            && is_match(word_cs, &w.word_cs)
+            && is_match(lemma, &w.lemma)
+            && pos
+                .as_ref()
+                .is_none_or(|re| coha.pos_matches(w, |p| re.is_match(p)))
+    });
+    let words = coha.explain_filter(&filter, limit.unwrap_or(usize::MAX));
+    let mut out = io::stdout().lock();
+    let mut header = "word ID\twordCS\tword\tlemma\tPoS".to_owned();
+    if frequencies {
+        header.push_str("\tfrequency");
+    }
+    writeln!(out, "{header}")?;
+    for w in &words {
+        write!(
+            out,
+            "{}\t{}\t{}\t{}\t{}",
+            w.word_id, w.word_cs, w.word, w.lemma, w.pos
+        )?;
+        if let Some(f) = coha.frequency(w.word_id) {
+            write!(out, "\t{f}")?;
+        }
+        writeln!(out)?;
+    }
+    info!("{} lexicon entries", words.len());
+    Ok(())
+}
+
+fn sources(
+    coha: &Coha,
+    genres: &[Genre],
+    years: Option<(Option<u16>, Option<u16>)>,
+    author: Option<&Regex>,
+    json: bool,
+) -> Result<()> {
+    let (year_from, year_to) = years.unwrap_or_default();
+    let restrictions = Restrictions {
+        genres: (!genres.is_empty()).then(|| genres.to_vec()),
+        year_from,
+        year_to,
+        ..Default::default()
+    };
+    let text_ids = coha.select_sources(&restrictions, author);
+    let format = if json {
+        ExportFormat::Json
+    } else {
+        ExportFormat::Csv
+    };
+    let mut out = io::stdout().lock();
+    coha.write_sources(&mut out, format, &text_ids)?;
+    if json {
+        writeln!(out)?;
+    }
+    Ok(())
+}
+
+fn run(args: &Args) -> Result<()> {
+    if let Command::Generate {
+        seed,
+        first_decade,
+        decades,
+        texts_per_decade,
+    } = args.command
+    {
+        let corpus = SyntheticCorpus {
+            seed,
+            first_decade,
+            decades,
+            texts_per_decade,
+            ..Default::default()
+        };
+        return corpus.write(&args.corpus_dir);
+    }
+    let mut coha = Coha::load(&args.corpus_dir)?;
+    match &args.command {
+        Command::Lexicon {
+            word_regex,
+            word_cs_regex,
+            lemma_regex,
+            pos_regex,
+            frequencies,
+            limit,
+        } => lexicon(
+            &mut coha,
+            [word_regex, word_cs_regex, lemma_regex, pos_regex],
+            *frequencies,
+            *limit,
+        ),
+        Command::Sources {
+            genre,
+            years,
+            author_regex,
+            json,
+        } => sources(&coha, genre, *years, author_regex.as_ref(), *json),
+        Command::Repl => {
+            let scratch_dir =
+                std::env::temp_dir().join(format!("coha-filter-repl-{}", std::process::id()));
+            repl::repl(&coha, &scratch_dir)
+        }
+        Command::Verify {
+            max_relative_difference,
+        } => {
+            let mismatches = coha.verify_word_counts(*max_relative_difference)?;
+            write_word_count_mismatches(io::stdout().lock(), &mismatches)
+        }
+        Command::Generate { .. } => unreachable!("handled above"),
+    }
+}
+
+fn main() -> Result<()> {
+    let args = Args::parse();
+    env_logger::Builder::new()
+        .filter_level(args.verbose.log_level_filter())
+        .init();
+    run(&args)
+}
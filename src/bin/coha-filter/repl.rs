@@ -0,0 +1,171 @@
+use crate::{parse_genre, parse_years};
+use anyhow::{bail, Result};
+use coha_filter::{Coha, Restrictions, SearchOptions, SearchPlan, SearchReport, SearchSpec};
+use std::fs;
+use std::io::{self, BufRead, Write};
+use std::path::Path;
+
+const LABEL: &str = "query";
+const CONTEXT_CHARS: usize = 40;
+
+const HELP: &str = "\
+A query has one term per word, e.g. `pos:vb* going to pos:/^v.i/`:
+  *             any word
+  go            the word \"go\" (case-insensitive)
+  cs:God        the case-sensitive form \"God\"
+  lemma:go      any form of \"go\"
+  pos:vvi       POS tag vvi
+  pos:vv*       POS tags starting with vv
+  pos:/^v.i/    POS tags matching a regular expression
+  !pos:at       any word except those tagged at
+Commands:
+  :sample N     print at most N concordance lines (default 10)
+  :genre G...   only search these genres, e.g. :genre FIC MAG; all if empty
+  :years A-B    only search these years, e.g. :years 1850-1879; all if empty
+  :help         print this help
+  :quit         exit";
+
+struct Repl<'a> {
+    coha: &'a Coha,
+    scratch_dir: &'a Path,
+    sample: usize,
+    restrictions: Restrictions,
+}
+
+/// The first or last `n` characters of `s`.
+fn clip(s: &str, n: usize, from_end: bool) -> String {
+    let len = s.chars().count();
+    if len <= n {
+        s.to_owned()
+    } else if from_end {
+        s.chars().skip(len - n).collect()
+    } else {
+        s.chars().take(n).collect()
+    }
+}
+
+impl Repl<'_> {
+    fn command(&mut self, command: &str, args: &[&str]) -> Result<bool> {
+        match command {
+            ":quit" | ":q" => return Ok(false),
+            ":help" => println!("{HELP}"),
+            ":sample" => match args {
+                [n] => self.sample = n.parse()?,
+                _ => bail!("usage: :sample N"),
+            },
+            ":genre" => {
+                let genres = args
+                    .iter()
+                    .map(|g| parse_genre(g))
+                    .collect::<Result<Vec<_>>>()?;
+                self.restrictions.genres = (!genres.is_empty()).then_some(genres);
+            }
+            ":years" => {
+                let (from, to) = match args {
+                    [] => (None, None),
+                    [years] => parse_years(years)?,
+                    _ => bail!("usage: :years A-B"),
+                };
+                self.restrictions.year_from = from;
+                self.restrictions.year_to = to;
+            }
+            _ => bail!("unknown command {command}, try :help"),
+        }
+        Ok(true)
+    }
+
+    fn query(&self, query: &str) -> Result<()> {
+        let mut spec = SearchSpec::parse_query(LABEL, query)?;
+        spec.restrictions = self.restrictions.clone();
+        let plan = SearchPlan {
+            searches: vec![spec],
+            options: SearchOptions {
+                merge_decades: true,
+                tidy_text: true,
+                ..Default::default()
+            },
+        };
+        let report = plan.run(self.coha, self.scratch_dir)?;
+        self.print_counts(&report);
+        let hits = report.searches[0].hits;
+        if hits > 0 && self.sample > 0 {
+            self.print_sample(hits)?;
+        }
+        Ok(())
+    }
+
+    fn print_counts(&self, report: &SearchReport) {
+        let counts = &report.searches[0];
+        println!("{} hits in {} texts", counts.hits, counts.by_text.len());
+        let by_decade: Vec<String> = counts
+            .by_decade
+            .iter()
+            .map(|(decade, hits)| format!("{decade}: {hits}"))
+            .collect();
+        if !by_decade.is_empty() {
+            println!("{}", by_decade.join(", "));
+        }
+    }
+
+    /// Print concordance lines evenly spaced over the hits.
+    fn print_sample(&self, hits: usize) -> Result<()> {
+        let path = self.scratch_dir.join(LABEL).join(format!("{LABEL}.csv"));
+        let mut reader = csv::Reader::from_path(path)?;
+        let header = reader.headers()?.clone();
+        let column = |name: &str| header.iter().position(|h| h == name);
+        let (Some(before), Some(after)) = (column("before"), column("after")) else {
+            bail!("unexpected result file header");
+        };
+        let step = hits.div_ceil(self.sample);
+        for record in reader.records().step_by(step) {
+            let record = record?;
+            let matched: Vec<&str> = record.iter().take(after).skip(before + 1).collect();
+            println!(
+                "{:>width$}  [{}]  {}",
+                clip(&record[before], CONTEXT_CHARS, true),
+                matched.join(" "),
+                clip(&record[after], CONTEXT_CHARS, false),
+                width = CONTEXT_CHARS,
+            );
+        }
+        Ok(())
+    }
+}
+
+/// Read queries and commands from standard input until `:quit` or end of
+/// input. Each query is a full corpus scan whose results are written to
+/// `scratch_dir`; the lexicon indexes are built once and reused.
+pub(crate) fn repl(coha: &Coha, scratch_dir: &Path) -> Result<()> {
+    let mut repl = Repl {
+        coha,
+        scratch_dir,
+        sample: 10,
+        restrictions: Restrictions::default(),
+    };
+    println!("type a query, or :help");
+    let mut lines = io::stdin().lock().lines();
+    loop {
+        print!("coha> ");
+        io::stdout().flush()?;
+        let Some(line) = lines.next() else {
+            println!();
+            break;
+        };
+        let line = line?;
+        let words: Vec<&str> = line.split_whitespace().collect();
+        let result = match words.as_slice() {
+            [] => Ok(true),
+            [command, args @ ..] if command.starts_with(':') => repl.command(command, args),
+            _ => repl.query(&line).map(|()| true),
+        };
+        match result {
+            Ok(true) => (),
+            Ok(false) => break,
+            Err(e) => eprintln!("error: {e:#}"),
+        }
+    }
+    if scratch_dir.exists() {
+        fs::remove_dir_all(scratch_dir)?;
+    }
+    Ok(())
+}
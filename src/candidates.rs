@@ -0,0 +1,238 @@
+use crate::attributes::AttributeParser;
+use crate::lines::LineReader;
+use crate::manifest::Fnv1a;
+use crate::text_files::CorpusLines;
+use crate::text_index::TextRange;
+use crate::{tsv_err, CohaFile, CohaFilter, Run, SearchOutput, TextId, Token, WordId};
+use anyhow::{bail, Result};
+use log::{debug, info};
+use rustc_hash::FxHashSet;
+use std::fs::File;
+use std::io::{BufReader, Read, Seek, SeekFrom};
+
+/// Removed tokens (`@`) in a text, and the number of runs of consecutive
+/// removed tokens.
+#[derive(Copy, Debug, Clone, Default)]
+pub(crate) struct RemovedCounts {
+    pub removed: usize,
+    pub gaps: usize,
+    prev_removed: bool,
+}
+
+impl RemovedCounts {
+    pub(crate) fn add(&mut self, is_removed: bool) {
+        if is_removed {
+            self.removed += 1;
+            if !self.prev_removed {
+                self.gaps += 1;
+            }
+        }
+        self.prev_removed = is_removed;
+    }
+}
+
+/// A text found in the first pass of [`SearchOptions::two_pass`].
+///
+/// [`SearchOptions::two_pass`]: crate::SearchOptions::two_pass
+pub(crate) struct TextSummary {
+    pub range: TextRange,
+    pub tokens: usize,
+    pub out_of_order: usize,
+    pub removed: RemovedCounts,
+    /// May the text have hits in some search? Texts that are not candidates
+    /// are counted but not read again.
+    pub candidate: bool,
+}
+
+/// The result of the first pass over a corpus file.
+pub(crate) struct FirstPass {
+    pub texts: Vec<TextSummary>,
+    pub tokens: usize,
+    pub malformed_lines: usize,
+    pub bytes: u64,
+    pub hash: Fnv1a,
+}
+
+/// For each search, words of which a text has to contain at least one to
+/// have hits: the words of its most selective slot. `None` if any text may
+/// have hits, e.g. in regular expression searches.
+fn required_words<'a>(run: &Run<'a>) -> Vec<Option<&'a FxHashSet<WordId>>> {
+    run.searches
+        .iter()
+        .map(|search| {
+            if !search.has_slots() {
+                return None;
+            }
+            search
+                .filter_list
+                .iter()
+                .filter_map(|f| match f {
+                    CohaFilter::Hash(ids) => Some(ids),
+                    _ => None,
+                })
+                .min_by_key(|ids| ids.len())
+        })
+        .collect()
+}
+
+impl CohaFile {
+    /// First pass of [`SearchOptions::two_pass`]: find the byte range of
+    /// each text and whether it contains the words required by some search,
+    /// parsing only the token lines.
+    ///
+    /// [`SearchOptions::two_pass`]: crate::SearchOptions::two_pass
+    pub(crate) fn find_candidates(&self, run: &Run, br: &mut CorpusLines) -> Result<FirstPass> {
+        let coha = run.coha;
+        let path = &self.corpus_path;
+        let required = required_words(run);
+        // may the text have hits in each search
+        let candidate_for = |text_id: TextId| -> Vec<Option<bool>> {
+            match coha.sources.get(&text_id) {
+                // the second pass reports the unknown text
+                None => vec![Some(true)],
+                Some(source) => run
+                    .searches
+                    .iter()
+                    .zip(&required)
+                    .map(|(search, words)| {
+                        if !search.restrictions.allows(source) {
+                            Some(false)
+                        } else {
+                            words.is_none().then_some(true)
+                        }
+                    })
+                    .collect(),
+            }
+        };
+        let mut pass = FirstPass {
+            texts: Vec::new(),
+            tokens: 0,
+            malformed_lines: 0,
+            bytes: 0,
+            hash: Fnv1a::default(),
+        };
+        let mut undecided: Vec<Option<bool>> = Vec::new();
+        let mut prev_token_id = None;
+        let mut s = String::new();
+        let mut attributes = self.attribute_parser();
+        loop {
+            let n = br.read_line(&mut s)?;
+            if n == 0 {
+                break;
+            }
+            pass.hash.write(s.as_bytes());
+            let offset = pass.bytes;
+            pass.bytes += n as u64;
+            let token = match self.parse_token(&s, &mut attributes) {
+                Ok(token) => token,
+                Err(e) if coha.load_options.lenient => {
+                    debug!("{}: skipping malformed line: {}", path.to_string_lossy(), e);
+                    pass.malformed_lines += 1;
+                    s.clear();
+                    continue;
+                }
+                Err(e) => return Err(e),
+            };
+            s.clear();
+            pass.tokens += 1;
+            let text = match pass.texts.last_mut() {
+                Some(text) if text.range.text_id == token.text_id => text,
+                _ => {
+                    undecided = candidate_for(token.text_id);
+                    prev_token_id = None;
+                    pass.texts.push(TextSummary {
+                        range: TextRange {
+                            text_id: token.text_id,
+                            start: offset,
+                            end: offset,
+                        },
+                        tokens: 0,
+                        out_of_order: 0,
+                        removed: RemovedCounts::default(),
+                        candidate: false,
+                    });
+                    pass.texts.last_mut().unwrap()
+                }
+            };
+            text.range.end = pass.bytes;
+            text.tokens += 1;
+            text.removed.add(coha.is_removed(&token));
+            if prev_token_id.is_some_and(|prev| prev >= token.token_id) {
+                if !coha.load_options.sort_tokens {
+                    bail!(tsv_err(path, "token IDs not increasing"));
+                }
+                text.out_of_order += 1;
+            }
+            prev_token_id = Some(token.token_id);
+            if !text.candidate {
+                for (c, words) in undecided.iter_mut().zip(&required) {
+                    if c.is_none() && words.is_some_and(|w| w.contains(&token.word_id)) {
+                        *c = Some(true);
+                    }
+                }
+                text.candidate = undecided.contains(&Some(true));
+            }
+        }
+        for text in &mut pass.texts {
+            // removed material is counted in the sorted order
+            text.candidate |= text.out_of_order > 0;
+        }
+        info!(
+            "{}: {} of {} texts are candidates",
+            path.to_string_lossy(),
+            pass.texts.iter().filter(|t| t.candidate).count(),
+            pass.texts.len()
+        );
+        Ok(pass)
+    }
+
+    /// Second pass of [`SearchOptions::two_pass`]: read the tokens of one
+    /// text. Malformed lines were already reported in the first pass.
+    ///
+    /// [`SearchOptions::two_pass`]: crate::SearchOptions::two_pass
+    pub(crate) fn read_range(
+        &self,
+        file: &mut File,
+        range: TextRange,
+        attributes: &mut AttributeParser,
+        tokens: &mut Vec<Token>,
+    ) -> Result<()> {
+        file.seek(SeekFrom::Start(range.start))?;
+        let reader = BufReader::new(file.take(range.end - range.start));
+        let mut br = LineReader::new(reader);
+        let mut s = String::new();
+        loop {
+            let n = br.read_line(&mut s)?;
+            if n == 0 {
+                break;
+            }
+            if let Ok(token) = self.parse_token(&s, attributes) {
+                tokens.push(token);
+            }
+            s.clear();
+        }
+        Ok(())
+    }
+
+    /// Count a text that cannot have hits as searched without hits.
+    pub(crate) fn skip_text(&self, run: &Run, outputs: &mut [SearchOutput], text: &TextSummary) {
+        let coha = run.coha;
+        let Some(source) = coha.sources.get(&text.range.text_id) else {
+            return;
+        };
+        for (output, search) in outputs.iter_mut().zip(run.searches) {
+            if !search.restrictions.allows(source) {
+                continue;
+            }
+            let metadata = coha.metadata.values(source.text_id);
+            output.counts.add(
+                &self.identifier,
+                source,
+                coha.metadata.columns(),
+                &metadata,
+                0,
+            );
+            output.stats.add(text.tokens, 0);
+        }
+    }
+}
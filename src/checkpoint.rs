@@ -0,0 +1,265 @@
+use crate::manifest::Fnv1a;
+use crate::output::{SearchOutput, WrittenFile};
+use crate::report::Warnings;
+use crate::{FileChecksum, FileStats, SearchCounts, Warning};
+use anyhow::Result;
+use log::{debug, info};
+use rustc_hash::FxHashMap;
+use serde::{Deserialize, Serialize};
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, BufWriter, ErrorKind, Seek, SeekFrom, Write};
+use std::path::Path;
+use std::sync::Mutex;
+
+/// A corpus file whose results are complete.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct CompletedFile {
+    run_key: String,
+    identifier: String,
+    checksum: FileChecksum,
+    counts: Vec<SearchCounts>,
+    stats: Vec<FileStats>,
+    warnings: Vec<Warning>,
+}
+
+impl CompletedFile {
+    /// The outputs of the earlier run, without writers.
+    pub fn restore(&self) -> (Vec<SearchOutput>, Warnings, FileChecksum) {
+        let outputs = self
+            .counts
+            .iter()
+            .zip(&self.stats)
+            .map(|(counts, stats)| SearchOutput {
+                files: None,
+                pending: Vec::new(),
+                counts: counts.clone(),
+                stats: stats.clone(),
+            })
+            .collect();
+        let mut warnings = Warnings::default();
+        warnings.add_all(&self.warnings);
+        (outputs, warnings, self.checksum.clone())
+    }
+}
+
+/// How far a corpus file had been read when its progress was recorded: the
+/// texts before byte `offset` are complete.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+pub(crate) struct FilePosition {
+    pub offset: u64,
+    /// Hash of the bytes before `offset`, for the checksum of the file.
+    pub hash: Fnv1a,
+    pub tokens: usize,
+    pub texts: usize,
+    pub hits: usize,
+    pub hit_texts: usize,
+    pub malformed_lines: usize,
+    pub reordered: usize,
+}
+
+/// A corpus file that has been searched up to some text.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct FileProgress {
+    run_key: String,
+    identifier: String,
+    pub position: FilePosition,
+    counts: Vec<SearchCounts>,
+    stats: Vec<FileStats>,
+    warnings: Vec<Warning>,
+    /// The result files of each search.
+    written: Vec<Vec<WrittenFile>>,
+    /// Size of the report of removed tokens, if any.
+    pub removed: Option<u64>,
+}
+
+impl FileProgress {
+    /// Continue the outputs of the earlier run; false if the result files
+    /// cannot be resumed.
+    pub fn restore(&self, outputs: &mut [SearchOutput], warnings: &mut Warnings) -> Result<bool> {
+        for (i, output) in outputs.iter_mut().enumerate() {
+            if let Some(files) = &mut output.files {
+                if !files.resume(&self.written[i])? {
+                    return Ok(false);
+                }
+            }
+            output.counts = self.counts[i].clone();
+            output.stats = self.stats[i].clone();
+        }
+        warnings.add_all(&self.warnings);
+        Ok(true)
+    }
+}
+
+/// The file at `path` truncated to `len` bytes and opened for appending;
+/// `None` if it is missing or shorter.
+pub(crate) fn reopen(path: &Path, len: u64) -> Result<Option<File>> {
+    let mut file = match OpenOptions::new().write(true).open(path) {
+        Ok(file) => file,
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e.into()),
+    };
+    if file.metadata()?.len() < len {
+        return Ok(None);
+    }
+    file.set_len(len)?;
+    file.seek(SeekFrom::End(0))?;
+    Ok(Some(file))
+}
+
+/// Progress of a search run, recorded as JSON lines. A corpus file is
+/// listed as complete only after all of its result files have been
+/// completed, so a run that is interrupted can be resumed by searching the
+/// corpus files that are not listed. Within a corpus file, the progress is
+/// recorded every few texts, after the result files have been made
+/// durable, and the search of the file continues after the last recorded
+/// text.
+pub(crate) struct Checkpoint {
+    run_key: String,
+    completed: FxHashMap<String, CompletedFile>,
+    progress: FxHashMap<String, FileProgress>,
+    writer: Mutex<File>,
+}
+
+impl Checkpoint {
+    /// Open the checkpoint file, keeping the entries of earlier runs with
+    /// the same run key and discarding everything else.
+    pub fn open(path: &Path, run_key: &str) -> Result<Self> {
+        debug!("{}: reading...", path.to_string_lossy());
+        let mut completed = FxHashMap::default();
+        let mut progress = FxHashMap::default();
+        match File::open(path) {
+            Ok(file) => {
+                for line in BufReader::new(file).lines() {
+                    // the last line may be incomplete after a crash
+                    let line = line?;
+                    if let Ok(entry) = serde_json::from_str::<CompletedFile>(&line) {
+                        if entry.run_key == run_key {
+                            progress.remove(&entry.identifier);
+                            completed.insert(entry.identifier.clone(), entry);
+                        }
+                    } else if let Ok(entry) = serde_json::from_str::<FileProgress>(&line) {
+                        if entry.run_key == run_key {
+                            progress.insert(entry.identifier.clone(), entry);
+                        }
+                    }
+                }
+            }
+            Err(e) if e.kind() == ErrorKind::NotFound => (),
+            Err(e) => return Err(e.into()),
+        }
+        if !completed.is_empty() || !progress.is_empty() {
+            info!(
+                "{}: resuming, {} corpus files already complete, {} partly searched",
+                path.to_string_lossy(),
+                completed.len(),
+                progress.len()
+            );
+        }
+        let mut writer = BufWriter::new(File::create(path)?);
+        for entry in completed.values() {
+            serde_json::to_writer(&mut writer, entry)?;
+            writer.write_all(b"\n")?;
+        }
+        for entry in progress.values() {
+            serde_json::to_writer(&mut writer, entry)?;
+            writer.write_all(b"\n")?;
+        }
+        writer.flush()?;
+        drop(writer);
+        let file = OpenOptions::new().append(true).open(path)?;
+        Ok(Self {
+            run_key: run_key.to_owned(),
+            completed,
+            progress,
+            writer: Mutex::new(file),
+        })
+    }
+
+    pub fn completed(&self, identifier: &str) -> Option<&CompletedFile> {
+        self.completed.get(identifier)
+    }
+
+    pub fn progress(&self, identifier: &str) -> Option<&FileProgress> {
+        self.progress.get(identifier)
+    }
+
+    /// Make the result files of a corpus file durable and record that it
+    /// has been searched up to `position`, unless a hit limit cut hits.
+    /// `removed` is the report of removed tokens, if any.
+    pub fn record_progress(
+        &self,
+        identifier: &str,
+        position: FilePosition,
+        outputs: &mut [SearchOutput],
+        warnings: &Warnings,
+        removed: Option<&mut csv::Writer<File>>,
+    ) -> Result<()> {
+        if outputs.iter().any(|o| o.stats.truncated) {
+            return Ok(());
+        }
+        let mut written = Vec::new();
+        for output in outputs.iter_mut() {
+            written.push(match &mut output.files {
+                Some(files) => files.progress()?,
+                None => Vec::new(),
+            });
+        }
+        let removed = match removed {
+            Some(writer) => {
+                writer.flush()?;
+                writer.get_ref().sync_data()?;
+                Some(writer.get_ref().metadata()?.len())
+            }
+            None => None,
+        };
+        let entry = FileProgress {
+            run_key: self.run_key.clone(),
+            identifier: identifier.to_owned(),
+            position,
+            counts: outputs.iter().map(|o| o.counts.clone()).collect(),
+            stats: outputs.iter().map(|o| o.stats.clone()).collect(),
+            warnings: warnings.clone().into_vec(),
+            written,
+            removed,
+        };
+        self.write(&entry)
+    }
+
+    fn write(&self, entry: &impl Serialize) -> Result<()> {
+        let mut line = serde_json::to_string(entry)?;
+        line.push('\n');
+        let mut file = self.writer.lock().expect("checkpoint lock");
+        file.write_all(line.as_bytes())?;
+        file.sync_data()?;
+        Ok(())
+    }
+
+    /// Flush the result files of a corpus file and mark it complete, unless
+    /// a limit was reached.
+    pub fn record(
+        &self,
+        identifier: &str,
+        outputs: &mut [SearchOutput],
+        warnings: &Warnings,
+        checksum: &FileChecksum,
+    ) -> Result<()> {
+        for output in outputs.iter_mut() {
+            output.finish()?;
+        }
+        if outputs
+            .iter()
+            .any(|o| o.stats.truncated || o.stats.stopped_early)
+        {
+            return Ok(());
+        }
+        let entry = CompletedFile {
+            run_key: self.run_key.clone(),
+            identifier: identifier.to_owned(),
+            checksum: checksum.clone(),
+            counts: outputs.iter().map(|o| o.counts.clone()).collect(),
+            stats: outputs.iter().map(|o| o.stats.clone()).collect(),
+            warnings: warnings.clone().into_vec(),
+        };
+        self.write(&entry)
+    }
+}
@@ -0,0 +1,471 @@
+use crate::stats::ll_term;
+use crate::{Coha, CohaFile, CohaSearch, SlotSpec, Token, WordId};
+use anyhow::{bail, Result};
+use log::info;
+use rayon::prelude::*;
+use rustc_hash::{FxHashMap, FxHashSet};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::io::Write;
+
+/// Options of [`Coha::collocates`].
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CollocationOptions {
+    /// Number of tokens before a hit that are counted as collocates, e.g. 0
+    /// for no left collocates.
+    pub left: usize,
+    /// Number of tokens after a hit that are counted as collocates.
+    pub right: usize,
+    /// Only count the words that match this term as collocates, e.g.
+    /// `pos:nn*` for nouns (see [`SlotSpec::parse_term`]).
+    pub collocates: Option<SlotSpec>,
+    /// Do not count the words that match this term as collocates, e.g.
+    /// `pos:/^(y|at|io|cc)/` for punctuation and some function words. They
+    /// still take up positions in the span.
+    pub exclude: Option<SlotSpec>,
+    /// Leave out collocates that occur fewer times than this next to the
+    /// hits.
+    pub min_count: u64,
+}
+
+impl Default for CollocationOptions {
+    fn default() -> Self {
+        Self {
+            left: 4,
+            right: 4,
+            collocates: None,
+            exclude: None,
+            min_count: 3,
+        }
+    }
+}
+
+/// Side of the hit on which a collocate occurs.
+#[derive(Copy, Debug, Clone, Eq, PartialEq, Ord, PartialOrd, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Side {
+    Left,
+    Right,
+}
+
+impl fmt::Display for Side {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Side::Left => write!(f, "left"),
+            Side::Right => write!(f, "right"),
+        }
+    }
+}
+
+/// A word (lower-case form) that occurs within the span on one side of the
+/// hits of a search, with its association scores. The scores compare the
+/// tokens within the span on that side with the rest of the corpus; only
+/// the words that may be collocates are counted.
+#[derive(Debug, Clone, Serialize)]
+pub struct Collocate {
+    pub word: String,
+    pub side: Side,
+    /// Occurrences within the span.
+    pub observed: u64,
+    /// Occurrences within the span expected by chance.
+    pub expected: f64,
+    /// Occurrences in the entire corpus.
+    pub frequency: u64,
+    /// Pointwise mutual information (log2 of observed / expected).
+    pub mi: f64,
+    /// Log-likelihood (G²).
+    pub log_likelihood: f64,
+    /// ΔP of the collocate given the node: P(collocate | span) −
+    /// P(collocate | elsewhere).
+    pub delta_p_collocate: f64,
+    /// ΔP of the node given the collocate: P(span | collocate) −
+    /// P(span | other words).
+    pub delta_p_node: f64,
+}
+
+impl Collocate {
+    /// The scores of a word that occurs `observed` times among the
+    /// `span_tokens` tokens within the span and `frequency` times among
+    /// all `n` tokens of the corpus.
+    fn new(
+        word: String,
+        side: Side,
+        observed: u64,
+        frequency: u64,
+        span_tokens: f64,
+        n: f64,
+    ) -> Self {
+        // 2×2 contingency table: o11 = collocate within the span,
+        // o12 = other words within the span, o21 = collocate
+        // elsewhere, o22 = other words elsewhere
+        let o11 = observed as f64;
+        let o12 = span_tokens - o11;
+        let o21 = (frequency as f64 - o11).max(0.0);
+        let o22 = (n - span_tokens - o21).max(0.0);
+        let r1 = o11 + o12;
+        let r2 = o21 + o22;
+        let c1 = o11 + o21;
+        let c2 = o12 + o22;
+        let expected = r1 * c1 / n;
+        let log_likelihood = 2.0
+            * (ll_term(o11, expected)
+                + ll_term(o12, r1 * c2 / n)
+                + ll_term(o21, r2 * c1 / n)
+                + ll_term(o22, r2 * c2 / n));
+        let ratio = |a: f64, b: f64| if b > 0.0 { a / b } else { 0.0 };
+        Self {
+            word,
+            side,
+            observed,
+            expected,
+            frequency,
+            mi: (o11 / expected).log2(),
+            log_likelihood,
+            delta_p_collocate: ratio(o11, r1) - ratio(o21, r2),
+            delta_p_node: ratio(o11, c1) - ratio(o12, c2),
+        }
+    }
+}
+
+/// Co-occurrence counts of one side.
+#[derive(Default)]
+struct SideCounts {
+    /// Tokens within the span that may be collocates.
+    tokens: u64,
+    words: FxHashMap<String, u64>,
+}
+
+impl SideCounts {
+    fn add<'a>(
+        &mut self,
+        coha: &Coha,
+        tokens: impl Iterator<Item = &'a Token>,
+        eligible: impl Fn(WordId) -> bool,
+    ) {
+        for t in tokens.filter(|t| eligible(t.word_id)) {
+            self.tokens += 1;
+            let word = &coha.get_word(t.word_id).word;
+            match self.words.get_mut(word) {
+                Some(n) => *n += 1,
+                None => {
+                    self.words.insert(word.clone(), 1);
+                }
+            }
+        }
+    }
+
+    fn merge(&mut self, other: SideCounts) {
+        self.tokens += other.tokens;
+        for (word, n) in other.words {
+            *self.words.entry(word).or_default() += n;
+        }
+    }
+}
+
+/// Collocates of a search in one decade, see [`Coha::collocates_by_decade`].
+#[derive(Debug, Clone, Serialize)]
+pub struct DecadeCollocate {
+    /// Decade, e.g. "1850s".
+    pub decade: String,
+    /// Rank of the collocate on its side in this decade by log-likelihood,
+    /// starting from 1; `None` if it occurs fewer than
+    /// [`CollocationOptions::min_count`] times in this decade.
+    pub rank: Option<usize>,
+    #[serde(flatten)]
+    pub collocate: Collocate,
+}
+
+/// Association scores of the words within the spans on each side,
+/// relative to `frequencies` (tokens per word ID) of the reference corpus.
+/// Collocates that occur fewer than `min_count` times are left out.
+fn score(
+    coha: &Coha,
+    sides: [(Side, SideCounts); 2],
+    frequencies: &[u64],
+    eligible: impl Fn(WordId) -> bool,
+    min_count: u64,
+) -> Vec<Collocate> {
+    let mut word_frequencies: FxHashMap<&str, u64> = FxHashMap::default();
+    let mut n = 0;
+    for word in coha.lexicon.iter().flatten() {
+        if eligible(word.word_id) {
+            let f = frequencies.get(word.word_id.0).copied().unwrap_or(0);
+            *word_frequencies.entry(&word.word).or_default() += f;
+            n += f;
+        }
+    }
+    let n = n as f64;
+    let mut collocates = Vec::new();
+    for (side, counts) in sides {
+        let span_tokens = counts.tokens as f64;
+        for (word, observed) in counts.words {
+            if observed < min_count {
+                continue;
+            }
+            let frequency = word_frequencies.get(word.as_str()).copied().unwrap_or(0);
+            collocates.push(Collocate::new(
+                word,
+                side,
+                observed,
+                frequency,
+                span_tokens,
+                n,
+            ));
+        }
+    }
+    collocates.sort_by(|a, b| {
+        a.side
+            .cmp(&b.side)
+            .then(b.log_likelihood.total_cmp(&a.log_likelihood))
+            .then_with(|| a.word.cmp(&b.word))
+    });
+    collocates
+}
+
+impl Coha {
+    /// Which words may be collocates: not removed material or structural
+    /// markers, and only the words that the options allow.
+    fn collocate_filter(
+        &self,
+        options: &CollocationOptions,
+    ) -> Result<impl Fn(WordId) -> bool + Sync + '_> {
+        let compile = |spec: &Option<SlotSpec>| spec.as_ref().map(|s| s.compile(self)).transpose();
+        let keep = compile(&options.collocates)?;
+        let exclude = compile(&options.exclude)?;
+        Ok(move |word_id: WordId| {
+            !self.removed.contains(&word_id)
+                && !self.markers.contains(&word_id)
+                && keep.as_ref().is_none_or(|f| f.matches(word_id))
+                && !exclude.as_ref().is_some_and(|f| f.matches(word_id))
+        })
+    }
+
+    /// The words within the spans around the hits of `search` in one corpus
+    /// file, and with `frequencies`, the tokens of each word in the file.
+    fn count_collocates(
+        &self,
+        cf: &CohaFile,
+        search: &CohaSearch,
+        options: &CollocationOptions,
+        eligible: impl Fn(WordId) -> bool,
+        mut frequencies: Option<&mut Vec<u64>>,
+    ) -> Result<[(Side, SideCounts); 2]> {
+        let mut left = SideCounts::default();
+        let mut right = SideCounts::default();
+        cf.scan_texts(self.load_options.lenient, |text_id, tokens| {
+            if let Some(frequencies) = &mut frequencies {
+                for token in tokens {
+                    if let Some(f) = frequencies.get_mut(token.word_id.0) {
+                        *f += 1;
+                    }
+                }
+            }
+            let allowed = self
+                .sources
+                .get(&text_id)
+                .is_some_and(|s| search.restrictions.allows(s));
+            if !allowed {
+                return Ok(());
+            }
+            for span in self.find_spans(search, tokens) {
+                let end = span.start + span.len;
+                let start = span.start.saturating_sub(options.left);
+                left.add(self, tokens[start..span.start].iter(), &eligible);
+                right.add(self, tokens[end..].iter().take(options.right), &eligible);
+            }
+            Ok(())
+        })?;
+        Ok([(Side::Left, left), (Side::Right, right)])
+    }
+
+    /// The collocates of the hits of `search`, separately on the left and
+    /// on the right, sorted by log-likelihood. Requires
+    /// [`Coha::compute_frequencies`]; this reads all corpus files.
+    pub fn collocates(
+        &self,
+        search: &CohaSearch,
+        options: &CollocationOptions,
+    ) -> Result<Vec<Collocate>> {
+        let Some(frequencies) = &self.frequencies else {
+            bail!("collocates: word frequencies have not been computed");
+        };
+        let eligible = self.collocate_filter(options)?;
+        let per_file = self
+            .coha_files
+            .par_iter()
+            .map(|cf| self.count_collocates(cf, search, options, &eligible, None))
+            .collect::<Result<Vec<_>>>()?;
+        let mut left = SideCounts::default();
+        let mut right = SideCounts::default();
+        for [(_, l), (_, r)] in per_file {
+            left.merge(l);
+            right.merge(r);
+        }
+        let sides = [(Side::Left, left), (Side::Right, right)];
+        let collocates = score(self, sides, frequencies, &eligible, options.min_count);
+        info!("{}: {} collocates", search.label, collocates.len());
+        Ok(collocates)
+    }
+
+    /// How the collocates of the hits of `search` change across decades:
+    /// the collocates of each decade, scored against the words of that
+    /// decade, for the union of the `top` collocates on each side of every
+    /// decade, so that each collocate can be followed through all decades
+    /// in which it occurs next to the hits. A long-format table, sorted by
+    /// side, word, and decade. This reads all corpus files.
+    pub fn collocates_by_decade(
+        &self,
+        search: &CohaSearch,
+        options: &CollocationOptions,
+        top: usize,
+    ) -> Result<Vec<DecadeCollocate>> {
+        let eligible = self.collocate_filter(options)?;
+        let per_file = self
+            .coha_files
+            .par_iter()
+            .map(|cf| {
+                let mut frequencies = vec![0; self.lexicon.len()];
+                let sides =
+                    self.count_collocates(cf, search, options, &eligible, Some(&mut frequencies))?;
+                // the collocates are sorted by side and log-likelihood
+                let mut ranks: FxHashMap<Side, usize> = FxHashMap::default();
+                let rows = score(self, sides, &frequencies, &eligible, 1)
+                    .into_iter()
+                    .map(|collocate| {
+                        let rank = (collocate.observed >= options.min_count).then(|| {
+                            let rank = ranks.entry(collocate.side).or_default();
+                            *rank += 1;
+                            *rank
+                        });
+                        DecadeCollocate {
+                            decade: cf.identifier.clone(),
+                            rank,
+                            collocate,
+                        }
+                    })
+                    .collect::<Vec<_>>();
+                Ok(rows)
+            })
+            .collect::<Result<Vec<_>>>()?;
+        let mut rows: Vec<DecadeCollocate> = per_file.into_iter().flatten().collect();
+        let selected: FxHashSet<(Side, String)> = rows
+            .iter()
+            .filter(|r| r.rank.is_some_and(|rank| rank <= top))
+            .map(|r| (r.collocate.side, r.collocate.word.clone()))
+            .collect();
+        rows.retain(|r| selected.contains(&(r.collocate.side, r.collocate.word.clone())));
+        rows.sort_by(|a, b| {
+            (a.collocate.side, &a.collocate.word, &a.decade).cmp(&(
+                b.collocate.side,
+                &b.collocate.word,
+                &b.decade,
+            ))
+        });
+        info!(
+            "{}: {} collocates in {} decades",
+            search.label,
+            selected.len(),
+            self.coha_files.len()
+        );
+        Ok(rows)
+    }
+}
+
+const SCORE_COLUMNS: [&str; 7] = [
+    "observed",
+    "expected",
+    "frequency",
+    "MI",
+    "log-likelihood",
+    "delta P collocate",
+    "delta P node",
+];
+
+impl Collocate {
+    fn score_values(&self) -> [String; 7] {
+        [
+            self.observed.to_string(),
+            format!("{:.3}", self.expected),
+            self.frequency.to_string(),
+            format!("{:.3}", self.mi),
+            format!("{:.3}", self.log_likelihood),
+            format!("{:.4}", self.delta_p_collocate),
+            format!("{:.4}", self.delta_p_node),
+        ]
+    }
+}
+
+/// Write the collocates as CSV.
+pub fn write_collocates<W: Write>(writer: W, collocates: &[Collocate]) -> Result<()> {
+    let mut writer = csv::Writer::from_writer(writer);
+    writer.write_record(["side", "word"].into_iter().chain(SCORE_COLUMNS))?;
+    for c in collocates {
+        let values = c.score_values();
+        writer.write_record(
+            [c.side.to_string(), c.word.clone()]
+                .into_iter()
+                .chain(values),
+        )?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+/// Write the collocates of each decade as CSV, one row per collocate and
+/// decade, with an empty rank for collocates that are too rare in a decade.
+pub fn write_decade_collocates<W: Write>(writer: W, collocates: &[DecadeCollocate]) -> Result<()> {
+    let mut writer = csv::Writer::from_writer(writer);
+    writer.write_record(
+        ["side", "word", "decade", "rank"]
+            .into_iter()
+            .chain(SCORE_COLUMNS),
+    )?;
+    for c in collocates {
+        let values = c.collocate.score_values();
+        writer.write_record(
+            [
+                c.collocate.side.to_string(),
+                c.collocate.word.clone(),
+                c.decade.clone(),
+                c.rank.map(|r| r.to_string()).unwrap_or_default(),
+            ]
+            .into_iter()
+            .chain(values),
+        )?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn close(a: f64, b: f64) -> bool {
+        (a - b).abs() < 1e-9
+    }
+
+    #[test]
+    fn scores() {
+        // 10 of the 100 tokens within the span, 50 of all 1000 tokens:
+        // o11 = 10, o12 = 90, o21 = 40, o22 = 860
+        let c = Collocate::new("dog".to_owned(), Side::Left, 10, 50, 100.0, 1000.0);
+        assert!(close(c.expected, 5.0));
+        assert!(close(c.mi, 1.0));
+        // 2 Σ o ln(o / e) with e = 5, 95, 45, 855
+        assert!(close(c.log_likelihood, 4.737383864602785));
+        assert!(close(c.delta_p_collocate, 10.0 / 100.0 - 40.0 / 900.0));
+        assert!(close(c.delta_p_node, 10.0 / 50.0 - 90.0 / 950.0));
+    }
+
+    #[test]
+    fn scores_without_association() {
+        // the collocate is as frequent within the span as elsewhere
+        let c = Collocate::new("the".to_owned(), Side::Right, 10, 100, 100.0, 1000.0);
+        assert!(close(c.expected, 10.0));
+        assert!(close(c.mi, 0.0));
+        assert!(close(c.log_likelihood, 0.0));
+        assert!(close(c.delta_p_collocate, 0.0));
+    }
+}
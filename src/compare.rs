@@ -0,0 +1,112 @@
+use crate::{Coha, SearchCounts, SearchPlan, SearchReport};
+use anyhow::{bail, Result};
+use log::{debug, info};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+
+/// Words in each (decade, genre) of a corpus.
+fn words_by_decade_genre(coha: &Coha) -> BTreeMap<(String, String), usize> {
+    coha.source_summary()
+        .into_iter()
+        .map(|s| ((s.decade, s.genre), s.words))
+        .collect()
+}
+
+fn per_million(hits: usize, words: usize) -> String {
+    if words == 0 {
+        String::new()
+    } else {
+        format!("{:.3}", hits as f64 * 1e6 / words as f64)
+    }
+}
+
+/// Write `{label}-comparison.csv` with the hits of one search in each corpus
+/// side by side, per (decade, genre) and in total.
+fn write_comparison(
+    result_dir: &Path,
+    names: &[&str],
+    words: &[BTreeMap<(String, String), usize>],
+    counts: &[&SearchCounts],
+) -> Result<()> {
+    let label = &counts[0].label;
+    let outpath = result_dir.join(format!("{label}-comparison.csv"));
+    debug!("{}: writing...", outpath.to_string_lossy());
+    let mut writer = csv::Writer::from_path(outpath)?;
+    let mut header = vec!["decade".to_owned(), "genre".to_owned()];
+    for name in names {
+        header.push(format!("{name} hits"));
+        header.push(format!("{name} words"));
+        header.push(format!("{name} per million"));
+    }
+    writer.write_record(&header)?;
+    let mut keys: Vec<&(String, String)> = words.iter().flat_map(|w| w.keys()).collect();
+    keys.extend(counts.iter().flat_map(|c| c.by_decade_genre.keys()));
+    keys.sort();
+    keys.dedup();
+    for key in keys {
+        let mut row = vec![key.0.clone(), key.1.clone()];
+        for (w, c) in words.iter().zip(counts) {
+            let hits = c.by_decade_genre.get(key).copied().unwrap_or(0);
+            let words = w.get(key).copied().unwrap_or(0);
+            row.push(hits.to_string());
+            row.push(words.to_string());
+            row.push(per_million(hits, words));
+        }
+        writer.write_record(&row)?;
+    }
+    let mut row = vec!["all".to_owned(), "all".to_owned()];
+    for (w, c) in words.iter().zip(counts) {
+        let words: usize = w.values().sum();
+        row.push(c.hits.to_string());
+        row.push(words.to_string());
+        row.push(per_million(c.hits, words));
+    }
+    writer.write_record(&row)?;
+    writer.flush()?;
+    Ok(())
+}
+
+impl SearchPlan {
+    /// Compile the searches against the corpus and run them.
+    pub fn run(&self, coha: &Coha, result_dir: &Path) -> Result<SearchReport> {
+        let compiled = self.compile(coha)?;
+        let searches: Vec<_> = compiled.iter().map(|c| c.search()).collect();
+        let searches: Vec<_> = searches.iter().collect();
+        coha.search_with(result_dir, &searches, &self.options)
+    }
+
+    /// Run the searches over several corpora, e.g. COHA and COCA or two
+    /// subsets of COHA. The results of corpus `name` are written to
+    /// `{result_dir}/{name}`, and the hit counts of each search are written
+    /// side by side to `{result_dir}/{label}-comparison.csv`, together with
+    /// the size of each (decade, genre) and the hits per million words.
+    /// Returns one report per corpus.
+    pub fn compare(
+        &self,
+        result_dir: &Path,
+        corpora: &[(&str, &Coha)],
+    ) -> Result<Vec<SearchReport>> {
+        let names: Vec<&str> = corpora.iter().map(|(name, _)| *name).collect();
+        for (i, name) in names.iter().enumerate() {
+            if name.is_empty() || name.contains(['/', '\\']) || names[..i].contains(name) {
+                bail!("invalid or duplicate corpus name {name:?}");
+            }
+        }
+        let mut reports = Vec::new();
+        for (name, coha) in corpora {
+            info!("corpus {name}: searching...");
+            reports.push(self.run(coha, &result_dir.join(name))?);
+        }
+        fs::create_dir_all(result_dir)?;
+        let words: Vec<_> = corpora
+            .iter()
+            .map(|(_, coha)| words_by_decade_genre(coha))
+            .collect();
+        for i in 0..self.searches.len() {
+            let counts: Vec<&SearchCounts> = reports.iter().map(|r| &r.searches[i]).collect();
+            write_comparison(result_dir, &names, &words, &counts)?;
+        }
+        Ok(reports)
+    }
+}
@@ -0,0 +1,196 @@
+use crate::{Coha, Restrictions, SlotField, SlotSpec, Token};
+use anyhow::{bail, Result};
+use log::{debug, info};
+use rayon::prelude::*;
+use rustc_hash::{FxHashMap, FxHashSet};
+use serde::{Deserialize, Serialize};
+use std::fs::{self, File};
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+/// Options of [`Coha::export_cooccurrences`].
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CooccurrenceOptions {
+    /// The lexicon field of the rows and columns, e.g. [`SlotField::Word`]
+    /// or [`SlotField::Lemma`].
+    pub field: SlotField,
+    /// Number of tokens on each side of a word that co-occur with it.
+    pub window: usize,
+    /// Weight each co-occurrence by 1 / distance, as in GloVe, instead of
+    /// counting it as 1.
+    pub harmonic: bool,
+    /// The rows and columns of the matrix. If empty, the `max_vocabulary`
+    /// most frequent values of `field`, which requires
+    /// [`Coha::compute_frequencies`].
+    pub vocabulary: Vec<String>,
+    pub max_vocabulary: usize,
+    /// Leave out the words that match this term, e.g. `pos:/^y/` for
+    /// punctuation. They still take up positions in the window.
+    pub exclude: Option<SlotSpec>,
+    /// Only count the texts that these restrictions allow.
+    pub restrictions: Restrictions,
+}
+
+impl Default for CooccurrenceOptions {
+    fn default() -> Self {
+        Self {
+            field: SlotField::Word,
+            window: 5,
+            harmonic: false,
+            vocabulary: Vec::new(),
+            max_vocabulary: 10_000,
+            exclude: None,
+            restrictions: Restrictions::default(),
+        }
+    }
+}
+
+/// Co-occurrence counts of (row, column) pairs of vocabulary indexes.
+type Matrix = FxHashMap<(u32, u32), f64>;
+
+impl Coha {
+    /// The vocabulary of the matrix and the index of each word ID in it.
+    fn cooccurrence_vocabulary(
+        &self,
+        options: &CooccurrenceOptions,
+    ) -> Result<(Vec<String>, Vec<Option<u32>>)> {
+        let exclude = options
+            .exclude
+            .as_ref()
+            .map(|s| s.compile(self))
+            .transpose()?;
+        let eligible = |word_id| {
+            !self.removed.contains(&word_id)
+                && !self.markers.contains(&word_id)
+                && !exclude.as_ref().is_some_and(|f| f.matches(word_id))
+        };
+        let vocabulary: Vec<String> = if options.vocabulary.is_empty() {
+            let Some(frequencies) = &self.frequencies else {
+                bail!("co-occurrences: word frequencies have not been computed");
+            };
+            let mut counts: FxHashMap<&str, u64> = FxHashMap::default();
+            for word in self.lexicon.iter().flatten() {
+                if eligible(word.word_id) {
+                    let f = frequencies.get(word.word_id.0).copied().unwrap_or(0);
+                    *counts.entry(options.field.get(word)).or_default() += f;
+                }
+            }
+            let mut counts: Vec<(&str, u64)> = counts.into_iter().filter(|c| c.1 > 0).collect();
+            counts.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(b.0)));
+            counts
+                .into_iter()
+                .take(options.max_vocabulary)
+                .map(|(value, _)| value.to_owned())
+                .collect()
+        } else {
+            let mut vocabulary = options.vocabulary.clone();
+            let mut seen = FxHashSet::default();
+            vocabulary.retain(|v| seen.insert(v.clone()));
+            vocabulary
+        };
+        let index: FxHashMap<&str, u32> = vocabulary
+            .iter()
+            .enumerate()
+            .map(|(i, v)| (v.as_str(), i as u32))
+            .collect();
+        let mut word_index = vec![None; self.lexicon.len()];
+        for word in self.lexicon.iter().flatten() {
+            if eligible(word.word_id) {
+                word_index[word.word_id.0] = index.get(options.field.get(word)).copied();
+            }
+        }
+        Ok((vocabulary, word_index))
+    }
+
+    /// Add the co-occurrences of one text. Windows do not extend over
+    /// removed material or paragraph and heading markers.
+    fn add_cooccurrences(
+        &self,
+        options: &CooccurrenceOptions,
+        word_index: &[Option<u32>],
+        tokens: &[Token],
+        matrix: &mut Matrix,
+    ) {
+        let index = |t: &Token| word_index.get(t.word_id.0).copied().flatten();
+        for (i, token) in tokens.iter().enumerate() {
+            let Some(a) = index(token) else {
+                continue;
+            };
+            for (d, other) in tokens[i + 1..].iter().take(options.window).enumerate() {
+                if self.is_removed(other) || self.is_marker(other) {
+                    break;
+                }
+                let Some(b) = index(other) else {
+                    continue;
+                };
+                let weight = if options.harmonic {
+                    1.0 / (d + 1) as f64
+                } else {
+                    1.0
+                };
+                *matrix.entry((a, b)).or_default() += weight;
+                *matrix.entry((b, a)).or_default() += weight;
+            }
+        }
+    }
+
+    /// Write a symmetric word–word co-occurrence matrix per corpus file
+    /// (decade), e.g. for training diachronic word embeddings: the
+    /// vocabulary as `{dir}/vocabulary.txt`, one value per line in the
+    /// order of the rows and columns, and the matrix of each decade as
+    /// `{dir}/{decade}.mtx` in the sparse Matrix Market coordinate format
+    /// (1-based row and column, value), e.g. for `scipy.io.mmread`. This
+    /// reads all corpus files.
+    pub fn export_cooccurrences(&self, dir: &Path, options: &CooccurrenceOptions) -> Result<()> {
+        if options.field == SlotField::Any {
+            bail!("co-occurrences: a lexicon field is required");
+        }
+        if options.window == 0 {
+            bail!("co-occurrences: window must be positive");
+        }
+        let (vocabulary, word_index) = self.cooccurrence_vocabulary(options)?;
+        info!(
+            "co-occurrences: {} words in the vocabulary",
+            vocabulary.len()
+        );
+        fs::create_dir_all(dir)?;
+        let mut writer = BufWriter::new(File::create(dir.join("vocabulary.txt"))?);
+        for value in &vocabulary {
+            writeln!(writer, "{value}")?;
+        }
+        writer.flush()?;
+        let lenient = self.load_options.lenient;
+        self.coha_files.par_iter().try_for_each(|cf| {
+            let mut matrix = Matrix::default();
+            cf.scan_texts(lenient, |text_id, tokens| {
+                let allowed = self
+                    .sources
+                    .get(&text_id)
+                    .is_some_and(|s| options.restrictions.allows(s));
+                if allowed {
+                    self.add_cooccurrences(options, &word_index, tokens, &mut matrix);
+                }
+                Ok(())
+            })?;
+            let outpath = dir.join(format!("{}.mtx", cf.identifier));
+            debug!("{}: writing...", outpath.to_string_lossy());
+            let mut writer = BufWriter::new(File::create(outpath)?);
+            let kind = if options.harmonic { "real" } else { "integer" };
+            writeln!(writer, "%%MatrixMarket matrix coordinate {kind} general")?;
+            let n = vocabulary.len();
+            writeln!(writer, "{} {} {}", n, n, matrix.len())?;
+            let mut entries: Vec<_> = matrix.into_iter().collect();
+            entries.sort_by_key(|e| e.0);
+            for ((a, b), x) in entries {
+                if options.harmonic {
+                    writeln!(writer, "{} {} {x:.6}", a + 1, b + 1)?;
+                } else {
+                    writeln!(writer, "{} {} {x}", a + 1, b + 1)?;
+                }
+            }
+            writer.flush()?;
+            Ok(())
+        })
+    }
+}
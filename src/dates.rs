@@ -0,0 +1,194 @@
+use anyhow::{bail, Result};
+use serde::{Deserialize, Serialize};
+use std::cmp::Ordering;
+use std::fmt;
+use std::str::FromStr;
+
+const MONTHS: [&str; 12] = [
+    "january",
+    "february",
+    "march",
+    "april",
+    "may",
+    "june",
+    "july",
+    "august",
+    "september",
+    "october",
+    "november",
+    "december",
+];
+
+/// A publication date finer than the year, e.g. of a newspaper or magazine
+/// issue, parsed from the publication information of the sources table.
+/// Written as `1925-03` or `1925-03-14`.
+#[derive(Copy, Debug, Clone, Eq, PartialEq, Ord, PartialOrd, Hash)]
+pub struct PublicationDate {
+    pub year: u16,
+    /// 1–12.
+    pub month: u8,
+    /// Day of the month, if known.
+    pub day: Option<u8>,
+}
+
+fn days_in_month(year: u16, month: u8) -> u8 {
+    match month {
+        2 if year.is_multiple_of(4) && (!year.is_multiple_of(100) || year.is_multiple_of(400)) => {
+            29
+        }
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+/// The month of a month name or its abbreviation of at least three
+/// letters, e.g. "Sept".
+fn month_number(word: &str) -> Option<u8> {
+    let word = word.to_lowercase();
+    if word.len() < 3 {
+        return None;
+    }
+    MONTHS
+        .iter()
+        .position(|m| m.starts_with(&word))
+        .map(|i| i as u8 + 1)
+}
+
+/// A day of the month, e.g. "14" or "14th".
+fn day_number(word: &str) -> Option<u8> {
+    let digits = word.trim_end_matches(|c: char| c.is_ascii_alphabetic());
+    let suffix = &word[digits.len()..];
+    if !matches!(suffix, "" | "st" | "nd" | "rd" | "th") || digits.len() > 2 {
+        return None;
+    }
+    digits.parse().ok()
+}
+
+impl PublicationDate {
+    fn new(year: u16, month: u8, day: Option<u8>) -> Option<Self> {
+        if !(1..=12).contains(&month) {
+            return None;
+        }
+        if day.is_some_and(|d| d == 0 || d > days_in_month(year, month)) {
+            return None;
+        }
+        Some(Self { year, month, day })
+    }
+
+    /// Find a date in the given year in publication information such as
+    /// "Chicago Tribune, 1925/03/14", "Time, 19250314", "Harper's, March
+    /// 14, 1925", "14 March 1925", or "Atlantic, Mar. 1925". Dates in other
+    /// years are ignored, as they are more likely to refer to something else
+    /// than the issue.
+    pub fn parse(info: &str, year: u16) -> Option<Self> {
+        let words: Vec<&str> = info
+            .split(|c: char| !c.is_alphanumeric() && c != '-' && c != '/')
+            .filter(|w| !w.is_empty())
+            .collect();
+        let is_year = |w: &str| w.len() == 4 && w.parse() == Ok(year);
+        for (i, &word) in words.iter().enumerate() {
+            // 1925-03-14, 1925/03/14, 1925-03
+            let mut parts = word.split(['-', '/']);
+            if parts.next().is_some_and(is_year) {
+                let month = parts.next().and_then(|m| m.parse().ok());
+                let day = parts.next().map(|d| d.parse().ok());
+                if let (Some(month), None | Some(Some(_)), None) = (month, day, parts.next()) {
+                    if let Some(date) = Self::new(year, month, day.flatten()) {
+                        return Some(date);
+                    }
+                }
+            }
+            // 19250314
+            if word.len() == 8 && word.bytes().all(|b| b.is_ascii_digit()) && is_year(&word[..4]) {
+                let month = word[4..6].parse().unwrap_or_default();
+                let day = word[6..8].parse().ok();
+                if let Some(date) = Self::new(year, month, day) {
+                    return Some(date);
+                }
+            }
+            let Some(month) = month_number(word) else {
+                continue;
+            };
+            let next = |j: usize| words.get(j).copied().unwrap_or_default();
+            if is_year(next(i + 1)) {
+                // 14 March 1925; March 1925
+                let day = i.checked_sub(1).and_then(|j| day_number(words[j]));
+                return Self::new(year, month, day).or(Self::new(year, month, None));
+            }
+            // March 14, 1925
+            if let Some(day) = day_number(next(i + 1)) {
+                if is_year(next(i + 2)) {
+                    if let Some(date) = Self::new(year, month, Some(day)) {
+                        return Some(date);
+                    }
+                }
+            }
+        }
+        None
+    }
+
+    /// The month, e.g. `1925-03`.
+    pub fn month_label(&self) -> String {
+        format!("{:04}-{:02}", self.year, self.month)
+    }
+
+    /// Compare with another date at the precision of the less precise one,
+    /// e.g. 1925-03 is equal to 1925-03-14.
+    pub(crate) fn cmp_coarse(&self, other: &Self) -> Ordering {
+        (self.year, self.month)
+            .cmp(&(other.year, other.month))
+            .then_with(|| match (self.day, other.day) {
+                (Some(a), Some(b)) => a.cmp(&b),
+                _ => Ordering::Equal,
+            })
+    }
+}
+
+impl fmt::Display for PublicationDate {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.month_label())?;
+        if let Some(day) = self.day {
+            write!(f, "-{day:02}")?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for PublicationDate {
+    type Err = anyhow::Error;
+
+    /// Parse a date written as `1925-03` or `1925-03-14`.
+    fn from_str(s: &str) -> Result<Self> {
+        let mut parts = s.split('-');
+        let (Some(year), Some(month), day, None) =
+            (parts.next(), parts.next(), parts.next(), parts.next())
+        else {
+            bail!("invalid date {s:?}, expected YYYY-MM or YYYY-MM-DD");
+        };
+        let year = year.parse()?;
+        let day = day.map(|d| d.parse()).transpose()?;
+        match Self::new(year, month.parse()?, day) {
+            Some(date) => Ok(date),
+            None => bail!("invalid date {s:?}"),
+        }
+    }
+}
+
+impl Serialize for PublicationDate {
+    fn serialize<S: serde::Serializer>(
+        &self,
+        serializer: S,
+    ) -> std::result::Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for PublicationDate {
+    fn deserialize<D: serde::Deserializer<'de>>(
+        deserializer: D,
+    ) -> std::result::Result<Self, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        s.parse().map_err(serde::de::Error::custom)
+    }
+}
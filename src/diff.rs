@@ -0,0 +1,139 @@
+use anyhow::{bail, Result};
+use log::debug;
+use serde::Serialize;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs::{self, File};
+use std::io::{BufRead, BufReader};
+use std::path::Path;
+
+const HIT_ID_COLUMN: &str = "hit ID";
+
+/// Hits of one search that differ between two result directories.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct SearchDiff {
+    pub label: String,
+    /// Hit IDs found only in the new results.
+    pub gained: Vec<String>,
+    /// Hit IDs found only in the old results.
+    pub lost: Vec<String>,
+    /// Number of hits found in both.
+    pub unchanged: usize,
+}
+
+/// The search label of a hit ID, `{text ID}:{position}:{label}`.
+fn label_of(hit_id: &str) -> Option<&str> {
+    hit_id.splitn(3, ':').nth(2)
+}
+
+/// Hits in corpus order: by text ID, then position.
+fn sort_hits(hits: &mut [String]) {
+    hits.sort_by_cached_key(|hit_id| {
+        let mut parts = hit_id
+            .splitn(3, ':')
+            .map(|x| x.parse::<usize>().unwrap_or(0));
+        (parts.next(), parts.next())
+    });
+}
+
+fn add_hit(hits: &mut BTreeMap<String, BTreeSet<String>>, path: &Path, hit_id: &str) -> Result<()> {
+    let Some(label) = label_of(hit_id) else {
+        bail!("{}: invalid hit ID {hit_id:?}", path.to_string_lossy());
+    };
+    hits.entry(label.to_owned())
+        .or_default()
+        .insert(hit_id.to_owned());
+    Ok(())
+}
+
+fn read_csv(path: &Path, hits: &mut BTreeMap<String, BTreeSet<String>>) -> Result<()> {
+    let mut reader = csv::Reader::from_path(path)?;
+    if reader.headers()?.get(0) != Some(HIT_ID_COLUMN) {
+        // not a concordance file, e.g. counts
+        return Ok(());
+    }
+    debug!("{}: reading...", path.to_string_lossy());
+    for record in reader.records() {
+        if let Some(hit_id) = record?.get(0) {
+            add_hit(hits, path, hit_id)?;
+        }
+    }
+    Ok(())
+}
+
+fn read_jsonl(path: &Path, hits: &mut BTreeMap<String, BTreeSet<String>>) -> Result<()> {
+    debug!("{}: reading...", path.to_string_lossy());
+    for line in BufReader::new(File::open(path)?).lines() {
+        let value: serde_json::Value = serde_json::from_str(&line?)?;
+        let Some(hit_id) = value.get(HIT_ID_COLUMN).and_then(|x| x.as_str()) else {
+            return Ok(());
+        };
+        add_hit(hits, path, hit_id)?;
+    }
+    Ok(())
+}
+
+/// The hit IDs in all result files below `dir`, by search label.
+fn read_hits(dir: &Path, hits: &mut BTreeMap<String, BTreeSet<String>>) -> Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            read_hits(&path, hits)?;
+            continue;
+        }
+        match path.extension().and_then(|e| e.to_str()) {
+            Some("csv") => read_csv(&path, hits)?,
+            Some("jsonl") => read_jsonl(&path, hits)?,
+            _ => (),
+        }
+    }
+    Ok(())
+}
+
+/// Compare the hits in two result directories, e.g. before and after
+/// refining a filter. Hits are identified by their hit IDs, so the
+/// comparison does not depend on the order of the hits or the layout of
+/// the result files; both runs need
+/// [`crate::SearchOptions::hit_id_column`].
+pub fn diff_results(old_dir: &Path, new_dir: &Path) -> Result<Vec<SearchDiff>> {
+    let mut old = BTreeMap::new();
+    read_hits(old_dir, &mut old)?;
+    let mut new = BTreeMap::new();
+    read_hits(new_dir, &mut new)?;
+    let labels: BTreeSet<&String> = old.keys().chain(new.keys()).collect();
+    let empty = BTreeSet::new();
+    Ok(labels
+        .into_iter()
+        .map(|label| {
+            let old = old.get(label).unwrap_or(&empty);
+            let new = new.get(label).unwrap_or(&empty);
+            let mut gained: Vec<String> = new.difference(old).cloned().collect();
+            let mut lost: Vec<String> = old.difference(new).cloned().collect();
+            sort_hits(&mut gained);
+            sort_hits(&mut lost);
+            SearchDiff {
+                label: label.clone(),
+                gained,
+                lost,
+                unchanged: old.intersection(new).count(),
+            }
+        })
+        .collect())
+}
+
+impl SearchDiff {
+    /// Write the gained and lost hits as `{label}-diff.csv` in `dir`.
+    pub fn write(&self, dir: &Path) -> Result<()> {
+        let outpath = dir.join(format!("{}-diff.csv", self.label));
+        debug!("{}: writing...", outpath.to_string_lossy());
+        let mut writer = csv::Writer::from_path(outpath)?;
+        writer.write_record([HIT_ID_COLUMN, "change"])?;
+        for hit_id in &self.gained {
+            writer.write_record([hit_id, "gained"])?;
+        }
+        for hit_id in &self.lost {
+            writer.write_record([hit_id, "lost"])?;
+        }
+        writer.flush()?;
+        Ok(())
+    }
+}
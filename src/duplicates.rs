@@ -0,0 +1,197 @@
+use crate::manifest::Fnv1a;
+use crate::output::HitRow;
+use crate::random::mix;
+use crate::TextId;
+use log::info;
+use rustc_hash::FxHashMap;
+use serde::{Deserialize, Serialize};
+
+/// Number of hash functions in a MinHash signature.
+const SIGNATURE_LEN: usize = 32;
+/// Signature values per band in locality-sensitive hashing; hits whose
+/// signatures agree on all values of some band are compared.
+const BAND_LEN: usize = 4;
+
+/// Detection of near-duplicate hits in different texts, e.g. reprints and
+/// syndicated news, by comparing the sets of word n-grams ("shingles") of
+/// their contexts. See [`SearchOptions::near_duplicates`].
+///
+/// [`SearchOptions::near_duplicates`]: crate::SearchOptions::near_duplicates
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct NearDuplicates {
+    /// Number of words per shingle.
+    pub shingle_words: usize,
+    /// Minimum similarity (estimated Jaccard similarity of the shingle
+    /// sets, 0–1) of near-duplicate hits.
+    pub threshold: f64,
+    /// Keep only the first hit of each cluster of near-duplicates.
+    pub collapse: bool,
+}
+
+impl Default for NearDuplicates {
+    fn default() -> Self {
+        Self {
+            shingle_words: 5,
+            threshold: 0.8,
+            collapse: false,
+        }
+    }
+}
+
+/// MinHash signature of a hit context, see [`NearDuplicates`].
+pub(crate) struct Fingerprint {
+    pub text_id: TextId,
+    signature: [u64; SIGNATURE_LEN],
+}
+
+impl Fingerprint {
+    pub(crate) fn new(text_id: TextId, words: &[&str], shingle_words: usize) -> Self {
+        let k = shingle_words.clamp(1, words.len().max(1));
+        let mut signature = [u64::MAX; SIGNATURE_LEN];
+        for shingle in words.windows(k) {
+            let mut h = Fnv1a::default();
+            for word in shingle {
+                h.write(word.as_bytes());
+                h.write(&[0]);
+            }
+            let h = h.finish();
+            for (i, s) in signature.iter_mut().enumerate() {
+                *s = (*s).min(mix(h, i));
+            }
+        }
+        Fingerprint { text_id, signature }
+    }
+
+    fn similarity(&self, other: &Fingerprint) -> f64 {
+        let same = self
+            .signature
+            .iter()
+            .zip(&other.signature)
+            .filter(|(a, b)| a == b)
+            .count();
+        same as f64 / SIGNATURE_LEN as f64
+    }
+}
+
+fn find(parent: &mut [usize], mut i: usize) -> usize {
+    while parent[i] != i {
+        parent[i] = parent[parent[i]];
+        i = parent[i];
+    }
+    i
+}
+
+impl NearDuplicates {
+    /// Cluster the rows, in corpus order, by their fingerprints. Adds the
+    /// cluster number (empty for hits without near-duplicates) and the
+    /// cluster size to each record if `add_columns`, and marks all but the
+    /// first hit of each cluster as duplicates.
+    pub(crate) fn mark(&self, label: &str, rows: &mut [&mut HitRow], add_columns: bool) {
+        let n = rows.len();
+        let mut parent: Vec<usize> = (0..n).collect();
+        let mut buckets: FxHashMap<(usize, &[u64]), Vec<usize>> = FxHashMap::default();
+        for (i, row) in rows.iter().enumerate() {
+            let Some(fp) = &row.fingerprint else {
+                continue;
+            };
+            for (b, band) in fp.signature.chunks(BAND_LEN).enumerate() {
+                buckets.entry((b, band)).or_default().push(i);
+            }
+        }
+        for bucket in buckets.values() {
+            for (x, &i) in bucket.iter().enumerate() {
+                for &j in &bucket[x + 1..] {
+                    let (Some(a), Some(b)) = (&rows[i].fingerprint, &rows[j].fingerprint) else {
+                        continue;
+                    };
+                    if a.text_id != b.text_id && a.similarity(b) >= self.threshold {
+                        let (ri, rj) = (find(&mut parent, i), find(&mut parent, j));
+                        parent[ri.max(rj)] = ri.min(rj);
+                    }
+                }
+            }
+        }
+        let roots: Vec<usize> = (0..n).map(|i| find(&mut parent, i)).collect();
+        let mut sizes = vec![0; n];
+        for &r in &roots {
+            sizes[r] += 1;
+        }
+        let mut numbers = vec![0; n];
+        let mut clusters = 0;
+        let mut duplicates = 0;
+        for (i, row) in rows.iter_mut().enumerate() {
+            let root = roots[i];
+            let size = sizes[root];
+            if size > 1 && root == i {
+                clusters += 1;
+                numbers[i] = clusters;
+            }
+            row.duplicate = root != i;
+            duplicates += usize::from(row.duplicate);
+            if add_columns {
+                row.record.push(if size > 1 {
+                    numbers[root].to_string()
+                } else {
+                    String::new()
+                });
+                row.record.push(size.to_string());
+            }
+        }
+        info!("{label}: {duplicates} near-duplicate hits in {clusters} clusters");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn row(text_id: usize, context: &str) -> HitRow {
+        let words: Vec<&str> = context.split(' ').collect();
+        HitRow {
+            filler: String::new(),
+            sort_key: Vec::new(),
+            fingerprint: Some(Fingerprint::new(TextId(text_id), &words, 3)),
+            duplicate: false,
+            file: String::new(),
+            record: Vec::new(),
+        }
+    }
+
+    const REPRINT: &str =
+        "the president said that the new tariff would be going to hurt the farmers";
+    const OTHER: &str = "she was going to see her mother in the city before the winter came";
+
+    #[test]
+    fn similarity() {
+        let a = row(1, REPRINT).fingerprint.unwrap();
+        let b = row(2, REPRINT).fingerprint.unwrap();
+        let c = row(3, OTHER).fingerprint.unwrap();
+        assert_eq!(a.similarity(&b), 1.0);
+        assert!(a.similarity(&c) < 0.5);
+    }
+
+    #[test]
+    fn mark_clusters_across_texts() {
+        let mut rows = [
+            row(1, REPRINT),
+            row(2, OTHER),
+            row(3, REPRINT),
+            row(4, REPRINT),
+        ];
+        let mut refs: Vec<&mut HitRow> = rows.iter_mut().collect();
+        NearDuplicates::default().mark("q", &mut refs, true);
+        let records: Vec<&[String]> = rows.iter().map(|r| &r.record[..]).collect();
+        assert_eq!(records, [["1", "3"], ["", "1"], ["1", "3"], ["1", "3"]]);
+        let duplicates: Vec<bool> = rows.iter().map(|r| r.duplicate).collect();
+        assert_eq!(duplicates, [false, false, true, true]);
+    }
+
+    #[test]
+    fn same_text_is_not_a_duplicate() {
+        let mut rows = [row(1, REPRINT), row(1, REPRINT)];
+        let mut refs: Vec<&mut HitRow> = rows.iter_mut().collect();
+        NearDuplicates::default().mark("q", &mut refs, false);
+        assert!(rows.iter().all(|r| !r.duplicate && r.record.is_empty()));
+    }
+}
@@ -0,0 +1,319 @@
+use crate::random::Rng;
+use crate::{Coha, CohaSearch, Source, TextId, Token};
+use anyhow::{bail, Result};
+use log::{info, warn};
+use rayon::prelude::*;
+use rustc_hash::{FxHashMap, FxHashSet};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::io::Write;
+
+/// Normal quantile of the 95% intervals of [`HitEstimate`].
+const Z_95: f64 = 1.96;
+
+/// Parameters of [`Coha::estimate_hits`]: which texts to sample.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Estimate {
+    /// Fraction of the texts of each decade to search, e.g. 0.05.
+    pub fraction: f64,
+    /// Search at least this many texts of each decade (or all of them, if
+    /// there are fewer).
+    pub min_texts: usize,
+    /// Seed of the random number generator; the same seed gives the same
+    /// sample.
+    pub seed: u64,
+}
+
+impl Default for Estimate {
+    fn default() -> Self {
+        Self {
+            fraction: 0.05,
+            min_texts: 5,
+            seed: 1,
+        }
+    }
+}
+
+impl Estimate {
+    fn check(&self) -> Result<()> {
+        if !(self.fraction > 0.0 && self.fraction <= 1.0) {
+            bail!("estimate: fraction must be between 0 and 1");
+        }
+        Ok(())
+    }
+}
+
+/// Estimated number of hits of a search in one decade, or in the entire
+/// corpus (decade "all"), see [`Coha::estimate_hits`].
+#[derive(Debug, Clone, Serialize)]
+pub struct HitEstimate {
+    pub label: String,
+    /// Decade, e.g. "1850s", or "all".
+    pub decade: String,
+    /// Texts allowed by the restrictions of the search.
+    pub texts: usize,
+    pub sampled_texts: usize,
+    /// Words of the allowed texts, according to the sources table.
+    pub words: usize,
+    pub sampled_words: usize,
+    /// Hits found in the sampled texts.
+    pub sampled_hits: usize,
+    /// Estimated hits in all allowed texts.
+    pub hits: f64,
+    pub per_million: f64,
+    /// Standard error of `hits`; NaN if a decade has only one sampled text
+    /// out of several.
+    pub standard_error: f64,
+    /// 95% confidence interval of `hits`, from the normal approximation.
+    /// The lower bound is at least `sampled_hits`.
+    pub interval: (f64, f64),
+}
+
+/// (hits, words) of the sampled texts and (texts, words) of all texts in
+/// one decade.
+#[derive(Default)]
+struct Stratum {
+    sample: Vec<(usize, usize)>,
+    texts: usize,
+    words: usize,
+}
+
+impl Stratum {
+    /// Ratio estimate of the hits and its variance.
+    fn estimate(&self) -> (f64, f64) {
+        let n = self.sample.len();
+        let hits: usize = self.sample.iter().map(|t| t.0).sum();
+        let words: usize = self.sample.iter().map(|t| t.1).sum();
+        if n == 0 || words == 0 {
+            return (0.0, 0.0);
+        }
+        let ratio = hits as f64 / words as f64;
+        let total = ratio * self.words as f64;
+        if n == self.texts {
+            return (total, 0.0);
+        }
+        if n < 2 {
+            return (total, f64::NAN);
+        }
+        let residuals: f64 = self
+            .sample
+            .iter()
+            .map(|&(h, w)| (h as f64 - ratio * w as f64).powi(2))
+            .sum::<f64>()
+            / (n - 1) as f64;
+        let big_n = self.texts as f64;
+        let fpc = 1.0 - n as f64 / big_n;
+        (total, big_n * big_n * fpc * residuals / n as f64)
+    }
+}
+
+fn decade(source: &Source) -> String {
+    format!("{}s", source.year.0 / 10 * 10)
+}
+
+impl Coha {
+    /// The texts to search: a random sample of each decade that has a
+    /// corpus file.
+    fn sample_texts(&self, estimate: &Estimate) -> FxHashSet<TextId> {
+        let decades: FxHashSet<&str> = self
+            .coha_files
+            .iter()
+            .map(|cf| cf.identifier.as_str())
+            .collect();
+        let mut by_decade: BTreeMap<String, Vec<TextId>> = BTreeMap::new();
+        for source in self.sources.values() {
+            let decade = decade(source);
+            if decades.contains(decade.as_str()) {
+                by_decade.entry(decade).or_default().push(source.text_id);
+            }
+        }
+        let mut rng = Rng(estimate.seed);
+        let mut sample = FxHashSet::default();
+        for texts in by_decade.values_mut() {
+            texts.sort();
+            let n = (texts.len() as f64 * estimate.fraction).ceil() as usize;
+            let n = n.max(estimate.min_texts).min(texts.len());
+            for i in 0..n {
+                let j = i + rng.below(texts.len() - i);
+                texts.swap(i, j);
+            }
+            sample.extend(&texts[..n]);
+        }
+        sample
+    }
+
+    /// Estimate the number of hits of each search from a random sample of
+    /// texts, stratified by decade, without reading the rest of the corpus:
+    /// a quick check of whether a query is worth a full run. The hits per
+    /// word of the sampled texts are extrapolated to the words of all texts
+    /// of the decade (a ratio estimate), and the error bounds reflect how
+    /// much the texts differ. The sampled texts are read with the text
+    /// index if it exists (see [`Coha::text_index`]) and in the
+    /// one-file-per-text layout; otherwise the corpus files are scanned but
+    /// only the sampled texts are matched. Decades without a corpus file
+    /// are left out.
+    pub fn estimate_hits(
+        &self,
+        searches: &[&CohaSearch],
+        estimate: &Estimate,
+    ) -> Result<Vec<HitEstimate>> {
+        estimate.check()?;
+        let sample = self.sample_texts(estimate);
+        info!("estimate: searching {} texts", sample.len());
+        let count_hits = |tokens: &[Token]| -> Vec<usize> {
+            searches
+                .iter()
+                .map(|search| self.find_spans(search, tokens).len())
+                .collect()
+        };
+        let per_file = self
+            .coha_files
+            .par_iter()
+            .map(|cf| {
+                let mut hits: FxHashMap<TextId, Vec<usize>> = FxHashMap::default();
+                if cf.text_files.is_empty() && self.text_index.get().is_none() {
+                    cf.scan_texts(self.load_options.lenient, |text_id, tokens| {
+                        if sample.contains(&text_id) {
+                            hits.insert(text_id, count_hits(tokens));
+                        }
+                        Ok(())
+                    })?;
+                    return Ok(hits);
+                }
+                let mut texts: Vec<TextId> = sample
+                    .iter()
+                    .copied()
+                    .filter(|id| {
+                        self.sources
+                            .get(id)
+                            .is_some_and(|s| decade(s) == cf.identifier)
+                    })
+                    .collect();
+                texts.sort();
+                for text_id in texts {
+                    match self.read_text_tokens(text_id) {
+                        Ok(tokens) => {
+                            hits.insert(text_id, count_hits(&tokens));
+                        }
+                        Err(e) => warn!("estimate: {e}"),
+                    }
+                }
+                Ok(hits)
+            })
+            .collect::<Result<Vec<_>>>()?;
+        let hits: FxHashMap<TextId, Vec<usize>> = per_file.into_iter().flatten().collect();
+
+        let decades: FxHashSet<&str> = self
+            .coha_files
+            .iter()
+            .map(|cf| cf.identifier.as_str())
+            .collect();
+        let mut sources: Vec<&Source> = self.sources.values().collect();
+        sources.sort_by_key(|s| s.text_id);
+        let mut result = Vec::new();
+        for (i, search) in searches.iter().enumerate() {
+            let mut strata: BTreeMap<String, Stratum> = BTreeMap::new();
+            for &source in &sources {
+                let decade = decade(source);
+                if !decades.contains(decade.as_str()) || !search.restrictions.allows(source) {
+                    continue;
+                }
+                let stratum = strata.entry(decade).or_default();
+                stratum.texts += 1;
+                stratum.words += source.words;
+                if let Some(h) = hits.get(&source.text_id) {
+                    stratum.sample.push((h[i], source.words));
+                }
+            }
+            let mut total = HitEstimate::new(&search.label, "all");
+            let mut variance = 0.0;
+            for (decade, stratum) in &strata {
+                let (hits, v) = stratum.estimate();
+                let mut row = HitEstimate::new(&search.label, decade);
+                row.add(stratum, hits);
+                row.finish(v);
+                total.add(stratum, hits);
+                variance += v;
+                result.push(row);
+            }
+            total.finish(variance);
+            result.push(total);
+        }
+        Ok(result)
+    }
+}
+
+impl HitEstimate {
+    fn new(label: &str, decade: &str) -> Self {
+        Self {
+            label: label.to_owned(),
+            decade: decade.to_owned(),
+            texts: 0,
+            sampled_texts: 0,
+            words: 0,
+            sampled_words: 0,
+            sampled_hits: 0,
+            hits: 0.0,
+            per_million: 0.0,
+            standard_error: 0.0,
+            interval: (0.0, 0.0),
+        }
+    }
+
+    fn add(&mut self, stratum: &Stratum, hits: f64) {
+        self.texts += stratum.texts;
+        self.sampled_texts += stratum.sample.len();
+        self.words += stratum.words;
+        self.sampled_words += stratum.sample.iter().map(|t| t.1).sum::<usize>();
+        self.sampled_hits += stratum.sample.iter().map(|t| t.0).sum::<usize>();
+        self.hits += hits;
+    }
+
+    fn finish(&mut self, variance: f64) {
+        if self.words > 0 {
+            self.per_million = self.hits * 1e6 / self.words as f64;
+        }
+        self.standard_error = variance.sqrt();
+        let margin = Z_95 * self.standard_error;
+        let lower = (self.hits - margin).max(self.sampled_hits as f64);
+        self.interval = (lower, self.hits + margin);
+    }
+}
+
+/// Write the estimates as CSV.
+pub fn write_hit_estimates<W: Write>(writer: W, estimates: &[HitEstimate]) -> Result<()> {
+    let mut writer = csv::Writer::from_writer(writer);
+    writer.write_record([
+        "search",
+        "decade",
+        "texts",
+        "sampled texts",
+        "words",
+        "sampled words",
+        "sampled hits",
+        "hits",
+        "per million",
+        "standard error",
+        "lower",
+        "upper",
+    ])?;
+    for e in estimates {
+        writer.write_record([
+            e.label.clone(),
+            e.decade.clone(),
+            e.texts.to_string(),
+            e.sampled_texts.to_string(),
+            e.words.to_string(),
+            e.sampled_words.to_string(),
+            e.sampled_hits.to_string(),
+            format!("{:.1}", e.hits),
+            format!("{:.3}", e.per_million),
+            format!("{:.1}", e.standard_error),
+            format!("{:.1}", e.interval.0),
+            format!("{:.1}", e.interval.1),
+        ])?;
+    }
+    writer.flush()?;
+    Ok(())
+}
@@ -0,0 +1,296 @@
+use crate::{Coha, CohaFilter, Restrictions, TextId, WordId};
+use anyhow::Result;
+use itertools::Itertools;
+use log::{debug, info};
+use rayon::prelude::*;
+use regex::Regex;
+use rustc_hash::FxHashSet;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs::{self, File};
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+#[derive(Copy, Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ExportFormat {
+    Csv,
+    /// A JSON array of objects.
+    Json,
+}
+
+impl Coha {
+    /// Write the lexicon as UTF-8, optionally only the words accepted by
+    /// `filter`.
+    pub fn export_lexicon(
+        &self,
+        path: &Path,
+        format: ExportFormat,
+        filter: Option<&CohaFilter>,
+    ) -> Result<()> {
+        debug!("{}: writing...", path.to_string_lossy());
+        let words = self
+            .lexicon
+            .iter()
+            .flatten()
+            .filter(|w| filter.is_none_or(|f| f.matches(w.word_id)));
+        match format {
+            ExportFormat::Csv => {
+                let mut writer = csv::Writer::from_path(path)?;
+                writer.write_record(["wID", "wordCS", "word", "lemma", "PoS"])?;
+                for w in words {
+                    writer.write_record([
+                        &w.word_id.0.to_string(),
+                        &w.word_cs,
+                        &w.word,
+                        &w.lemma,
+                        &w.pos,
+                    ])?;
+                }
+                writer.flush()?;
+            }
+            ExportFormat::Json => write_json(path, &words.collect::<Vec<_>>())?,
+        }
+        Ok(())
+    }
+}
+
+/// Number of texts and words in one part of the corpus.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct SourceSummary {
+    pub decade: String,
+    pub genre: String,
+    pub texts: usize,
+    pub words: usize,
+}
+
+fn write_json<T: Serialize>(path: &Path, value: &T) -> Result<()> {
+    let mut writer = BufWriter::new(File::create(path)?);
+    serde_json::to_writer(&mut writer, value)?;
+    writer.flush()?;
+    Ok(())
+}
+
+impl Coha {
+    /// Write the sources table as UTF-8, sorted by text ID.
+    pub fn export_sources(&self, path: &Path, format: ExportFormat) -> Result<()> {
+        debug!("{}: writing...", path.to_string_lossy());
+        let text_ids: Vec<TextId> = self.sources.keys().copied().sorted().collect();
+        let mut writer = BufWriter::new(File::create(path)?);
+        self.write_sources(&mut writer, format, &text_ids)?;
+        writer.flush()?;
+        Ok(())
+    }
+
+    /// The texts that the restrictions allow and whose author matches
+    /// `author`, sorted by text ID, e.g. for designing a text ID allowlist.
+    pub fn select_sources(
+        &self,
+        restrictions: &Restrictions,
+        author: Option<&Regex>,
+    ) -> Vec<TextId> {
+        let selected: Vec<TextId> = self
+            .sources
+            .values()
+            .filter(|s| restrictions.allows(s) && author.is_none_or(|re| re.is_match(&s.author)))
+            .map(|s| s.text_id)
+            .sorted()
+            .collect();
+        info!(
+            "selected {} texts, {} words",
+            selected.len(),
+            selected
+                .iter()
+                .map(|id| self.sources[id].words)
+                .sum::<usize>()
+        );
+        selected
+    }
+
+    /// Write the rows of the sources table for the given texts, in the given
+    /// order. Unknown text IDs are skipped.
+    pub fn write_sources<W: Write>(
+        &self,
+        writer: W,
+        format: ExportFormat,
+        text_ids: &[TextId],
+    ) -> Result<()> {
+        let sources = text_ids.iter().filter_map(|id| self.sources.get(id));
+        match format {
+            ExportFormat::Csv => {
+                let mut writer = csv::Writer::from_writer(writer);
+                writer.write_record([
+                    "text ID",
+                    "words",
+                    "genre",
+                    "year",
+                    "title",
+                    "author",
+                    "publication information",
+                    "LC classification",
+                    "date",
+                ])?;
+                for s in sources {
+                    writer.write_record([
+                        &s.text_id.0.to_string(),
+                        &s.words.to_string(),
+                        &s.genre.to_string(),
+                        &s.year.0.to_string(),
+                        &s.title,
+                        &s.author,
+                        &s.publication_info,
+                        &s.lc_classification,
+                        &s.date.map(|d| d.to_string()).unwrap_or_default(),
+                    ])?;
+                }
+                writer.flush()?;
+            }
+            ExportFormat::Json => serde_json::to_writer(writer, &sources.collect::<Vec<_>>())?,
+        }
+        Ok(())
+    }
+
+    /// Number of texts and words per decade and genre, according to the
+    /// sources table.
+    pub fn source_summary(&self) -> Vec<SourceSummary> {
+        let mut summary: BTreeMap<(String, String), SourceSummary> = BTreeMap::new();
+        for s in self.sources.values() {
+            let decade = format!("{}s", s.year.0 / 10 * 10);
+            let genre = s.genre.to_string();
+            let entry = summary
+                .entry((decade.clone(), genre.clone()))
+                .or_insert_with(|| SourceSummary {
+                    decade,
+                    genre,
+                    ..Default::default()
+                });
+            entry.texts += 1;
+            entry.words += s.words;
+        }
+        summary.into_values().collect()
+    }
+
+    /// Write [`Coha::source_summary`].
+    pub fn export_source_summary(&self, path: &Path, format: ExportFormat) -> Result<()> {
+        debug!("{}: writing...", path.to_string_lossy());
+        let summary = self.source_summary();
+        match format {
+            ExportFormat::Csv => {
+                let mut writer = csv::Writer::from_path(path)?;
+                writer.write_record(["decade", "genre", "texts", "words"])?;
+                for x in summary {
+                    writer.write_record([
+                        x.decade,
+                        x.genre,
+                        x.texts.to_string(),
+                        x.words.to_string(),
+                    ])?;
+                }
+                writer.flush()?;
+            }
+            ExportFormat::Json => write_json(path, &summary)?,
+        }
+        Ok(())
+    }
+}
+
+/// `s` with the XML special characters escaped.
+fn xml_escape(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+impl Coha {
+    /// Write one text as XML with a `<w>` element per token.
+    fn write_lancsbox_text(&self, dir: &Path, text_id: TextId, word_ids: &[WordId]) -> Result<()> {
+        let path = dir.join(format!("{}.xml", text_id.0));
+        let mut writer = BufWriter::new(File::create(&path)?);
+        writeln!(writer, r#"<?xml version="1.0" encoding="UTF-8"?>"#)?;
+        match self.sources.get(&text_id) {
+            Some(s) => writeln!(
+                writer,
+                r#"<text id="{}" genre="{}" year="{}" title="{}" author="{}">"#,
+                text_id.0,
+                s.genre,
+                s.year.0,
+                xml_escape(&s.title),
+                xml_escape(&s.author)
+            )?,
+            None => writeln!(writer, r#"<text id="{}">"#, text_id.0)?,
+        }
+        for &word_id in word_ids {
+            let Some(w) = self.word(word_id) else {
+                continue;
+            };
+            writeln!(
+                writer,
+                r#"<w lemma="{}" pos="{}">{}</w>"#,
+                xml_escape(&w.lemma),
+                xml_escape(&w.pos),
+                xml_escape(&w.word_cs)
+            )?;
+        }
+        writeln!(writer, "</text>")?;
+        writer.flush()?;
+        Ok(())
+    }
+
+    /// Write the texts as a corpus for #LancsBox, one XML file per text,
+    /// `{dir}/{decade}/{text ID}.xml`, with the text metadata as attributes
+    /// of the root element and the lemma and POS tag of each token as
+    /// attributes of its `<w>` element; optionally only the given texts.
+    /// This reads all corpus files.
+    pub fn export_lancsbox_corpus(
+        &self,
+        dir: &Path,
+        text_ids: Option<&FxHashSet<TextId>>,
+    ) -> Result<()> {
+        let lenient = self.load_options.lenient;
+        let texts = self
+            .coha_files
+            .par_iter()
+            .map(|cf| {
+                let dir = dir.join(&cf.identifier);
+                fs::create_dir_all(&dir)?;
+                debug!("{}: writing...", dir.to_string_lossy());
+                let mut current: Option<TextId> = None;
+                let mut word_ids = Vec::new();
+                let mut texts = 0;
+                let mut result = Ok(());
+                let mut flush = |text_id: TextId, word_ids: &mut Vec<WordId>| {
+                    if result.is_ok() && text_ids.is_none_or(|ids| ids.contains(&text_id)) {
+                        result = self.write_lancsbox_text(&dir, text_id, word_ids);
+                        texts += 1;
+                    }
+                    word_ids.clear();
+                };
+                cf.scan_tokens(lenient, |token| {
+                    if current.is_some_and(|id| id != token.text_id) {
+                        flush(current.unwrap(), &mut word_ids);
+                    }
+                    current = Some(token.text_id);
+                    word_ids.push(token.word_id);
+                })?;
+                if let Some(id) = current {
+                    flush(id, &mut word_ids);
+                }
+                result.map(|()| texts)
+            })
+            .collect::<Result<Vec<usize>>>()?;
+        info!(
+            "{}: {} texts",
+            dir.to_string_lossy(),
+            texts.iter().sum::<usize>()
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,168 @@
+use crate::text_files::CorpusLines;
+use crate::{tsv_err, Coha, CohaFile, TextId, Token, Word, WordId};
+use anyhow::{bail, Result};
+use log::debug;
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom};
+
+/// A hit together with its context, as in the result files.
+#[derive(Debug, Clone)]
+pub struct HitContext {
+    pub text_id: TextId,
+    /// Position of the first token of the hit within the text.
+    pub position: usize,
+    /// Number of tokens in the hit.
+    pub len: usize,
+    pub before: String,
+    pub hit: String,
+    pub after: String,
+    pub before_pos: String,
+    pub hit_pos: String,
+    pub after_pos: String,
+}
+
+/// The full token sequence of one text.
+#[derive(Debug, Clone)]
+pub struct ExtractedText {
+    pub text_id: TextId,
+    pub word_ids: Vec<WordId>,
+    /// The running text, with tokens joined as in the original orthography.
+    pub text: String,
+}
+
+impl Coha {
+    /// Look up a word in the lexicon.
+    pub fn word(&self, word_id: WordId) -> Option<&Word> {
+        self.lexicon.get(word_id.0).and_then(|w| w.as_ref())
+    }
+
+    /// The corpus file that contains the given text, based on its year.
+    fn file_for_text(&self, text_id: TextId) -> Result<&CohaFile> {
+        let Some(source) = self.sources.get(&text_id) else {
+            bail!("unknown text ID {}", text_id.0);
+        };
+        let decade = format!("{}s", source.year.0 / 10 * 10);
+        match self.coha_files.iter().find(|cf| cf.identifier == decade) {
+            Some(cf) => Ok(cf),
+            None => bail!("no corpus file for text ID {}", text_id.0),
+        }
+    }
+
+    pub(crate) fn read_text_tokens(&self, text_id: TextId) -> Result<Vec<Token>> {
+        let located = self.text_index.get().and_then(|index| {
+            let (identifier, range) = index.locate(text_id)?;
+            let cf = self
+                .coha_files
+                .iter()
+                .find(|cf| cf.identifier == identifier)?;
+            Some((cf, range))
+        });
+        let (cf, range) = match located {
+            Some((cf, range)) => (cf, Some(range)),
+            None => (self.file_for_text(text_id)?, None),
+        };
+        let path = &cf.corpus_path;
+        debug!("{}: reading...", path.to_string_lossy());
+        let prefix = format!("{}\t", text_id.0);
+        let mut br = if cf.text_files.is_empty() {
+            let mut file = File::open(path)?;
+            let reader: Box<dyn Read> = match range {
+                Some(range) => {
+                    file.seek(SeekFrom::Start(range.start))?;
+                    Box::new(file.take(range.end - range.start))
+                }
+                None => Box::new(file),
+            };
+            CorpusLines::from_reader(reader)
+        } else {
+            let text_files = cf
+                .text_files
+                .iter()
+                .filter(|(id, _)| *id == text_id)
+                .cloned()
+                .collect();
+            CorpusLines::texts(text_files, cf.attribute_columns)
+        };
+        let mut s = String::new();
+        let mut attributes = cf.attribute_parser();
+        let mut tokens: Vec<Token> = Vec::new();
+        let mut out_of_order = false;
+        while br.read_line(&mut s)? > 0 {
+            if s.starts_with(&prefix) {
+                let token = cf.parse_token(&s, &mut attributes)?;
+                if let Some(prev) = tokens.last() {
+                    if prev.token_id >= token.token_id {
+                        if !self.load_options.sort_tokens {
+                            bail!(tsv_err(path, "token IDs not increasing"));
+                        }
+                        out_of_order = true;
+                    }
+                }
+                tokens.push(token);
+            } else if !tokens.is_empty() {
+                // the tokens of a text are contiguous
+                break;
+            }
+            s.clear();
+        }
+        if out_of_order {
+            tokens.sort_by_key(|t| t.token_id);
+        }
+        if tokens.is_empty() {
+            bail!(
+                "{}: text ID {} not found",
+                path.to_string_lossy(),
+                text_id.0
+            );
+        }
+        Ok(tokens)
+    }
+
+    /// Read an entire text from its corpus file.
+    pub fn extract_text(&self, text_id: TextId) -> Result<ExtractedText> {
+        let tokens = self.read_text_tokens(text_id)?;
+        let (text, _) = self.surface_text(&tokens);
+        Ok(ExtractedText {
+            text_id,
+            word_ids: tokens.iter().map(|t| t.word_id).collect(),
+            text,
+        })
+    }
+
+    /// Re-extract a hit identified by text ID and position (as in the
+    /// "position" column of the result files) with `context` tokens of
+    /// context on both sides.
+    pub fn hit_context(
+        &self,
+        text_id: TextId,
+        position: usize,
+        len: usize,
+        context: usize,
+    ) -> Result<HitContext> {
+        let tokens = self.read_text_tokens(text_id)?;
+        if position + len > tokens.len() {
+            bail!(
+                "text ID {}: position {} out of range ({} tokens)",
+                text_id.0,
+                position,
+                tokens.len()
+            );
+        }
+        let start = position.saturating_sub(context);
+        let end = tokens.len().min(position + len + context);
+        let before = &tokens[start..position];
+        let hit = &tokens[position..position + len];
+        let after = &tokens[position + len..end];
+        Ok(HitContext {
+            text_id,
+            position,
+            len,
+            before: self.get_text(before.iter()),
+            hit: self.get_text(hit.iter()),
+            after: self.get_text(after.iter()),
+            before_pos: self.get_lemma_pos(before.iter()),
+            hit_pos: self.get_lemma_pos(hit.iter()),
+            after_pos: self.get_lemma_pos(after.iter()),
+        })
+    }
+}
@@ -0,0 +1,262 @@
+use crate::{Coha, CohaFilter, WordId};
+use anyhow::{bail, Result};
+use log::debug;
+use rustc_hash::FxHashSet;
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Read, Write};
+use std::ops::{BitAnd, BitOr, Not};
+use std::path::Path;
+
+const FILTER_MAGIC: &[u8; 8] = b"COHAFLT1";
+
+impl CohaFilter {
+    pub fn from_word_ids(word_ids: impl IntoIterator<Item = WordId>) -> CohaFilter {
+        CohaFilter::Hash(word_ids.into_iter().collect())
+    }
+
+    /// Does the filter accept this word?
+    pub fn matches(&self, word_id: WordId) -> bool {
+        match self {
+            CohaFilter::Any => true,
+            CohaFilter::Hash(x) => x.contains(&word_id),
+            CohaFilter::Complement(x) => !x.contains(&word_id),
+        }
+    }
+
+    /// Words accepted by either filter.
+    pub fn union(&self, other: &CohaFilter) -> CohaFilter {
+        use CohaFilter::*;
+        match (self, other) {
+            (Any, _) | (_, Any) => Any,
+            (Hash(a), Hash(b)) => Hash(a.union(b).copied().collect()),
+            (Hash(a), Complement(b)) | (Complement(b), Hash(a)) => {
+                Complement(b.difference(a).copied().collect())
+            }
+            (Complement(a), Complement(b)) => Complement(a.intersection(b).copied().collect()),
+        }
+    }
+
+    /// Words accepted by both filters.
+    pub fn intersection(&self, other: &CohaFilter) -> CohaFilter {
+        use CohaFilter::*;
+        match (self, other) {
+            (Any, x) | (x, Any) => x.clone(),
+            (Hash(a), Hash(b)) => Hash(a.intersection(b).copied().collect()),
+            (Hash(a), Complement(b)) | (Complement(b), Hash(a)) => {
+                Hash(a.difference(b).copied().collect())
+            }
+            (Complement(a), Complement(b)) => Complement(a.union(b).copied().collect()),
+        }
+    }
+
+    /// Words not accepted by this filter.
+    pub fn complement(&self) -> CohaFilter {
+        use CohaFilter::*;
+        match self {
+            Any => Hash(FxHashSet::default()),
+            Hash(a) => Complement(a.clone()),
+            Complement(a) => Hash(a.clone()),
+        }
+    }
+}
+
+impl CohaFilter {
+    /// Save the filter in a compact binary format. The file records a
+    /// fingerprint of the lexicon so that loading it against a different
+    /// lexicon fails.
+    pub fn save(&self, path: &Path, coha: &Coha) -> Result<()> {
+        debug!("{}: writing...", path.to_string_lossy());
+        let (kind, set) = match self {
+            CohaFilter::Any => (0u8, None),
+            CohaFilter::Hash(x) => (1u8, Some(x)),
+            CohaFilter::Complement(x) => (2u8, Some(x)),
+        };
+        let mut ids: Vec<u32> = Vec::new();
+        for id in set.into_iter().flatten() {
+            match u32::try_from(id.0) {
+                Ok(x) => ids.push(x),
+                Err(_) => bail!("word ID {} too large", id.0),
+            }
+        }
+        ids.sort();
+        let mut w = BufWriter::new(File::create(path)?);
+        w.write_all(FILTER_MAGIC)?;
+        w.write_all(&coha.lexicon_fingerprint().to_le_bytes())?;
+        w.write_all(&[kind])?;
+        w.write_all(&(ids.len() as u64).to_le_bytes())?;
+        for id in ids {
+            w.write_all(&id.to_le_bytes())?;
+        }
+        w.flush()?;
+        Ok(())
+    }
+
+    /// Load a filter saved with [`CohaFilter::save`].
+    pub fn load(path: &Path, coha: &Coha) -> Result<CohaFilter> {
+        debug!("{}: reading...", path.to_string_lossy());
+        let mut r = BufReader::new(File::open(path)?);
+        let mut magic = [0u8; 8];
+        r.read_exact(&mut magic)?;
+        if &magic != FILTER_MAGIC {
+            bail!("{}: not a filter file", path.to_string_lossy());
+        }
+        let mut buf8 = [0u8; 8];
+        r.read_exact(&mut buf8)?;
+        if u64::from_le_bytes(buf8) != coha.lexicon_fingerprint() {
+            bail!(
+                "{}: filter was saved with a different lexicon",
+                path.to_string_lossy()
+            );
+        }
+        let mut kind = [0u8; 1];
+        r.read_exact(&mut kind)?;
+        r.read_exact(&mut buf8)?;
+        let n = u64::from_le_bytes(buf8);
+        let mut set = FxHashSet::default();
+        let mut buf4 = [0u8; 4];
+        for _ in 0..n {
+            r.read_exact(&mut buf4)?;
+            set.insert(WordId(u32::from_le_bytes(buf4) as usize));
+        }
+        match kind[0] {
+            0 => Ok(CohaFilter::Any),
+            1 => Ok(CohaFilter::Hash(set)),
+            2 => Ok(CohaFilter::Complement(set)),
+            k => bail!("{}: unknown filter kind {k}", path.to_string_lossy()),
+        }
+    }
+}
+
+impl FromIterator<WordId> for CohaFilter {
+    fn from_iter<I: IntoIterator<Item = WordId>>(iter: I) -> Self {
+        CohaFilter::from_word_ids(iter)
+    }
+}
+
+impl<'a> FromIterator<&'a WordId> for CohaFilter {
+    fn from_iter<I: IntoIterator<Item = &'a WordId>>(iter: I) -> Self {
+        CohaFilter::from_word_ids(iter.into_iter().copied())
+    }
+}
+
+impl From<usize> for WordId {
+    fn from(id: usize) -> Self {
+        WordId(id)
+    }
+}
+
+impl From<WordId> for usize {
+    fn from(id: WordId) -> Self {
+        id.0
+    }
+}
+
+impl BitOr for &CohaFilter {
+    type Output = CohaFilter;
+
+    fn bitor(self, rhs: &CohaFilter) -> CohaFilter {
+        self.union(rhs)
+    }
+}
+
+impl BitOr for CohaFilter {
+    type Output = CohaFilter;
+
+    fn bitor(self, rhs: CohaFilter) -> CohaFilter {
+        self.union(&rhs)
+    }
+}
+
+impl BitAnd for &CohaFilter {
+    type Output = CohaFilter;
+
+    fn bitand(self, rhs: &CohaFilter) -> CohaFilter {
+        self.intersection(rhs)
+    }
+}
+
+impl BitAnd for CohaFilter {
+    type Output = CohaFilter;
+
+    fn bitand(self, rhs: CohaFilter) -> CohaFilter {
+        self.intersection(&rhs)
+    }
+}
+
+impl Not for &CohaFilter {
+    type Output = CohaFilter;
+
+    fn not(self) -> CohaFilter {
+        self.complement()
+    }
+}
+
+impl Not for CohaFilter {
+    type Output = CohaFilter;
+
+    fn not(self) -> CohaFilter {
+        self.complement()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Words 0..6 as a universe that is large enough to tell filters apart.
+    const WORDS: usize = 6;
+
+    fn filters() -> Vec<CohaFilter> {
+        let set = |ids: &[usize]| ids.iter().map(|&i| WordId(i)).collect();
+        vec![
+            CohaFilter::Any,
+            CohaFilter::Hash(set(&[])),
+            CohaFilter::Hash(set(&[1, 2])),
+            CohaFilter::Hash(set(&[2, 3, 4])),
+            CohaFilter::Complement(set(&[])),
+            CohaFilter::Complement(set(&[2])),
+            CohaFilter::Complement(set(&[1, 5])),
+        ]
+    }
+
+    fn accepted(filter: &CohaFilter) -> Vec<usize> {
+        (0..WORDS).filter(|&i| filter.matches(WordId(i))).collect()
+    }
+
+    #[test]
+    fn complement() {
+        for a in &filters() {
+            let x = accepted(a);
+            let expected: Vec<usize> = (0..WORDS).filter(|i| !x.contains(i)).collect();
+            assert_eq!(accepted(&!a), expected);
+            assert_eq!(accepted(&!!a), x);
+        }
+    }
+
+    #[test]
+    fn union_and_intersection() {
+        for a in &filters() {
+            for b in &filters() {
+                let (x, y) = (accepted(a), accepted(b));
+                let union: Vec<usize> = (0..WORDS)
+                    .filter(|i| x.contains(i) || y.contains(i))
+                    .collect();
+                let intersection: Vec<usize> = (0..WORDS)
+                    .filter(|i| x.contains(i) && y.contains(i))
+                    .collect();
+                assert_eq!(accepted(&(a | b)), union);
+                assert_eq!(accepted(&(a & b)), intersection);
+            }
+        }
+    }
+
+    #[test]
+    fn de_morgan() {
+        for a in &filters() {
+            for b in &filters() {
+                assert_eq!(accepted(&!(a | b)), accepted(&(!a & !b)));
+                assert_eq!(accepted(&!(a & b)), accepted(&(!a | !b)));
+            }
+        }
+    }
+}
@@ -0,0 +1,273 @@
+use crate::{Coha, CohaFile, CohaFilter, ExportFormat, TextId, Token, Word, WordId};
+use anyhow::Result;
+use log::{debug, info};
+use rayon::prelude::*;
+use rustc_hash::FxHashMap;
+use serde::Serialize;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+/// Number of tokens of each lemma in each decade, see
+/// [`Coha::lemma_frequencies`].
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct LemmaFrequencies {
+    /// Corpus file identifiers (decades, e.g. "1850s"), in order.
+    pub decades: Vec<String>,
+    /// Rows of the matrix, sorted by lemma.
+    pub rows: Vec<LemmaRow>,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct LemmaRow {
+    pub lemma: String,
+    /// Number of tokens in each decade, in the order of
+    /// [`LemmaFrequencies::decades`].
+    pub counts: Vec<u64>,
+}
+
+impl LemmaFrequencies {
+    /// Write the matrix with a row per lemma and a column per decade, plus
+    /// a total column in CSV.
+    pub fn write(&self, path: &Path, format: ExportFormat) -> Result<()> {
+        debug!("{}: writing...", path.to_string_lossy());
+        match format {
+            ExportFormat::Csv => {
+                let mut writer = csv::Writer::from_path(path)?;
+                let mut header = vec!["lemma"];
+                header.extend(self.decades.iter().map(|d| d.as_str()));
+                header.push("total");
+                writer.write_record(&header)?;
+                for row in &self.rows {
+                    let mut record = vec![row.lemma.clone()];
+                    record.extend(row.counts.iter().map(|c| c.to_string()));
+                    record.push(row.counts.iter().sum::<u64>().to_string());
+                    writer.write_record(&record)?;
+                }
+                writer.flush()?;
+            }
+            ExportFormat::Json => {
+                let mut writer = BufWriter::new(File::create(path)?);
+                serde_json::to_writer(&mut writer, self)?;
+                writer.flush()?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Write the matrix as a Parquet file in long format, with the columns
+    /// `lemma`, `decade`, and `count` and a row per nonzero count.
+    #[cfg(feature = "parquet")]
+    pub fn write_parquet(&self, path: &Path) -> Result<()> {
+        use parquet::data_type::{ByteArray, ByteArrayType, Int64Type};
+        use parquet::file::properties::WriterProperties;
+        use parquet::file::writer::SerializedFileWriter;
+        use parquet::schema::parser::parse_message_type;
+        use std::sync::Arc;
+
+        debug!("{}: writing...", path.to_string_lossy());
+        let schema = parse_message_type(
+            "message lemma_frequencies {
+                REQUIRED BYTE_ARRAY lemma (UTF8);
+                REQUIRED BYTE_ARRAY decade (UTF8);
+                REQUIRED INT64 count;
+            }",
+        )?;
+        let mut lemmas = Vec::new();
+        let mut decades = Vec::new();
+        let mut counts = Vec::new();
+        for row in &self.rows {
+            for (decade, &count) in self.decades.iter().zip(&row.counts) {
+                if count > 0 {
+                    lemmas.push(ByteArray::from(row.lemma.as_str()));
+                    decades.push(ByteArray::from(decade.as_str()));
+                    counts.push(count as i64);
+                }
+            }
+        }
+        let props = Arc::new(WriterProperties::builder().build());
+        let mut writer = SerializedFileWriter::new(File::create(path)?, Arc::new(schema), props)?;
+        let mut row_group = writer.next_row_group()?;
+        for values in [&lemmas, &decades] {
+            let mut column = row_group.next_column()?.expect("string column");
+            column
+                .typed::<ByteArrayType>()
+                .write_batch(values, None, None)?;
+            column.close()?;
+        }
+        let mut column = row_group.next_column()?.expect("count column");
+        column
+            .typed::<Int64Type>()
+            .write_batch(&counts, None, None)?;
+        column.close()?;
+        row_group.close()?;
+        writer.close()?;
+        Ok(())
+    }
+}
+
+impl CohaFile {
+    /// Call `f` for each token of this corpus file; a missing file has no
+    /// tokens. Malformed lines are skipped if `lenient`.
+    pub(crate) fn scan_tokens(&self, lenient: bool, mut f: impl FnMut(Token)) -> Result<()> {
+        let path = &self.corpus_path;
+        let Some(mut br) = self.lines()? else {
+            return Ok(());
+        };
+        debug!("{}: scanning...", path.to_string_lossy());
+        let mut s = String::new();
+        let mut attributes = self.attribute_parser();
+        while br.read_line(&mut s)? > 0 {
+            match self.parse_token(&s, &mut attributes) {
+                Ok(token) => f(token),
+                Err(_) if lenient => (),
+                Err(e) => return Err(e),
+            }
+            s.clear();
+        }
+        Ok(())
+    }
+
+    /// Call `f` with the tokens of each text of this corpus file, in token
+    /// ID order; a missing file has no texts.
+    pub(crate) fn scan_texts(
+        &self,
+        lenient: bool,
+        mut f: impl FnMut(TextId, &[Token]) -> Result<()>,
+    ) -> Result<()> {
+        let mut tokens: Vec<Token> = Vec::new();
+        let mut flush = |tokens: &mut Vec<Token>| {
+            if tokens.is_empty() {
+                return Ok(());
+            }
+            if !tokens.is_sorted_by_key(|t| t.token_id) {
+                tokens.sort_by_key(|t| t.token_id);
+            }
+            let result = f(tokens[0].text_id, tokens);
+            tokens.clear();
+            result
+        };
+        let mut result = Ok(());
+        self.scan_tokens(lenient, |token| {
+            if result.is_err() {
+                return;
+            }
+            if tokens.last().is_some_and(|t| t.text_id != token.text_id) {
+                result = flush(&mut tokens);
+            }
+            tokens.push(token);
+        })?;
+        result?;
+        flush(&mut tokens)
+    }
+
+    /// Number of tokens of each word in this corpus file.
+    fn word_frequencies(&self, lexicon_len: usize, lenient: bool) -> Result<Vec<u64>> {
+        let mut freq = vec![0; lexicon_len];
+        self.scan_tokens(lenient, |token| {
+            if let Some(f) = freq.get_mut(token.word_id.0) {
+                *f += 1;
+            }
+        })?;
+        Ok(freq)
+    }
+}
+
+impl Coha {
+    /// Count the tokens of each word in the entire corpus, for
+    /// [`Coha::frequency`] and [`Coha::explain_filter`]. This reads all
+    /// corpus files.
+    pub fn compute_frequencies(&mut self) -> Result<()> {
+        let n = self.lexicon.len();
+        let lenient = self.load_options.lenient;
+        let per_file = self
+            .coha_files
+            .par_iter()
+            .map(|cf| cf.word_frequencies(n, lenient))
+            .collect::<Result<Vec<_>>>()?;
+        let mut freq = vec![0; n];
+        for file_freq in per_file {
+            for (f, x) in freq.iter_mut().zip(file_freq) {
+                *f += x;
+            }
+        }
+        info!("word frequencies: {} tokens", freq.iter().sum::<u64>());
+        self.frequencies = Some(freq);
+        Ok(())
+    }
+
+    /// Count the tokens of each lemma of the words accepted by `filter`
+    /// (e.g. all verbs) in each decade, in one pass over the corpus files.
+    pub fn lemma_frequencies(&self, filter: &CohaFilter) -> Result<LemmaFrequencies> {
+        let mut lemmas: Vec<&str> = Vec::new();
+        let mut lemma_ids: FxHashMap<&str, usize> = FxHashMap::default();
+        let lemma_of: Vec<Option<usize>> = self
+            .lexicon
+            .iter()
+            .map(|w| {
+                let w = w.as_ref().filter(|w| filter.matches(w.word_id))?;
+                Some(*lemma_ids.entry(&w.lemma).or_insert_with(|| {
+                    lemmas.push(&w.lemma);
+                    lemmas.len() - 1
+                }))
+            })
+            .collect();
+        let lenient = self.load_options.lenient;
+        let per_file = self
+            .coha_files
+            .par_iter()
+            .map(|cf| {
+                let mut counts = vec![0; lemmas.len()];
+                cf.scan_tokens(lenient, |token| {
+                    if let Some(Some(i)) = lemma_of.get(token.word_id.0) {
+                        counts[*i] += 1;
+                    }
+                })?;
+                Ok(counts)
+            })
+            .collect::<Result<Vec<_>>>()?;
+        let mut rows: Vec<LemmaRow> = lemmas
+            .iter()
+            .enumerate()
+            .map(|(i, lemma)| LemmaRow {
+                lemma: (*lemma).to_owned(),
+                counts: per_file.iter().map(|counts| counts[i]).collect(),
+            })
+            .filter(|row| row.counts.iter().any(|&c| c > 0))
+            .collect();
+        rows.sort_by(|a, b| a.lemma.cmp(&b.lemma));
+        info!("lemma frequencies: {} lemmas", rows.len());
+        Ok(LemmaFrequencies {
+            decades: self
+                .coha_files
+                .iter()
+                .map(|cf| cf.identifier.clone())
+                .collect(),
+            rows,
+        })
+    }
+
+    /// Number of tokens of the word in the corpus, if
+    /// [`Coha::compute_frequencies`] has been called.
+    pub fn frequency(&self, word_id: WordId) -> Option<u64> {
+        self.frequencies
+            .as_ref()
+            .and_then(|f| f.get(word_id.0).copied())
+    }
+
+    /// The words that the filter matches, most frequent first if frequencies
+    /// have been computed, otherwise in lexicon order; at most `limit` words.
+    pub fn explain_filter(&self, filter: &CohaFilter, limit: usize) -> Vec<&Word> {
+        let mut words: Vec<&Word> = self
+            .lexicon
+            .iter()
+            .flatten()
+            .filter(|w| filter.matches(w.word_id))
+            .collect();
+        if self.frequencies.is_some() {
+            words.sort_by_key(|w| std::cmp::Reverse(self.frequency(w.word_id)));
+        }
+        words.truncate(limit);
+        words
+    }
+}
@@ -0,0 +1,92 @@
+use crate::{Coha, CohaFilter, Lexicon, LoadOptions, WordId};
+use itertools::Itertools;
+use log::debug;
+use rustc_hash::FxHashMap;
+
+type Index = FxHashMap<String, Vec<WordId>>;
+
+/// Hash indexes from the string fields of the lexicon to word IDs, built on
+/// first use.
+#[derive(Debug, Default)]
+pub(crate) struct LexiconIndex {
+    word_cs: Index,
+    word: Index,
+    lemma: Index,
+    pos: Index,
+}
+
+impl LexiconIndex {
+    fn build(lexicon: &Lexicon, options: &LoadOptions) -> Self {
+        debug!("building lexicon indexes...");
+        let mut index = Self::default();
+        for w in lexicon.iter().flatten() {
+            for (map, key) in [
+                (&mut index.word_cs, &w.word_cs),
+                (&mut index.word, &w.word),
+                (&mut index.lemma, &w.lemma),
+                (&mut index.pos, &w.pos),
+            ] {
+                map.entry(key.clone()).or_default().push(w.word_id);
+            }
+            // the alternatives of ambiguous tags, see Coha::pos_matches
+            let tags: Vec<&str> = if options.primary_pos_only {
+                vec![w.primary_pos()]
+            } else {
+                w.pos_tags().collect()
+            };
+            for tag in tags.into_iter().unique() {
+                if tag != w.pos {
+                    index.pos.entry(tag.to_owned()).or_default().push(w.word_id);
+                }
+            }
+        }
+        index
+    }
+}
+
+fn lookup<'a>(index: &'a Index, key: &str) -> &'a [WordId] {
+    index.get(key).map_or(&[], |ids| ids.as_slice())
+}
+
+impl Coha {
+    fn index(&self) -> &LexiconIndex {
+        self.index
+            .get_or_init(|| LexiconIndex::build(&self.lexicon, &self.load_options))
+    }
+
+    /// Words whose case-sensitive surface form is exactly `word_cs`.
+    pub fn word_ids_by_word_cs(&self, word_cs: &str) -> &[WordId] {
+        lookup(&self.index().word_cs, word_cs)
+    }
+
+    /// Words whose lowercase form is exactly `word`.
+    pub fn word_ids_by_word(&self, word: &str) -> &[WordId] {
+        lookup(&self.index().word, word)
+    }
+
+    pub fn word_ids_by_lemma(&self, lemma: &str) -> &[WordId] {
+        lookup(&self.index().lemma, lemma)
+    }
+
+    /// Words with this POS tag, including the words with an ambiguous tag
+    /// of which this is an alternative, see [`Coha::pos_matches`].
+    pub fn word_ids_by_pos(&self, pos: &str) -> &[WordId] {
+        lookup(&self.index().pos, pos)
+    }
+
+    /// Filter for words whose lowercase form is exactly `word`.
+    pub fn get_filter_word(&self, word: &str) -> CohaFilter {
+        self.word_ids_by_word(word).iter().collect()
+    }
+
+    /// Filter for words with exactly this lemma.
+    pub fn get_filter_lemma(&self, lemma: &str) -> CohaFilter {
+        self.word_ids_by_lemma(lemma).iter().collect()
+    }
+
+    /// Filter for words with exactly this POS tag, or with an ambiguous tag
+    /// of which this is an alternative.
+    pub fn get_filter_pos(&self, pos: &str) -> CohaFilter {
+        self.word_ids_by_pos(pos).iter().collect()
+    }
+}
@@ -0,0 +1,223 @@
+use crate::stats::ll_term;
+use crate::{Coha, Restrictions};
+use anyhow::Result;
+use log::info;
+use rayon::prelude::*;
+use rustc_hash::FxHashMap;
+use serde::{Deserialize, Serialize};
+use std::io::Write;
+
+/// The texts of a corpus that [`Restrictions`] allow, e.g. one genre or a
+/// range of years, as the target or reference corpus of [`keyness`].
+pub struct Subcorpus<'a> {
+    pub coha: &'a Coha,
+    pub restrictions: Restrictions,
+}
+
+/// Options of [`keyness`].
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct KeynessOptions {
+    /// Leave out words that occur fewer times than this in the target and
+    /// the reference corpus together.
+    pub min_count: u64,
+    /// Leave out words whose log-likelihood is below this, e.g. 3.84 for
+    /// p < 0.05 or 15.13 for p < 0.0001.
+    pub min_log_likelihood: f64,
+}
+
+impl Default for KeynessOptions {
+    fn default() -> Self {
+        Self {
+            min_count: 5,
+            min_log_likelihood: 3.84,
+        }
+    }
+}
+
+/// A word (lower-case form) whose frequency differs between the target and
+/// the reference corpus.
+#[derive(Debug, Clone, Serialize)]
+pub struct Keyword {
+    pub word: String,
+    /// Occurrences in the target corpus.
+    pub target: u64,
+    /// Occurrences in the reference corpus.
+    pub reference: u64,
+    pub target_per_million: f64,
+    pub reference_per_million: f64,
+    /// Log-likelihood (G²) of the difference.
+    pub log_likelihood: f64,
+    /// Effect size: the binary log of the ratio of the relative
+    /// frequencies, positive if the word is more frequent in the target
+    /// corpus. Zero frequencies are replaced with 0.5.
+    pub log_ratio: f64,
+}
+
+impl Subcorpus<'_> {
+    /// Number of tokens of each word, and the total number of tokens,
+    /// leaving out removed tokens (`@`).
+    fn word_counts(&self) -> Result<(FxHashMap<&str, u64>, u64)> {
+        let coha = self.coha;
+        let n = coha.lexicon.len();
+        let lenient = coha.load_options.lenient;
+        // one vector of counts per worker thread, not per corpus file
+        let freq = coha
+            .coha_files
+            .par_iter()
+            .try_fold(
+                || vec![0u64; n],
+                |mut freq, cf| {
+                    cf.scan_texts(lenient, |text_id, tokens| {
+                        let allowed = coha
+                            .sources
+                            .get(&text_id)
+                            .is_some_and(|s| self.restrictions.allows(s));
+                        if allowed {
+                            for t in tokens.iter().filter(|t| !coha.is_removed(t)) {
+                                if let Some(f) = freq.get_mut(t.word_id.0) {
+                                    *f += 1;
+                                }
+                            }
+                        }
+                        Ok(())
+                    })?;
+                    Ok::<_, anyhow::Error>(freq)
+                },
+            )
+            .try_reduce(
+                || vec![0u64; n],
+                |mut freq, other| {
+                    for (f, x) in freq.iter_mut().zip(other) {
+                        *f += x;
+                    }
+                    Ok(freq)
+                },
+            )?;
+        let mut counts: FxHashMap<&str, u64> = FxHashMap::default();
+        let mut total = 0;
+        for word in coha.lexicon.iter().flatten() {
+            let f = freq[word.word_id.0];
+            if f > 0 {
+                *counts.entry(&word.word).or_default() += f;
+                total += f;
+            }
+        }
+        Ok((counts, total))
+    }
+}
+
+/// Log-likelihood (G²) of a word that occurs `a` times among the `n1`
+/// tokens of the target corpus and `b` times among the `n2` tokens of the
+/// reference corpus.
+fn log_likelihood(a: f64, b: f64, n1: f64, n2: f64) -> f64 {
+    let e1 = n1 * (a + b) / (n1 + n2);
+    let e2 = n2 * (a + b) / (n1 + n2);
+    2.0 * (ll_term(a, e1) + ll_term(b, e2))
+}
+
+/// Compare the word frequencies of `target` with `reference`, which may be
+/// another part of the same corpus or a part of another corpus. Returns the
+/// words that are significantly more or less frequent in the target corpus,
+/// sorted by log-likelihood. This reads all corpus files of both corpora.
+pub fn keyness(
+    target: &Subcorpus,
+    reference: &Subcorpus,
+    options: &KeynessOptions,
+) -> Result<Vec<Keyword>> {
+    let (target_counts, n1) = target.word_counts()?;
+    let (reference_counts, n2) = reference.word_counts()?;
+    let (n1, n2) = (n1 as f64, n2 as f64);
+    let mut words: Vec<&str> = target_counts
+        .keys()
+        .chain(reference_counts.keys())
+        .copied()
+        .collect();
+    words.sort_unstable();
+    words.dedup();
+    let per_million = |f: f64, n: f64| if n > 0.0 { f * 1e6 / n } else { 0.0 };
+    let mut keywords = Vec::new();
+    for word in words {
+        let a = target_counts.get(word).copied().unwrap_or(0);
+        let b = reference_counts.get(word).copied().unwrap_or(0);
+        if a + b < options.min_count {
+            continue;
+        }
+        let (fa, fb) = (a as f64, b as f64);
+        let log_likelihood = log_likelihood(fa, fb, n1, n2);
+        if log_likelihood < options.min_log_likelihood {
+            continue;
+        }
+        let log_ratio = ((fa.max(0.5) / n1) / (fb.max(0.5) / n2)).log2();
+        keywords.push(Keyword {
+            word: word.to_owned(),
+            target: a,
+            reference: b,
+            target_per_million: per_million(fa, n1),
+            reference_per_million: per_million(fb, n2),
+            log_likelihood,
+            log_ratio,
+        });
+    }
+    keywords.sort_by(|a, b| {
+        b.log_likelihood
+            .total_cmp(&a.log_likelihood)
+            .then_with(|| a.word.cmp(&b.word))
+    });
+    info!("keyness: {} keywords", keywords.len());
+    Ok(keywords)
+}
+
+/// Write the keywords as CSV.
+pub fn write_keywords<W: Write>(writer: W, keywords: &[Keyword]) -> Result<()> {
+    let mut writer = csv::Writer::from_writer(writer);
+    writer.write_record([
+        "word",
+        "target",
+        "reference",
+        "target per million",
+        "reference per million",
+        "log-likelihood",
+        "log ratio",
+    ])?;
+    for k in keywords {
+        writer.write_record([
+            k.word.clone(),
+            k.target.to_string(),
+            k.reference.to_string(),
+            format!("{:.3}", k.target_per_million),
+            format!("{:.3}", k.reference_per_million),
+            format!("{:.3}", k.log_likelihood),
+            format!("{:.3}", k.log_ratio),
+        ])?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn log_likelihood_of_equal_corpora() {
+        // expected 7.5 in both: 2 (10 ln(10 / 7.5) + 5 ln(5 / 7.5))
+        let g2 = log_likelihood(10.0, 5.0, 1000.0, 1000.0);
+        assert!((g2 - 1.6989903679539724).abs() < 1e-9);
+    }
+
+    #[test]
+    fn log_likelihood_of_unequal_corpora() {
+        // expected 40/3 and 80/3: 2 (30 ln(30 / (40/3)) + 10 ln(10 / (80/3)))
+        let g2 = log_likelihood(30.0, 10.0, 10000.0, 20000.0);
+        assert!((g2 - 29.0392279127452).abs() < 1e-9);
+    }
+
+    #[test]
+    fn log_likelihood_of_missing_word() {
+        // expected 3 and 3: 2 (0 + 6 ln(6 / 3))
+        let g2 = log_likelihood(0.0, 6.0, 500.0, 500.0);
+        assert!((g2 - 12.0 * 2f64.ln()).abs() < 1e-9);
+        assert_eq!(log_likelihood(4.0, 8.0, 100.0, 200.0), 0.0);
+    }
+}
@@ -1,26 +1,135 @@
 use anyhow::{bail, Result};
+use attributes::{AttributeParser, AttributeValues};
+use candidates::{RemovedCounts, TextSummary};
+use checkpoint::{Checkpoint, FilePosition};
+use index::LexiconIndex;
 use itertools::Itertools;
+use limits::Limits;
+use lines::LineReader;
 use log::{debug, info, warn};
+use matcher::Span;
+use output::{clear_parts, merge_parts, write_filler_table, HitRow, OutputFiles, SearchOutput};
 use rayon::prelude::*;
 use regex::Regex;
 use rustc_hash::{FxHashMap, FxHashSet};
+use sentences::{is_structure_marker, Sentences};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 use std::fmt;
 use std::fs;
 use std::fs::File;
 use std::io::{BufRead, BufReader, Read};
 use std::path::{Path, PathBuf};
+use std::sync::{Arc, OnceLock};
+use std::time::Instant;
+mod annotate;
+mod attributes;
+mod candidates;
+mod checkpoint;
+mod collocation;
+mod compare;
+mod cooccurrence;
 mod cp437;
+mod dates;
+mod diff;
+mod duplicates;
+mod estimate;
+mod export;
+mod extract;
+mod filter;
+mod frequency;
+mod index;
+mod keyness;
+mod limits;
+mod lines;
+mod manifest;
+mod matcher;
+mod memory;
+mod metadata;
+mod normalize;
+mod output;
+mod plain_text;
+mod pos_ngrams;
+mod profile;
+mod random;
+mod readability;
+mod report;
+mod schedule;
+mod sentences;
+mod sink;
+mod spec;
+mod stats;
+mod synthetic;
+mod text_files;
+mod text_index;
+mod thinning;
+mod tokenization;
+mod trend;
+mod variants;
+mod verify;
+
+pub use annotate::{Hit, HitAnnotator};
+pub use attributes::AttributeFilter;
+pub use collocation::{
+    write_collocates, write_decade_collocates, Collocate, CollocationOptions, DecadeCollocate, Side,
+};
+pub use cooccurrence::CooccurrenceOptions;
+pub use dates::PublicationDate;
+pub use diff::{diff_results, SearchDiff};
+use duplicates::Fingerprint;
+pub use duplicates::NearDuplicates;
+pub use estimate::{write_hit_estimates, Estimate, HitEstimate};
+pub use export::{ExportFormat, SourceSummary};
+pub use extract::{ExtractedText, HitContext};
+pub use frequency::{LemmaFrequencies, LemmaRow};
+pub use keyness::{keyness, write_keywords, KeynessOptions, Keyword, Subcorpus};
+pub use limits::LimitAction;
+pub use manifest::FileChecksum;
+use manifest::Fnv1a;
+pub use memory::MemoryUsage;
+pub use metadata::Metadata;
+pub use normalize::{fold_quotes, CleanupPolicy, NormalizationForm};
+pub use plain_text::{PlainTextOptions, PlainTextSpan};
+pub use pos_ngrams::{PosNgramCount, PosNgramOptions, PosNgramProfile};
+pub use profile::{CorpusProfile, ProfileEntry};
+pub use readability::{write_length_distributions, write_length_summary, LengthStatistics};
+use report::Warnings;
+pub use report::{Burstiness, FileStats, SearchCounts, SearchReport, Warning, WarningKind};
+pub use sink::{FileSink, MemorySink, OutputSink, SinkWriter, WriterSink};
+pub use spec::{CompiledSearch, PatternKind, SearchPlan, SearchSpec, SlotField, SlotSpec};
+pub use synthetic::SyntheticCorpus;
+pub use text_index::{FileTextIndex, TextIndex, TextRange};
+pub use thinning::Thinning;
+pub use tokenization::Tokenization;
+pub use trend::{write_decade_frequencies, Bootstrap, DecadeFrequency};
+pub use variants::SpellingVariants;
+pub use verify::{write_word_count_mismatches, WordCountMismatch};
 
 const SOURCES_FILE: &str = "shared/coha_sources.utf8.txt";
 const LEXICON_FILE: &str = "shared/coha_lexicon.txt";
 const CORPUS_DIR: &str = "db";
+const CORPUS_FILE_PATTERN: &str = r"^coha_db_(\d+s)\.txt$";
 const CONTEXT: usize = 30;
+/// Number of context words in the sort key of [`HitOrder::RightContext`]
+/// and [`HitOrder::LeftContext`].
+const SORT_KEY_WORDS: usize = 3;
+const REMOVED_DIR: &str = "removed";
+const STATS_FILE: &str = "stats.json";
+const MANIFEST_FILE: &str = "manifest.json";
+const CHECKPOINT_FILE: &str = "checkpoint.jsonl";
+/// Characters that separate the alternatives of ambiguous POS tags.
+const POS_SEPARATORS: [char; 2] = ['%', '@'];
+
+/// The genre of a text. Any genre name is accepted, so that corpora with
+/// other genres than COHA can be loaded. The texts of a genre share its
+/// name, so genres are cheap to clone.
+#[derive(Debug, Clone)]
+pub struct Genre(GenreName);
 
-enum Genre {
-    Fic,
-    Mag,
-    News,
-    Nf,
+#[derive(Debug, Clone)]
+enum GenreName {
+    WellKnown(&'static str),
+    Other(Arc<str>),
 }
 
 #[derive(Debug)]
@@ -45,59 +154,176 @@ fn tsv_err(path: &Path, e: &str) -> TsvError {
 }
 
 impl Genre {
-    fn parse(s: &str) -> Option<Self> {
-        match s {
-            "FIC" => Some(Genre::Fic),
-            "MAG" => Some(Genre::Mag),
-            "NEWS" => Some(Genre::News),
-            "NF" => Some(Genre::Nf),
-            _ => None,
+    pub const FIC: Genre = Genre(GenreName::WellKnown("FIC"));
+    pub const MAG: Genre = Genre(GenreName::WellKnown("MAG"));
+    pub const NEWS: Genre = Genre(GenreName::WellKnown("NEWS"));
+    pub const NF: Genre = Genre(GenreName::WellKnown("NF"));
+    /// The genres of COHA.
+    pub const WELL_KNOWN: [Genre; 4] = [Genre::FIC, Genre::MAG, Genre::NEWS, Genre::NF];
+
+    /// The genre with the given name; `None` if the name is empty or
+    /// contains whitespace.
+    pub fn parse(s: &str) -> Option<Self> {
+        if s.is_empty() || s.contains(char::is_whitespace) {
+            return None;
+        }
+        if let Some(genre) = Genre::WELL_KNOWN.iter().find(|g| g.as_str() == s) {
+            return Some(genre.clone());
         }
+        Some(Genre(GenreName::Other(s.into())))
     }
 
-    fn parse_for_files(path: &Path, s: &str) -> Result<Self> {
+    /// [`Genre::parse`], sharing the name with the earlier texts of the
+    /// genre in `genres`.
+    fn parse_for_files(path: &Path, s: &str, genres: &mut FxHashSet<Genre>) -> Result<Self> {
+        if let Some(genre) = genres.get(s) {
+            return Ok(genre.clone());
+        }
         match Genre::parse(s) {
-            None => bail!(tsv_err(path, &format!("invalid genre: {s}"))),
-            Some(x) => Ok(x),
+            None => bail!(tsv_err(path, &format!("invalid genre: {s:?}"))),
+            Some(x) => {
+                genres.insert(x.clone());
+                Ok(x)
+            }
         }
     }
+
+    pub fn as_str(&self) -> &str {
+        match &self.0 {
+            GenreName::WellKnown(name) => name,
+            GenreName::Other(name) => name,
+        }
+    }
+
+    /// Is this one of the genres of COHA?
+    pub fn is_well_known(&self) -> bool {
+        Genre::WELL_KNOWN.contains(self)
+    }
+}
+
+impl PartialEq for Genre {
+    fn eq(&self, other: &Self) -> bool {
+        self.as_str() == other.as_str()
+    }
+}
+
+impl Eq for Genre {}
+
+impl PartialOrd for Genre {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Genre {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        self.as_str().cmp(other.as_str())
+    }
+}
+
+impl std::hash::Hash for Genre {
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        self.as_str().hash(state);
+    }
+}
+
+impl std::borrow::Borrow<str> for Genre {
+    fn borrow(&self) -> &str {
+        self.as_str()
+    }
 }
 
 impl fmt::Display for Genre {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(
-            f,
-            "{}",
-            match self {
-                Genre::Fic => "FIC",
-                Genre::Mag => "MAG",
-                Genre::News => "NEWS",
-                Genre::Nf => "NF",
-            }
-        )
+        f.write_str(self.as_str())
     }
 }
 
-#[derive(Copy, Debug, Clone, Eq, PartialEq, Ord, PartialOrd, Hash)]
-struct TextId(usize);
+impl Serialize for Genre {
+    fn serialize<S: serde::Serializer>(
+        &self,
+        serializer: S,
+    ) -> std::result::Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.as_str())
+    }
+}
 
-#[derive(Copy, Debug, Clone, Eq, PartialEq, Ord, PartialOrd, Hash)]
+impl<'de> Deserialize<'de> for Genre {
+    fn deserialize<D: serde::Deserializer<'de>>(
+        deserializer: D,
+    ) -> std::result::Result<Self, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        Genre::parse(&s).ok_or_else(|| serde::de::Error::custom(format!("invalid genre: {s:?}")))
+    }
+}
+
+#[derive(Copy, Debug, Clone, Eq, PartialEq, Ord, PartialOrd, Hash, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct TextId(pub usize);
+
+#[derive(Copy, Debug, Clone, Eq, PartialEq, Ord, PartialOrd, Hash, Serialize, Deserialize)]
+#[serde(transparent)]
 pub struct WordId(usize);
 
+impl fmt::Display for WordId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
 #[derive(Copy, Debug, Clone, Eq, PartialEq, Ord, PartialOrd, Hash)]
 struct TokenId(usize);
 
-#[derive(Copy, Debug, Clone, Eq, PartialEq, Ord, PartialOrd, Hash)]
+#[derive(Copy, Debug, Clone, Eq, PartialEq, Ord, PartialOrd, Hash, Serialize)]
+#[serde(transparent)]
 struct Year(u16);
 
+#[derive(Serialize)]
 struct Source {
     text_id: TextId,
+    words: usize,
     genre: Genre,
     year: Year,
+    /// Month or day of publication, if given in the publication information.
+    date: Option<PublicationDate>,
     title: String,
     author: String,
+    publication_info: String,
+    lc_classification: String,
 }
 
+/// Capitalization pattern of a surface form. Characters that are not
+/// letters are ignored; a form without any letters matches no pattern.
+#[derive(Copy, Debug, Clone, Eq, PartialEq)]
+pub enum Capitalization {
+    /// All letters are lowercase, e.g. "dog".
+    Lowercase,
+    /// The first letter is uppercase, e.g. "God", "USA", "I".
+    InitialCapital,
+    /// The first letter is uppercase and the rest are lowercase, e.g. "God", "I".
+    TitleCase,
+    /// All letters are uppercase, e.g. "USA", "I".
+    AllCaps,
+}
+
+impl Capitalization {
+    pub fn matches(&self, s: &str) -> bool {
+        let mut letters = s.chars().filter(|c| c.is_alphabetic()).peekable();
+        let Some(&first) = letters.peek() else {
+            return false;
+        };
+        match self {
+            Capitalization::Lowercase => letters.all(|c| !c.is_uppercase()),
+            Capitalization::InitialCapital => first.is_uppercase(),
+            Capitalization::TitleCase => {
+                first.is_uppercase() && letters.skip(1).all(|c| !c.is_uppercase())
+            }
+            Capitalization::AllCaps => letters.all(|c| !c.is_lowercase()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
 pub struct Word {
     pub word_id: WordId,
     pub word_cs: String,
@@ -106,39 +332,44 @@ pub struct Word {
     pub pos: String,
 }
 
+#[derive(Clone)]
 struct Token {
     text_id: TextId,
     token_id: TokenId,
     word_id: WordId,
+    attributes: AttributeValues,
 }
 
 impl Source {
-    fn parse_tsv(path: &Path, s: &str) -> Result<Self> {
+    fn parse_tsv(path: &Path, s: &str, genres: &mut FxHashSet<Genre>) -> Result<Self> {
         let mut fields = tsv_split(s);
         let mut next = || match fields.next() {
             None => Err(tsv_err(path, "TSV field missing")),
             Some(x) => Ok(x),
         };
         let text_id = TextId(next()?.parse()?);
-        next()?; // # words
-        let genre = Genre::parse_for_files(path, next()?)?;
+        let words = next()?.trim().parse()?;
+        let genre = Genre::parse_for_files(path, next()?, genres)?;
         let year = Year(next()?.parse()?);
         let title = next()?.to_owned();
         let author = next()?.to_owned();
+        let publication_info = fields.next().unwrap_or_default().to_owned();
+        let lc_classification = fields.next().unwrap_or_default().to_owned();
+        let date = PublicationDate::parse(&publication_info, year.0);
         Ok(Self {
             text_id,
+            words,
             genre,
             year,
+            date,
             title,
             author,
+            publication_info,
+            lc_classification,
         })
     }
 }
 
-fn word_cleanup(x: &str) -> String {
-    x.replace(|c: char| c.is_control(), "")
-}
-
 impl Word {
     fn parse_tsv(path: &Path, s: &str) -> Result<Self> {
         let mut fields = tsv_split(s);
@@ -147,8 +378,8 @@ impl Word {
             Some(x) => Ok(x),
         };
         let word_id = WordId(next()?.parse()?);
-        let word_cs = word_cleanup(next()?);
-        let word = word_cleanup(next()?);
+        let word_cs = next()?.to_owned();
+        let word = next()?.to_owned();
         let lemma = next()?.to_owned();
         let pos = next()?.to_owned();
         Ok(Self {
@@ -161,9 +392,57 @@ impl Word {
     }
 }
 
+impl Source {
+    fn citation(&self, template: &str) -> String {
+        template
+            .replace("{author}", &self.author)
+            .replace("{title}", &self.title)
+            .replace("{year}", &self.year.0.to_string())
+            .replace("{genre}", &self.genre.to_string())
+            .replace("{text_id}", &self.text_id.0.to_string())
+    }
+
+    fn clean(&mut self, options: &LoadOptions) {
+        let cleanup = &options.cleanup;
+        self.title = cleanup.clean(&self.title);
+        self.author = cleanup.clean(&self.author);
+        self.publication_info = cleanup.clean(&self.publication_info);
+        self.lc_classification = cleanup.clean(&self.lc_classification);
+    }
+}
+
+impl Word {
+    fn clean(&mut self, options: &LoadOptions) {
+        let cleanup = &options.cleanup;
+        self.word_cs = cleanup.clean_word(&self.word_cs);
+        self.word = cleanup.clean_word(&self.word);
+        self.lemma = cleanup.clean_lemma(&self.lemma, &self.word);
+        if options.normalization.is_none() && !options.fold_quotes {
+            return;
+        }
+        let n = |s: &str| normalize::normalize(s, options.normalization, options.fold_quotes);
+        self.word_cs = n(&self.word_cs);
+        self.word = n(&self.word);
+        self.lemma = n(&self.lemma);
+    }
+
+    /// The alternatives of an ambiguous (portmanteau) POS tag such as
+    /// `nn1%jj`, most likely first; an unambiguous tag is its only
+    /// alternative.
+    pub fn pos_tags(&self) -> impl Iterator<Item = &str> {
+        self.pos.split(POS_SEPARATORS).filter(|tag| !tag.is_empty())
+    }
+
+    /// The first alternative of the POS tag, see [`Word::pos_tags`].
+    pub fn primary_pos(&self) -> &str {
+        self.pos_tags().next().unwrap_or(&self.pos)
+    }
+}
+
 impl Token {
-    fn parse_tsv(path: &Path, s: &str) -> Result<Self> {
-        let mut fields = tsv_split(s);
+    /// Parse a corpus line with `attributes` extra columns after the word ID.
+    fn parse_tsv(path: &Path, s: &str, attributes: &mut AttributeParser) -> Result<Self> {
+        let mut fields = s.trim_end_matches(['\n', '\r']).splitn(4, '\t');
         let mut next = || match fields.next() {
             None => Err(tsv_err(path, "TSV field missing")),
             Some(x) => Ok(x),
@@ -171,21 +450,25 @@ impl Token {
         let text_id = TextId(next()?.parse()?);
         let token_id = TokenId(next()?.parse()?);
         let word_id = WordId(next()?.parse()?);
+        let Some(attributes) = attributes.parse(fields.next()) else {
+            bail!(tsv_err(path, "TSV field missing"));
+        };
         Ok(Self {
             text_id,
             token_id,
             word_id,
+            attributes,
         })
     }
 }
 
-fn tsv_split(s: &str) -> std::str::Split<char> {
+fn tsv_split(s: &str) -> std::str::Split<'_, char> {
     s.trim_end_matches(['\n', '\r']).split('\t')
 }
 
-fn tsv_check_header<R: Read>(
+fn tsv_check_header<R: BufRead>(
     path: &Path,
-    br: &mut BufReader<R>,
+    br: &mut LineReader<R>,
     exp_header: &[&str],
 ) -> Result<()> {
     let mut header = String::new();
@@ -206,29 +489,552 @@ type CohaFiles = Vec<CohaFile>;
 pub struct Coha {
     sources: Sources,
     lexicon: Lexicon,
+    index: OnceLock<LexiconIndex>,
+    /// Tokens per word ID, see [`Coha::compute_frequencies`].
+    frequencies: Option<Vec<u64>>,
+    /// See [`Coha::profile`].
+    profile: OnceLock<CorpusProfile>,
+    /// See [`Coha::text_index`].
+    text_index: OnceLock<TextIndex>,
     coha_files: CohaFiles,
+    removed: FxHashSet<WordId>,
+    /// Paragraph and heading markers.
+    markers: FxHashSet<WordId>,
+    root_dir: PathBuf,
+    load_options: LoadOptions,
+    load_warnings: Vec<Warning>,
+    load_peak_bytes: Option<u64>,
+    metadata: Metadata,
 }
 
 struct CohaFile {
     corpus_path: PathBuf,
     identifier: String,
+    /// The files of the texts of this decade, sorted by text ID, in the
+    /// one-file-per-text layout (see [`LoadOptions::text_file_pattern`]);
+    /// then `corpus_path` is the directory of the corpus files, for
+    /// messages.
+    text_files: Vec<(TextId, PathBuf)>,
+    /// Number of extra columns after the word ID, see
+    /// [`LoadOptions::token_attributes`].
+    attribute_columns: usize,
+}
+
+/// State shared by all corpus files of one search run.
+struct Run<'a> {
+    coha: &'a Coha,
+    result_dir: &'a Path,
+    sink: Arc<dyn OutputSink>,
+    searches: &'a [&'a CohaSearch<'a>],
+    options: &'a SearchOptions,
+    file_template: String,
+    limits: Limits,
+    checkpoint: Option<&'a Checkpoint>,
 }
 
+#[derive(Debug, Clone)]
 pub enum CohaFilter {
     Any,
     Hash(FxHashSet<WordId>),
+    /// All words except these.
+    Complement(FxHashSet<WordId>),
 }
 
+#[derive(Default)]
 pub struct CohaSearch<'a> {
     pub label: String,
     pub filter_list: Vec<&'a CohaFilter>,
+    pub restrictions: Restrictions,
+    /// Slots (0-based) whose columns are left out of the output. The slots
+    /// still have to match.
+    pub hidden_slots: Vec<usize>,
+    /// Match this regular expression against the running text of each text
+    /// instead of matching `filter_list` against the tokens. A hit consists
+    /// of all tokens that overlap the match.
+    pub surface_regex: Option<Regex>,
+    /// Match this regular expression against the tagged token stream of each
+    /// text instead of matching `filter_list` against the tokens. Each token
+    /// is encoded as `word/lemma/pos` followed by a single space, e.g.
+    /// `r"\S+/be/\S+ going/go/vvg to/to/to \S+/\S+/v.i "`. A hit consists of all
+    /// tokens that overlap the match.
+    pub tagged_regex: Option<Regex>,
+    /// Add computed columns to each hit. Only in [`ResultLayout::Full`].
+    pub annotator: Option<HitAnnotator<'a>>,
+    /// Conditions on the extra token attributes of the slots (0-based), in
+    /// addition to `filter_list`, see [`Coha::get_attribute_filter`].
+    pub attribute_filters: Vec<(usize, AttributeFilter)>,
+}
+
+impl CohaSearch<'_> {
+    fn shown_slots(&self) -> impl Iterator<Item = usize> + '_ {
+        (0..self.filter_list.len()).filter(|j| !self.hidden_slots.contains(j))
+    }
+
+    /// Does every hit consist of one token per slot?
+    fn has_slots(&self) -> bool {
+        self.surface_regex.is_none() && self.tagged_regex.is_none()
+    }
+}
+
+/// Restrict a search to a subset of texts. Empty restrictions allow all texts.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Restrictions {
+    /// Allowed genres; all genres if `None`.
+    pub genres: Option<Vec<Genre>>,
+    /// First allowed publication year.
+    pub year_from: Option<u16>,
+    /// Last allowed publication year.
+    pub year_to: Option<u16>,
+    /// First allowed publication date, e.g. `1925-03`, see
+    /// [`PublicationDate`]. Texts without a date are not allowed if this or
+    /// `date_to` is set.
+    pub date_from: Option<PublicationDate>,
+    /// Last allowed publication date; `1925-03` allows all of March.
+    pub date_to: Option<PublicationDate>,
+    /// Allowed texts; all texts if `None`.
+    #[serde(serialize_with = "serialize_sorted")]
+    pub text_ids: Option<FxHashSet<TextId>>,
+}
+
+/// Serialize a set in sorted order, so that the result is deterministic.
+fn serialize_sorted<S>(set: &Option<FxHashSet<TextId>>, serializer: S) -> Result<S::Ok, S::Error>
+where
+    S: serde::Serializer,
+{
+    set.as_ref()
+        .map(|set| set.iter().sorted().collect::<Vec<_>>())
+        .serialize(serializer)
+}
+
+impl Restrictions {
+    fn allows(&self, source: &Source) -> bool {
+        if let Some(genres) = &self.genres {
+            if !genres.contains(&source.genre) {
+                return false;
+            }
+        }
+        if self.year_from.is_some_and(|y| source.year.0 < y) {
+            return false;
+        }
+        if self.year_to.is_some_and(|y| source.year.0 > y) {
+            return false;
+        }
+        if self.date_from.is_some() || self.date_to.is_some() {
+            let Some(date) = &source.date else {
+                return false;
+            };
+            if self.date_from.is_some_and(|d| date.cmp_coarse(&d).is_lt()) {
+                return false;
+            }
+            if self.date_to.is_some_and(|d| date.cmp_coarse(&d).is_gt()) {
+                return false;
+            }
+        }
+        if let Some(text_ids) = &self.text_ids {
+            if !text_ids.contains(&source.text_id) {
+                return false;
+            }
+        }
+        true
+    }
+}
+
+/// Order in which hits are written to the result files.
+#[derive(Copy, Debug, Clone, Default, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum HitOrder {
+    /// Hits are written in corpus order.
+    #[default]
+    Corpus,
+    /// Hits are sorted by the frequency of the filler (the matched words) in
+    /// the entire search, most frequent first, also in result files that
+    /// cover several decades. All hits are kept in memory until the end of
+    /// the run. A table of filler frequencies is also written for each
+    /// search.
+    FillerFrequency,
+    /// Hits are sorted by the words right after the match (R1, R2, R3), as
+    /// in a concordancer, within each decade.
+    RightContext,
+    /// Hits are sorted by the words right before the match (L1, L2, L3),
+    /// within each decade.
+    LeftContext,
+    /// Hits are sorted by the lemmas of the matched words and then by the
+    /// word right after the match, within each decade.
+    NodeLemma,
+}
+
+/// Columns of the result files.
+#[derive(Copy, Debug, Clone, Default, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ResultLayout {
+    /// All metadata, context, and annotation columns.
+    #[default]
+    Full,
+    /// Concordance lines as in AntConc's KWIC view: the left context, the
+    /// hit, the right context, and the file name `{text ID}.txt`. Written
+    /// as tab-separated `.txt` files without a header by default.
+    AntConc,
+    /// The columns of CQPweb's tab-delimited concordance download: text ID,
+    /// context before, query item, and context after. Written as `.tsv`
+    /// files by default.
+    CqpWeb,
+    /// The columns of #LancsBox's KWIC export: file (text ID), left
+    /// context, node, and right context.
+    LancsBox,
+}
+
+impl ResultLayout {
+    /// Extension of the result files if no file template is given.
+    fn default_extension(&self) -> &'static str {
+        match self {
+            ResultLayout::Full | ResultLayout::LancsBox => "csv",
+            ResultLayout::AntConc => "txt",
+            ResultLayout::CqpWeb => "tsv",
+        }
+    }
+}
+
+/// Publication year ranges for splitting the result files.
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum YearBins {
+    /// One bin per year, e.g. "1850".
+    Year,
+    /// Bins of this many years starting at multiples of it, e.g.
+    /// "1850-1874" for 25.
+    Width(u16),
+    /// Bins starting at these years, in increasing order, e.g. `[1820, 1870]`
+    /// gives "-1819", "1820-1869", and "1870-".
+    Starts(Vec<u16>),
+}
+
+impl YearBins {
+    fn check(&self) -> Result<()> {
+        match self {
+            YearBins::Year => (),
+            YearBins::Width(0) => bail!("year bin width must be positive"),
+            YearBins::Width(_) => (),
+            YearBins::Starts(starts) => {
+                if starts.is_empty() || !starts.windows(2).all(|w| w[0] < w[1]) {
+                    bail!("year bin starts must be non-empty and increasing");
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// The label of the bin that contains `year`.
+    pub fn label(&self, year: u16) -> String {
+        match self {
+            YearBins::Year => year.to_string(),
+            YearBins::Width(w) => {
+                let start = year / w * w;
+                format!("{}-{}", start, start + (w - 1))
+            }
+            YearBins::Starts(starts) => {
+                let i = starts.partition_point(|&s| s <= year);
+                let from = if i == 0 {
+                    String::new()
+                } else {
+                    starts[i - 1].to_string()
+                };
+                let to = match starts.get(i) {
+                    Some(&next) => (next - 1).to_string(),
+                    None => String::new(),
+                };
+                format!("{from}-{to}")
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct LoadOptions {
+    /// Skip malformed lines in the sources, lexicon, and corpus files
+    /// instead of failing. Skipped lines are reported as warnings.
+    pub lenient: bool,
+    /// Cleanup of control characters, escapes, and missing lemmas in the
+    /// lexicon, and of control characters and escapes in the sources table.
+    /// By default only control characters are removed from word forms; see
+    /// [`CleanupPolicy::Strip`] for the rest.
+    pub cleanup: CleanupPolicy,
+    /// Unicode normalization applied to the word forms and lemmas of the
+    /// lexicon.
+    pub normalization: Option<NormalizationForm>,
+    /// Replace typographic quotes with ASCII quotes in the lexicon.
+    pub fold_quotes: bool,
+    /// Sort the tokens of each text by token ID if they are not in
+    /// increasing order, instead of failing. Reordered tokens are reported
+    /// as warnings.
+    pub sort_tokens: bool,
+    /// Regular expression for the names of the corpus files in `db` and its
+    /// subdirectories, with a capture group for the identifier of the file,
+    /// e.g. `^(\d+s)\.tsv$`. The identifier should be the decade, e.g.
+    /// "1850s". Files with other names are ignored. Defaults to
+    /// `^coha_db_(\d+s)\.txt$`.
+    pub corpus_file_pattern: Option<String>,
+    /// Regular expression for the names of corpus files with one text each,
+    /// with a capture group for the text ID, e.g. `^text_(\d+)\.txt$`.
+    /// Each line of such a file has the token ID and the word ID, with or
+    /// without the text ID before them; the text ID of the file name is
+    /// used in any case. The texts are grouped into decades by their year
+    /// in the sources table, and files of texts that are not in the sources
+    /// table are ignored. Replaces `corpus_file_pattern`. Random access with
+    /// [`Coha::text_index`] and [`SearchOptions::two_pass`] are not
+    /// available in this layout.
+    pub text_file_pattern: Option<String>,
+    /// POS filters only consider the first alternative of ambiguous POS
+    /// tags (see [`Word::pos_tags`]) instead of any alternative.
+    pub primary_pos_only: bool,
+    /// Names of extra columns of the corpus files after the word ID, e.g. a
+    /// layer of semantic tags added to a copy of COHA. Each token gets the
+    /// values of these columns as attributes that searches can filter on
+    /// (see [`Coha::get_attribute_filter`]) and that
+    /// [`SearchOptions::attribute_columns`] adds to the results. Lines
+    /// without these columns are malformed; further columns are ignored,
+    /// except in the one-file-per-text layout, where the number of columns
+    /// tells whether a line starts with the text ID.
+    pub token_attributes: Vec<String>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SearchOptions {
+    pub order: HitOrder,
+    /// Leave out the "@" tokens that mark removed material from the context
+    /// columns.
+    pub skip_removed_context: bool,
+    /// Leave out paragraph and heading markers (e.g. `<p>`) from the context
+    /// columns.
+    pub skip_marker_context: bool,
+    /// Write a table per corpus file with the amount of removed material in
+    /// each text.
+    pub report_removed: bool,
+    /// Only count hits; no concordance files are written. The counts are
+    /// written to `{label}-counts.csv` and `{label}-text-counts.csv`.
+    pub count_only: bool,
+    /// Split each result file into numbered chunks of at most this many rows
+    /// (plus the header), named `{label}-{decade}-001.csv` etc.
+    pub max_rows_per_file: Option<usize>,
+    /// Add a "hit ID" column before all other columns with an identifier of
+    /// the hit that does not depend on the order of the hits, see
+    /// [`hit_id`] and [`diff_results`]. Only in [`ResultLayout::Full`].
+    pub hit_id_column: bool,
+    /// Add a "citation" column formatted with this template. The
+    /// placeholders `{author}`, `{title}`, `{year}`, `{genre}`, and
+    /// `{text_id}` are replaced with the values of the text, e.g.
+    /// `"{author}, {title} ({year}), COHA text {text_id}"`.
+    pub citation_template: Option<String>,
+    /// Record each completed corpus file in `checkpoint.jsonl` in the result
+    /// directory. If the file already exists from an interrupted run with
+    /// the same searches and options, the corpus files listed in it are not
+    /// searched again. The progress within a corpus file is recorded too,
+    /// see `checkpoint_interval`, and its search continues after the last
+    /// recorded text if the [`OutputSink`] can resume files, as
+    /// [`FileSink`] can. Only supported with [`HitOrder::Corpus`] and
+    /// without `near_duplicates`.
+    pub checkpoint: bool,
+    /// With `checkpoint`, record the progress within a corpus file every
+    /// this many texts; by default every 1000 texts. Not used with
+    /// `two_pass` or with one file per text.
+    pub checkpoint_interval: Option<usize>,
+    /// Maximum number of hits per search; further hits are left out.
+    pub max_hits: Option<usize>,
+    /// Stop after this many hits per search, e.g. for a quick look at the
+    /// first few hundred examples: further hits are left out, and no more
+    /// texts are read once every search has this many hits. The cutoff is
+    /// recorded as a [`WarningKind::LimitReached`] warning and in
+    /// [`FileStats::stopped_early`].
+    pub stop_after_hits: Option<usize>,
+    /// Maximum wall-clock time of the entire run in seconds; the texts that
+    /// are not reached in time are not searched.
+    pub time_limit_secs: Option<u64>,
+    /// What to do when `max_hits` or `time_limit_secs` is exceeded.
+    pub on_limit: LimitAction,
+    /// Memory budget of the entire process in bytes. The number of corpus
+    /// files searched concurrently is reduced so that the memory already in
+    /// use plus [`Coha::worker_memory_estimate`] per worker fits within the
+    /// budget.
+    pub memory_budget_bytes: Option<u64>,
+    /// Write a separate result file per genre, `{label}-{decade}-{genre}.csv`.
+    pub split_by_genre: bool,
+    /// Write one result file for all decades, `{label}.csv` (or
+    /// `{label}-{genre}.csv`), instead of one per decade. The hits are in
+    /// decade order.
+    pub merge_decades: bool,
+    /// Write a separate result file per publication year or range of years,
+    /// `{label}-{decade}-{bin}.csv`, based on the year in the sources table.
+    /// Combine with `merge_decades` for bins that span several decades.
+    pub split_by_year: Option<YearBins>,
+    /// Path of the result files relative to the result directory, e.g.
+    /// `"{label}/{genre}/{decade}.csv"` or `"{label}-{year_bin}.jsonl"`.
+    /// The placeholders `{label}`, `{decade}`, `{genre}`, `{year}`, and
+    /// `{year_bin}` (as in `split_by_year`, or the year if not set) are
+    /// replaced with the values of each hit. Files that span several decades
+    /// are in decade order. The extension `.csv`, `.jsonl`, `.tsv`, or
+    /// `.txt` (tab-separated without a header) selects the format.
+    /// Replaces the file names given by `split_by_genre`, `split_by_year`,
+    /// and `merge_decades`.
+    pub file_template: Option<String>,
+    /// Write the context, match, and wordCS columns ready for publication:
+    /// typographic quotes become ASCII quotes, "--" becomes an em dash, and
+    /// punctuation is attached to the neighbouring words, e.g. "do n't ."
+    /// becomes "don't.".
+    pub tidy_text: bool,
+    pub layout: ResultLayout,
+    /// Add a "line" column with the context and the match as one string,
+    /// the match between these markers, e.g. `("<<", ">>")` gives
+    /// "she was <<going to>> see". Only in [`ResultLayout::Full`].
+    pub line_markers: Option<(String, String)>,
+    /// Instead of 30 tokens on each side of the match, take about this many
+    /// characters of context on each side, cut at the token boundary
+    /// closest to the given width.
+    pub context_chars: Option<usize>,
+    /// Add "sentence" and "sentence position" columns with the number of
+    /// the sentence of the hit within its text and the position of the hit
+    /// within the sentence, both starting from 1. Sentences are delimited by
+    /// sentence-final punctuation and paragraph and heading markers. Only in
+    /// [`ResultLayout::Full`].
+    pub sentence_numbers: bool,
+    /// Add a "paragraph" column with the number of the paragraph of the hit
+    /// within its text, starting from 1. Paragraphs are delimited by
+    /// paragraph and heading markers. Only in [`ResultLayout::Full`].
+    pub paragraph_numbers: bool,
+    /// Add "L1", "R1", and "node lemma" columns with the word right before
+    /// the match, the word right after the match, and the lemmas of the
+    /// matched words, for sorting the results, e.g. in a spreadsheet. Only
+    /// in [`ResultLayout::Full`].
+    pub sort_key_columns: bool,
+    /// Add a "date" column after "year" with the month or day of
+    /// publication, if given in the publication information (see
+    /// [`PublicationDate`]). Only in [`ResultLayout::Full`].
+    pub date_column: bool,
+    /// Add a column per slot and token attribute (see
+    /// [`LoadOptions::token_attributes`]) after the "pos" column of the slot,
+    /// e.g. "sem 1", or one column per attribute with the values of all
+    /// matched tokens for searches without slots, e.g. "match_sem". Only in
+    /// [`ResultLayout::Full`].
+    pub attribute_columns: bool,
+    /// Find hits in different texts with nearly the same context, e.g. in
+    /// reprints, and add "duplicate cluster" and "cluster size" columns
+    /// (only in [`ResultLayout::Full`]); hits without near-duplicates have
+    /// an empty cluster. All hits are kept in memory until the end of the
+    /// run. The hit counts include the near-duplicates.
+    pub near_duplicates: Option<NearDuplicates>,
+    /// Measure how the hits cluster within each text, see [`Burstiness`],
+    /// and write the results to `{label}/{label}-burstiness.csv`.
+    pub burstiness: bool,
+    /// Keep only a sample of the hits, proportional to the length of each
+    /// text, see [`Thinning`]. The hit counts and `max_hits` only include
+    /// the kept hits; the number of hits left out is in
+    /// [`FileStats::thinned`].
+    pub thinning: Option<Thinning>,
+    /// Search each corpus file in two passes: first find the texts that
+    /// contain at least one word of the most selective slot of some search,
+    /// reading only the word IDs, and then read and match only those texts.
+    /// Faster when the hits are concentrated in a small fraction of the
+    /// texts. Regular expression searches and searches without a word list
+    /// in any slot read all texts in the second pass.
+    pub two_pass: bool,
+    /// Size of the write buffer of each result file in bytes, 256 KiB by
+    /// default and at least 4 KiB. Larger buffers mean fewer and larger
+    /// writes, which helps when many result files are written in parallel,
+    /// e.g. on a network file system.
+    pub write_buffer_bytes: Option<usize>,
+}
+
+const DEFAULT_WRITE_BUFFER_BYTES: usize = 256 << 10;
+const MIN_WRITE_BUFFER_BYTES: usize = 4 << 10;
+const DEFAULT_CHECKPOINT_INTERVAL: usize = 1000;
+
+const TEMPLATE_PLACEHOLDERS: &[&str] = &["{label}", "{decade}", "{genre}", "{year}", "{year_bin}"];
+
+impl SearchOptions {
+    fn checkpoint_interval(&self) -> usize {
+        self.checkpoint_interval
+            .unwrap_or(DEFAULT_CHECKPOINT_INTERVAL)
+    }
+
+    fn buffer_bytes(&self) -> usize {
+        self.write_buffer_bytes
+            .unwrap_or(DEFAULT_WRITE_BUFFER_BYTES)
+    }
+
+    /// The template of the result file paths, by default
+    /// `{label}/{label}-{decade}.csv` with the parts given by the split
+    /// options.
+    fn file_template(&self) -> String {
+        if let Some(template) = &self.file_template {
+            return template.clone();
+        }
+        let mut template = "{label}/{label}".to_owned();
+        if !self.merge_decades {
+            template.push_str("-{decade}");
+        }
+        if self.split_by_genre {
+            template.push_str("-{genre}");
+        }
+        if self.split_by_year.is_some() {
+            template.push_str("-{year_bin}");
+        }
+        template.push('.');
+        template.push_str(self.layout.default_extension());
+        template
+    }
+
+    fn check_file_template(&self, searches: &[&CohaSearch]) -> Result<()> {
+        let template = self.file_template();
+        let mut rest = template.clone();
+        for placeholder in TEMPLATE_PLACEHOLDERS {
+            rest = rest.replace(placeholder, "");
+        }
+        if rest.contains(['{', '}']) {
+            bail!("{template}: unknown placeholder in file template");
+        }
+        if Path::new(&template).is_absolute() || template.split('/').any(|p| p == "..") {
+            bail!("{template}: file template must be relative to the result directory");
+        }
+        if searches.len() > 1 && !template.contains("{label}") {
+            bail!("{template}: file template must contain {{label}} for several searches");
+        }
+        // result file paths are written on one line by WriterSink
+        if template.contains(['\t', '\n', '\r']) {
+            bail!("{template:?}: file template contains a tab or a line break");
+        }
+        for search in searches {
+            if search.label.contains(['\t', '\n', '\r']) {
+                bail!("{:?}: label contains a tab or a line break", search.label);
+            }
+        }
+        output::FileFormat::from_path(&template)?;
+        Ok(())
+    }
 }
 
-fn read_sources(root_dir: &Path) -> Result<Sources> {
+/// Report a malformed line: an error in strict mode, a warning otherwise.
+fn malformed_line(
+    path: &Path,
+    lenient: bool,
+    warnings: &mut Warnings,
+    e: anyhow::Error,
+) -> Result<()> {
+    if !lenient {
+        return Err(e);
+    }
+    debug!("{}: skipping malformed line: {}", path.to_string_lossy(), e);
+    warnings.add(
+        WarningKind::MalformedLine,
+        format!("{}: malformed line", path.to_string_lossy()),
+    );
+    Ok(())
+}
+
+fn read_sources(root_dir: &Path, options: &LoadOptions) -> Result<(Sources, Warnings)> {
     let path = root_dir.join(SOURCES_FILE);
     debug!("{}: reading...", path.to_string_lossy());
     let file = File::open(path.clone())?;
-    let mut br = BufReader::new(file);
+    let mut br = LineReader::new(BufReader::new(file));
 
     let header = &[
         "textID",
@@ -244,14 +1050,21 @@ fn read_sources(root_dir: &Path) -> Result<Sources> {
     tsv_check_header(&path, &mut br, header)?;
 
     let mut sources = FxHashMap::default();
+    let mut genres = FxHashSet::default();
+    let mut warnings = Warnings::default();
     let mut s = String::new();
     while br.read_line(&mut s)? > 0 {
-        let source = Source::parse_tsv(&path, &s)?;
-        sources.insert(source.text_id, source);
+        match Source::parse_tsv(&path, &s, &mut genres) {
+            Ok(mut source) => {
+                source.clean(options);
+                sources.insert(source.text_id, source);
+            }
+            Err(e) => malformed_line(&path, options.lenient, &mut warnings, e)?,
+        }
         s.clear();
     }
     info!("{}: {} sources", path.to_string_lossy(), sources.len());
-    Ok(sources)
+    Ok((sources, warnings))
 }
 
 fn read_cp437_file_to_string(path: &Path) -> Result<String> {
@@ -259,17 +1072,17 @@ fn read_cp437_file_to_string(path: &Path) -> Result<String> {
     let mut bytes = Vec::new();
     file.read_to_end(&mut bytes)?;
     let mut string = String::new();
-    for b in bytes {
+    for &b in lines::strip_bom(&bytes) {
         string.push(cp437::CP437[b as usize]);
     }
     Ok(string)
 }
 
-fn read_lexicon(root_dir: &Path) -> Result<Lexicon> {
+fn read_lexicon(root_dir: &Path, options: &LoadOptions) -> Result<(Lexicon, Warnings)> {
     let path = root_dir.join(LEXICON_FILE);
     debug!("{}: reading...", path.to_string_lossy());
     let file_string = read_cp437_file_to_string(&path)?;
-    let mut br = BufReader::new(file_string.as_bytes());
+    let mut br = LineReader::new(file_string.as_bytes());
 
     let header = &["wID", "wordCS", "word", "lemma", "PoS"];
     tsv_check_header(&path, &mut br, header)?;
@@ -280,11 +1093,23 @@ fn read_lexicon(root_dir: &Path) -> Result<Lexicon> {
 
     let mut lexicon = Vec::new();
     let mut lexicon_padding: usize = 0;
+    let mut warnings = Warnings::default();
     let mut s = String::new();
     while br.read_line(&mut s)? > 0 {
-        let word = Word::parse_tsv(&path, &s)?;
+        let mut word = match Word::parse_tsv(&path, &s) {
+            Ok(word) => word,
+            Err(e) => {
+                malformed_line(&path, options.lenient, &mut warnings, e)?;
+                s.clear();
+                continue;
+            }
+        };
+        word.clean(options);
         if word.word_id.0 < lexicon.len() {
-            bail!(tsv_err(&path, "word IDs not increasing"));
+            let e = tsv_err(&path, "word IDs not increasing").into();
+            malformed_line(&path, options.lenient, &mut warnings, e)?;
+            s.clear();
+            continue;
         }
         while word.word_id.0 > lexicon.len() {
             lexicon_padding += 1;
@@ -300,63 +1125,249 @@ fn read_lexicon(root_dir: &Path) -> Result<Lexicon> {
         lexicon.len() - lexicon_padding,
         lexicon_padding
     );
-    Ok(lexicon)
+    Ok((lexicon, warnings))
 }
 
-fn read_corpus(root_dir: &Path) -> Result<CohaFiles> {
+fn read_corpus(
+    root_dir: &Path,
+    options: &LoadOptions,
+    sources: &Sources,
+) -> Result<(CohaFiles, Warnings)> {
     let path = root_dir.join(CORPUS_DIR);
     debug!("{}: reading...", path.to_string_lossy());
-    let mut corpus_paths = Vec::new();
-    for subdir in path.read_dir()? {
-        let subdir = subdir?.path();
-        if subdir.is_dir() {
-            for file in subdir.read_dir()? {
+    let pattern = match (&options.text_file_pattern, &options.corpus_file_pattern) {
+        (Some(pattern), _) | (None, Some(pattern)) => pattern.as_str(),
+        (None, None) => CORPUS_FILE_PATTERN,
+    };
+    let re = Regex::new(pattern)?;
+    if re.captures_len() < 2 {
+        bail!("corpus file pattern {pattern:?} has no capture group");
+    }
+    let mut corpus_files = Vec::new();
+    let mut add_file = |file: PathBuf| match CohaFile::new(file.clone(), &re) {
+        None => debug!("{}: skipping", file.to_string_lossy()),
+        Some(cf) => corpus_files.push(cf),
+    };
+    for entry in path.read_dir()? {
+        let entry = entry?.path();
+        if entry.is_dir() {
+            for file in entry.read_dir()? {
                 let file = file?.path();
-                let ext = file.extension();
-                match ext {
-                    None => continue,
-                    Some(s) => {
-                        if s != "txt" {
-                            continue;
-                        }
-                    }
-                };
-                corpus_paths.push(file);
+                if file.is_file() {
+                    add_file(file);
+                }
             }
+        } else {
+            add_file(entry);
+        }
+    }
+    let mut warnings = Warnings::default();
+    if options.text_file_pattern.is_some() {
+        corpus_files = group_text_files(&path, corpus_files, sources, &mut warnings)?;
+    }
+    corpus_files.sort_by(|a, b| a.corpus_path.cmp(&b.corpus_path));
+    for cf in &mut corpus_files {
+        cf.attribute_columns = options.token_attributes.len();
+    }
+    for (i, cf) in corpus_files.iter().enumerate() {
+        if let Some(prev) = corpus_files[..i]
+            .iter()
+            .find(|p| p.identifier == cf.identifier)
+        {
+            bail!(
+                "{} and {}: same identifier {}",
+                prev.corpus_path.to_string_lossy(),
+                cf.corpus_path.to_string_lossy(),
+                cf.identifier
+            );
         }
     }
-    corpus_paths.sort();
     info!(
         "{}: {} corpus files",
         path.to_string_lossy(),
-        corpus_paths.len()
+        corpus_files.len()
     );
-    corpus_paths.into_iter().map(CohaFile::new).collect()
+    Ok((corpus_files, warnings))
+}
+
+/// Group files with one text each, whose identifiers are text IDs, into
+/// one corpus file per decade.
+fn group_text_files(
+    dir: &Path,
+    files: CohaFiles,
+    sources: &Sources,
+    warnings: &mut Warnings,
+) -> Result<CohaFiles> {
+    let mut decades: BTreeMap<String, Vec<(TextId, PathBuf)>> = BTreeMap::new();
+    for cf in files {
+        let Ok(text_id) = cf.identifier.parse().map(TextId) else {
+            bail!(
+                "{}: text ID {:?} is not a number",
+                cf.corpus_path.to_string_lossy(),
+                cf.identifier
+            );
+        };
+        let Some(source) = sources.get(&text_id) else {
+            let message = format!(
+                "{}: unknown text ID {}",
+                cf.corpus_path.to_string_lossy(),
+                text_id.0
+            );
+            warn!("{}", message);
+            warnings.add(WarningKind::UnknownTextId, message);
+            continue;
+        };
+        let decade = format!("{}s", source.year.0 / 10 * 10);
+        decades
+            .entry(decade)
+            .or_default()
+            .push((text_id, cf.corpus_path));
+    }
+    let mut corpus_files = Vec::new();
+    for (decade, mut text_files) in decades {
+        text_files.sort();
+        if let Some(pair) = text_files.windows(2).find(|p| p[0].0 == p[1].0) {
+            bail!(
+                "{} and {}: same text ID {}",
+                pair[0].1.to_string_lossy(),
+                pair[1].1.to_string_lossy(),
+                pair[0].0 .0
+            );
+        }
+        corpus_files.push(CohaFile {
+            corpus_path: dir.join(&decade),
+            identifier: decade,
+            text_files,
+            attribute_columns: 0,
+        });
+    }
+    Ok(corpus_files)
+}
+
+/// A deterministic identifier of a hit, `{text ID}:{position}:{label}`.
+pub fn hit_id(text_id: TextId, position: usize, label: &str) -> String {
+    format!("{}:{}:{}", text_id.0, position, label)
+}
+
+/// COHA replaces removed copyrighted material with runs of "@" tokens.
+fn is_removal_marker(word: &Word) -> bool {
+    !word.word_cs.is_empty() && word.word_cs.chars().all(|c| c == '@')
+}
+
+/// Paragraph and heading markers, see [`sentences::is_structure_marker`].
+fn marker_word_ids(lexicon: &Lexicon) -> FxHashSet<WordId> {
+    lexicon
+        .iter()
+        .flatten()
+        .filter(|w| is_structure_marker(&w.word_cs))
+        .map(|w| w.word_id)
+        .collect()
+}
+
+fn removed_word_ids(lexicon: &Lexicon) -> FxHashSet<WordId> {
+    lexicon
+        .iter()
+        .flatten()
+        .filter(|w| is_removal_marker(w))
+        .map(|w| w.word_id)
+        .collect()
 }
 
 impl Coha {
     pub fn load(root_dir: &Path) -> Result<Self> {
-        let ((c, s), l) = rayon::join(
-            || (read_corpus(root_dir), read_sources(root_dir)),
-            || read_lexicon(root_dir),
+        Self::load_with(root_dir, &LoadOptions::default())
+    }
+
+    pub fn load_with(root_dir: &Path, options: &LoadOptions) -> Result<Self> {
+        let (s, l) = rayon::join(
+            || read_sources(root_dir, options),
+            || read_lexicon(root_dir, options),
         );
-        let c = c?;
-        let s = s?;
-        let l = l?;
-        Ok(Self {
+        let (s, mut warnings) = s?;
+        let (l, w) = l?;
+        warnings.merge(w);
+        let (c, w) = read_corpus(root_dir, options, &s)?;
+        warnings.merge(w);
+        let load_warnings = warnings.into_vec();
+        for w in &load_warnings {
+            warn!("{} ({} times)", w.message, w.count);
+        }
+        let removed = removed_word_ids(&l);
+        let markers = marker_word_ids(&l);
+        let mut coha = Self {
             sources: s,
             lexicon: l,
+            index: OnceLock::new(),
+            frequencies: None,
+            profile: OnceLock::new(),
+            text_index: OnceLock::new(),
             coha_files: c,
-        })
+            removed,
+            markers,
+            root_dir: root_dir.to_owned(),
+            load_options: options.clone(),
+            load_warnings,
+            load_peak_bytes: memory::peak_rss(),
+            metadata: Metadata::default(),
+        };
+        if let Some(peak) = coha.load_peak_bytes {
+            info!("peak memory while loading: {} MiB", peak >> 20);
+        }
+        for decade in coha.missing_decades() {
+            let message = format!("no corpus file for decade {decade}");
+            warn!("{}", message);
+            coha.load_warnings.push(Warning {
+                kind: WarningKind::MissingDecade,
+                message,
+                count: 1,
+            });
+        }
+        Ok(coha)
+    }
+
+    /// Decades (e.g. "1850s") that have texts in the sources table but no
+    /// corpus file.
+    pub fn missing_decades(&self) -> Vec<String> {
+        let present: FxHashSet<&str> = self
+            .coha_files
+            .iter()
+            .map(|cf| cf.identifier.as_str())
+            .collect();
+        self.sources
+            .values()
+            .map(|source| format!("{}s", source.year.0 / 10 * 10))
+            .filter(|decade| !present.contains(decade.as_str()))
+            .sorted()
+            .dedup()
+            .collect()
+    }
+
+    /// Problems encountered while loading the corpus.
+    pub fn load_warnings(&self) -> &[Warning] {
+        &self.load_warnings
+    }
+
+    /// Does the POS tag of the word satisfy `p`: any alternative of an
+    /// ambiguous tag, or only the first one with
+    /// [`LoadOptions::primary_pos_only`]. The entire tag also counts.
+    pub fn pos_matches(&self, w: &Word, p: impl Fn(&str) -> bool) -> bool {
+        if p(&w.pos) {
+            return true;
+        }
+        if self.load_options.primary_pos_only {
+            p(w.primary_pos())
+        } else {
+            w.pos_tags().any(p)
+        }
     }
 
     pub fn get_filter<P>(&self, p: P) -> CohaFilter
     where
-        P: Fn(&Word) -> bool,
+        P: Fn(&Word) -> bool + Sync,
     {
         CohaFilter::Hash(
             self.lexicon
-                .iter()
+                .par_iter()
                 .filter_map(|w| match w {
                     None => None,
                     Some(w) => {
@@ -371,7 +1382,45 @@ impl Coha {
         )
     }
 
+    /// Filter for words whose case-sensitive surface form is exactly `word_cs`.
+    pub fn get_filter_surface_cs(&self, word_cs: &str) -> CohaFilter {
+        self.word_ids_by_word_cs(word_cs).iter().collect()
+    }
+
+    /// Filter for words whose case-sensitive surface form follows the given
+    /// capitalization pattern.
+    pub fn get_filter_capitalization(&self, cap: Capitalization) -> CohaFilter {
+        self.get_filter(|w| cap.matches(&w.word_cs))
+    }
+
     pub fn search(&self, result_dir: &Path, searches: &[&CohaSearch]) -> Result<()> {
+        self.search_with(result_dir, searches, &SearchOptions::default())?;
+        Ok(())
+    }
+
+    pub fn search_with(
+        &self,
+        result_dir: &Path,
+        searches: &[&CohaSearch],
+        options: &SearchOptions,
+    ) -> Result<SearchReport> {
+        let sink = Arc::new(FileSink::new(result_dir));
+        self.search_to(result_dir, searches, options, sink)
+    }
+
+    /// Search as [`Coha::search_with`], but write the result files to
+    /// `sink`, e.g. to stream them over a socket or into object storage
+    /// instead of local files. The other files of the run, such as the
+    /// counts, statistics, manifest, and checkpoints, are still written to
+    /// `result_dir`, as are the parts of result files that span several
+    /// corpus files until they are merged.
+    pub fn search_to(
+        &self,
+        result_dir: &Path,
+        searches: &[&CohaSearch],
+        options: &SearchOptions,
+        sink: Arc<dyn OutputSink>,
+    ) -> Result<SearchReport> {
         for search in searches {
             let filter_sizes = search
                 .filter_list
@@ -379,21 +1428,234 @@ impl Coha {
                 .map(|f| match f {
                     CohaFilter::Any => "∞".to_owned(),
                     CohaFilter::Hash(x) => x.len().to_string(),
+                    CohaFilter::Complement(x) => format!("∞ - {}", x.len()),
                 })
                 .join(", ");
             info!("search {}: filter sizes: {}", search.label, filter_sizes);
             fs::create_dir_all(result_dir.join(&search.label))?;
         }
-        let mut results = Vec::new();
-        results.par_extend(
-            self.coha_files
-                .par_iter()
-                .map(|cf| cf.search(self, result_dir, searches)),
-        );
+        if options.report_removed {
+            fs::create_dir_all(result_dir.join(REMOVED_DIR))?;
+        }
+        if let Some(bins) = &options.split_by_year {
+            bins.check()?;
+        }
+        options.check_file_template(searches)?;
+        if let Some(thinning) = &options.thinning {
+            thinning.check()?;
+        }
+        if options
+            .write_buffer_bytes
+            .is_some_and(|b| b < MIN_WRITE_BUFFER_BYTES)
+        {
+            bail!("write buffer size must be at least {MIN_WRITE_BUFFER_BYTES} bytes");
+        }
+        if options.max_rows_per_file == Some(0) {
+            bail!("maximum rows per file must be positive");
+        }
+        if options.checkpoint_interval == Some(0) {
+            bail!("checkpoint interval must be positive");
+        }
+        let checkpoint = if options.checkpoint {
+            if options.order != HitOrder::Corpus || options.near_duplicates.is_some() {
+                bail!(
+                    "checkpoints are only supported with corpus order and without near-duplicates"
+                );
+            }
+            let run_key = self.run_key(searches, options);
+            Some(Checkpoint::open(
+                &result_dir.join(CHECKPOINT_FILE),
+                &run_key,
+            )?)
+        } else {
+            None
+        };
+        // parts of an interrupted run are kept only for the corpus files
+        // whose progress the checkpoint has
+        clear_parts(result_dir, |identifier| {
+            checkpoint.as_ref().is_some_and(|c| {
+                c.completed(identifier).is_some() || c.progress(identifier).is_some()
+            })
+        })?;
+        let run = Run {
+            coha: self,
+            result_dir,
+            sink,
+            searches,
+            options,
+            file_template: options.file_template(),
+            limits: Limits::new(searches, options),
+            checkpoint: checkpoint.as_ref(),
+        };
+        memory::reset_peak();
+        // largest files first, so that the run does not end with one
+        // worker on a large file
+        let search_files = || {
+            schedule::largest_first(&self.coha_files, CohaFile::size, |cf| {
+                let Some(checkpoint) = &checkpoint else {
+                    return cf.search(&run);
+                };
+                if let Some(completed) = checkpoint.completed(&cf.identifier) {
+                    info!("{}: already complete", cf.corpus_path.to_string_lossy());
+                    return Ok(completed.restore());
+                }
+                let (mut outputs, warnings, checksum) = cf.search(&run)?;
+                checkpoint.record(&cf.identifier, &mut outputs, &warnings, &checksum)?;
+                Ok((outputs, warnings, checksum))
+            })
+        };
+        let results = match options.memory_budget_bytes {
+            None => search_files(),
+            Some(budget) => {
+                let workers = self.workers_for_budget(budget);
+                info!(
+                    "memory budget {} MiB, {} MiB per worker: {} workers",
+                    budget >> 20,
+                    self.worker_memory_estimate() >> 20,
+                    workers
+                );
+                rayon::ThreadPoolBuilder::new()
+                    .num_threads(workers)
+                    .build()?
+                    .install(search_files)
+            }
+        };
+        let mut file_outputs = Vec::new();
+        let mut corpus_files = Vec::new();
+        let mut warnings = Warnings::default();
+        warnings.add_all(&self.load_warnings);
         for result in results {
-            result?;
+            let (outputs, w, checksum) = result?;
+            file_outputs.push(outputs);
+            corpus_files.push(checksum);
+            warnings.merge(w);
         }
-        Ok(())
+        for message in run.limits.reached() {
+            warn!("{}", message);
+            warnings.add(WarningKind::LimitReached, message);
+        }
+        if let Some(near_duplicates) = options
+            .near_duplicates
+            .as_ref()
+            .filter(|_| !options.count_only)
+        {
+            for (i, search) in searches.iter().enumerate() {
+                let mut rows: Vec<&mut HitRow> = file_outputs
+                    .iter_mut()
+                    .flat_map(|outputs| outputs[i].pending.iter_mut())
+                    .collect();
+                near_duplicates.mark(
+                    &search.label,
+                    &mut rows,
+                    options.layout == ResultLayout::Full,
+                );
+            }
+            if near_duplicates.collapse {
+                for output in file_outputs.iter_mut().flatten() {
+                    output.pending.retain(|row| !row.duplicate);
+                }
+            }
+        }
+        if options.order == HitOrder::FillerFrequency && !options.count_only {
+            for (i, search) in searches.iter().enumerate() {
+                let mut freq: FxHashMap<String, usize> = FxHashMap::default();
+                for outputs in &file_outputs {
+                    for row in &outputs[i].pending {
+                        *freq.entry(row.filler.clone()).or_default() += 1;
+                    }
+                }
+                write_filler_table(result_dir, search, &freq)?;
+                let mut pending: Vec<(usize, HitRow)> = Vec::new();
+                for (f, outputs) in file_outputs.iter_mut().enumerate() {
+                    let rows = std::mem::take(&mut outputs[i].pending);
+                    pending.extend(rows.into_iter().map(|row| (f, row)));
+                }
+                pending.sort_by(|(_, a), (_, b)| {
+                    freq[&b.filler]
+                        .cmp(&freq[&a.filler])
+                        .then_with(|| a.filler.cmp(&b.filler))
+                });
+                // the parts of a result file that covers several decades are
+                // merged in corpus file order, so all of its hits go to one
+                // part to keep them sorted
+                let merged = !run.file_template.contains("{decade}");
+                let first = file_outputs.iter().position(|o| o[i].files.is_some());
+                for (f, row) in pending {
+                    let f = if merged { first.unwrap_or(f) } else { f };
+                    file_outputs[f][i].write(row)?;
+                }
+            }
+        } else if !options.count_only {
+            for output in file_outputs.iter_mut().flatten() {
+                let mut pending = std::mem::take(&mut output.pending);
+                if options.order != HitOrder::Corpus {
+                    pending.sort_by(|a, b| a.sort_key.cmp(&b.sort_key));
+                }
+                for row in pending {
+                    output.write(row)?;
+                }
+            }
+        }
+        let memory = MemoryUsage {
+            load_peak_bytes: self.load_peak_bytes,
+            search_peak_bytes: memory::peak_rss(),
+        };
+        if let Some(peak) = memory.search_peak_bytes {
+            info!("peak memory while searching: {} MiB", peak >> 20);
+        }
+        let mut report = SearchReport {
+            searches: searches
+                .iter()
+                .map(|s| SearchCounts::new(&s.label))
+                .collect(),
+            files: Vec::new(),
+            warnings: warnings.into_vec(),
+            memory,
+        };
+        for outputs in file_outputs {
+            for (counts, mut output) in report.searches.iter_mut().zip(outputs) {
+                output.finish()?;
+                counts.merge(output.counts);
+                report.files.push(output.stats);
+            }
+        }
+        merge_parts(
+            result_dir,
+            &run.sink,
+            options.max_rows_per_file,
+            options.buffer_bytes(),
+        )?;
+        report.write_stats(&result_dir.join(STATS_FILE))?;
+        self.write_manifest(
+            &result_dir.join(MANIFEST_FILE),
+            searches,
+            options,
+            &corpus_files,
+        )?;
+        if options.count_only {
+            for counts in &report.searches {
+                counts.write(&result_dir.join(&counts.label))?;
+            }
+        }
+        if options.burstiness {
+            for counts in &report.searches {
+                counts.write_burstiness(&result_dir.join(&counts.label))?;
+            }
+        }
+        Ok(report)
+    }
+
+    /// A stable 64-bit FNV-1a hash of the lexicon contents.
+    pub fn lexicon_fingerprint(&self) -> u64 {
+        let mut h = Fnv1a::default();
+        for w in self.lexicon.iter().flatten() {
+            h.write(&(w.word_id.0 as u64).to_le_bytes());
+            for field in [&w.word_cs, &w.word, &w.lemma, &w.pos] {
+                h.write(field.as_bytes());
+                h.write(&[0]);
+            }
+        }
+        h.finish()
     }
 
     fn get_word(&self, word_id: WordId) -> &Word {
@@ -403,16 +1665,62 @@ impl Coha {
         }
     }
 
-    fn get_text(&self, tokens: &[Token]) -> String {
-        tokens
-            .iter()
-            .map(|t| &self.get_word(t.word_id).word_cs)
-            .join(" ")
+    fn is_removed(&self, token: &Token) -> bool {
+        self.removed.contains(&token.word_id)
+    }
+
+    fn is_marker(&self, token: &Token) -> bool {
+        self.markers.contains(&token.word_id)
+    }
+
+    fn get_text<'a>(&self, tokens: impl Iterator<Item = &'a Token>) -> String {
+        tokens.map(|t| &self.get_word(t.word_id).word_cs).join(" ")
+    }
+
+    /// [`Coha::get_text`], or [`normalize::tidy_text`] if `tidy`.
+    fn get_text_with<'a>(&self, tokens: impl Iterator<Item = &'a Token>, tidy: bool) -> String {
+        if tidy {
+            normalize::tidy_text(tokens.map(|t| self.get_word(t.word_id).word_cs.as_str()))
+        } else {
+            self.get_text(tokens)
+        }
     }
 
-    fn get_lemma_pos(&self, tokens: &[Token]) -> String {
+    /// Number of tokens to take from `tokens`, nearest first, for a context
+    /// of about `chars` characters, ending at the token boundary closest to
+    /// `chars`. Tokens for which `skip` is true do not count.
+    fn context_tokens<'a>(
+        &self,
+        tokens: impl Iterator<Item = &'a Token>,
+        chars: usize,
+        skip: impl Fn(&Token) -> bool,
+    ) -> usize {
+        let mut len = 0;
+        let mut taken = 0;
+        for (i, t) in tokens.enumerate() {
+            if len >= chars {
+                break;
+            }
+            if skip(t) {
+                continue;
+            }
+            let w = self.get_word(t.word_id).word_cs.chars().count();
+            let next = if len == 0 { w } else { len + 1 + w };
+            if next > chars && next - chars > chars - len {
+                break;
+            }
+            len = next;
+            taken = i + 1;
+        }
+        taken
+    }
+
+    fn get_filler<'a>(&self, tokens: impl Iterator<Item = &'a Token>) -> String {
+        tokens.map(|t| &self.get_word(t.word_id).word).join(" ")
+    }
+
+    fn get_lemma_pos<'a>(&self, tokens: impl Iterator<Item = &'a Token>) -> String {
         tokens
-            .iter()
             .map(|t| {
                 let word = self.get_word(t.word_id);
                 format!("{}_{}", word.lemma, word.pos)
@@ -422,72 +1730,282 @@ impl Coha {
 }
 
 impl CohaFile {
-    fn new(corpus_path: PathBuf) -> Result<Self> {
-        let name = corpus_path
-            .file_name()
-            .expect("valid file name")
-            .to_string_lossy()
-            .into_owned();
-        let re = Regex::new(r"^coha_db_(\d+s)\.txt$").unwrap();
-        let identifier = match re.captures(&name) {
-            None => bail!("unexpected file name {name}"),
-            Some(caps) => caps.get(1).unwrap().as_str().to_owned(),
-        };
-        Ok(Self {
+    /// Parse a line of this corpus file.
+    fn parse_token(&self, s: &str, attributes: &mut AttributeParser) -> Result<Token> {
+        Token::parse_tsv(&self.corpus_path, s, attributes)
+    }
+
+    /// A parser of the extra columns of this corpus file, for
+    /// [`CohaFile::parse_token`].
+    fn attribute_parser(&self) -> AttributeParser {
+        AttributeParser::new(self.attribute_columns)
+    }
+
+    /// The corpus file at `corpus_path`, if its name matches `re`.
+    fn new(corpus_path: PathBuf, re: &Regex) -> Option<Self> {
+        let name = corpus_path.file_name()?.to_string_lossy();
+        let identifier = re.captures(&name)?.get(1)?.as_str().to_owned();
+        Some(Self {
             corpus_path,
             identifier,
+            text_files: Vec::new(),
+            attribute_columns: 0,
         })
     }
-    fn search(&self, coha: &Coha, result_dir: &Path, searches: &[&CohaSearch]) -> Result<()> {
+
+    /// Size of the corpus file in bytes; 0 if it is missing.
+    fn size(&self) -> u64 {
+        if !self.text_files.is_empty() {
+            return self
+                .text_files
+                .iter()
+                .map(|(_, path)| fs::metadata(path).map_or(0, |m| m.len()))
+                .sum();
+        }
+        fs::metadata(&self.corpus_path).map_or(0, |m| m.len())
+    }
+
+    fn search(&self, run: &Run) -> Result<(Vec<SearchOutput>, Warnings, FileChecksum)> {
+        let Run {
+            coha,
+            result_dir,
+            searches,
+            options,
+            ..
+        } = *run;
         let path = &self.corpus_path;
         debug!("{}: reading...", path.to_string_lossy());
-        let mut writers = Vec::new();
-        for search in searches {
-            let outpath = result_dir.join(&search.label);
-            let outpath = outpath.join(format!("{}-{}.csv", &search.label, &self.identifier));
-            debug!("{}: writing...", outpath.to_string_lossy());
-            let mut writer = csv::Writer::from_path(outpath)?;
-            self.write_header(&mut writer, search.filter_list.len())?;
-            writers.push(writer);
-        }
-        let file = File::open(path)?;
-        let mut br = BufReader::new(file);
+        let start_time = Instant::now();
+        let lines = self.lines()?;
+        let missing = lines.is_none();
+        let template = &run.file_template;
+        let new_outputs = || -> Vec<SearchOutput> {
+            searches
+                .iter()
+                .map(|search| SearchOutput {
+                    files: (!options.count_only && !missing).then(|| {
+                        OutputFiles::new(
+                            result_dir,
+                            run.sink.clone(),
+                            self.header(coha, search, options),
+                            options.max_rows_per_file,
+                            options.buffer_bytes(),
+                            (!template.contains("{decade}")).then_some(self.identifier.as_str()),
+                        )
+                    }),
+                    pending: Vec::new(),
+                    counts: SearchCounts::new(&search.label),
+                    stats: FileStats::new(&search.label, &self.identifier),
+                })
+                .collect()
+        };
+        let mut outputs = new_outputs();
+        let mut warnings = Warnings::default();
+        let Some(mut br) = lines else {
+            let message = format!("{}: corpus file missing", path.to_string_lossy());
+            warn!("{}", message);
+            warnings.add(WarningKind::MissingDecade, message);
+            return Ok((outputs, warnings, FileChecksum::new(path, 0, None)));
+        };
+        let removed_path = result_dir
+            .join(REMOVED_DIR)
+            .join(format!("removed-{}.csv", &self.identifier));
+        let mut removed_writer = None;
+        // the progress within the file is recorded only if its lines can be
+        // read again from a byte offset
+        let checkpoint = run
+            .checkpoint
+            .filter(|_| self.text_files.is_empty() && !options.two_pass);
+        let interval = options.checkpoint_interval();
+        let mut position = FilePosition::default();
+        if let Some(progress) = checkpoint.and_then(|c| c.progress(&self.identifier)) {
+            let removed = match progress.removed {
+                Some(len) if options.report_removed => checkpoint::reopen(&removed_path, len)?,
+                _ => None,
+            };
+            if removed.is_some() == options.report_removed
+                && progress.restore(&mut outputs, &mut warnings)?
+            {
+                info!(
+                    "{}: resuming after {} texts",
+                    path.to_string_lossy(),
+                    progress.position.texts
+                );
+                removed_writer = removed.map(csv::Writer::from_writer);
+                position = progress.position;
+                for (i, output) in outputs.iter().enumerate() {
+                    run.limits.reserve(i, output.stats.hits)?;
+                }
+                br = self.lines_from(position.offset)?;
+            } else {
+                outputs = new_outputs();
+                warnings = Warnings::default();
+            }
+        }
+        // a result file that does not depend on the text exists even
+        // without hits
+        for (output, search) in outputs.iter_mut().zip(searches) {
+            let file_name = self.file_name(template, &search.label, options, None);
+            if let (Some(files), Some(file_name)) = (&mut output.files, file_name) {
+                files.open(&file_name)?;
+            }
+        }
+        if options.report_removed && removed_writer.is_none() {
+            debug!("{}: writing...", removed_path.to_string_lossy());
+            let mut writer = csv::Writer::from_path(&removed_path)?;
+            writer.write_record(["text ID", "tokens", "removed tokens", "removed gaps"])?;
+            removed_writer = Some(writer);
+        }
         let mut s = String::new();
+        let mut attributes = self.attribute_parser();
         let mut tokens: Vec<Token> = Vec::new();
-        let mut count_tokens: usize = 0;
-        let mut count_texts: usize = 0;
-        let mut total_hits: usize = 0;
-        let mut hit_texts: usize = 0;
+        let mut count_tokens: usize = position.tokens;
+        let mut count_texts: usize = position.texts;
+        let mut total_hits: usize = position.hits;
+        let mut hit_texts: usize = position.hit_texts;
+        let mut malformed_lines: usize = position.malformed_lines;
+        let mut truncated = false;
+        let mut stopped_early = run.limits.hits_reached();
+        let mut hash = position.hash;
+        let mut bytes: u64 = position.offset;
+        let mut bytes_again: u64 = 0;
+        let mut out_of_order: usize = 0;
+        let mut reordered: usize = position.reordered;
 
-        let mut flush = |tokens: &mut Vec<Token>| -> Result<()> {
-            let hits = self.search_text(coha, &mut writers, searches, tokens)?;
+        // `at` is where the text ends, to record the progress there
+        let mut flush = |tokens: &mut Vec<Token>,
+                         out_of_order: usize,
+                         skipped: Option<&TextSummary>,
+                         at: Option<FilePosition>|
+         -> Result<()> {
+            if let Some(text) = skipped {
+                self.skip_text(run, &mut outputs, text);
+                if let Some(writer) = &mut removed_writer {
+                    self.write_removed(writer, text.range.text_id, text.tokens, &text.removed)?;
+                }
+                count_texts += 1;
+                return Ok(());
+            }
+            if out_of_order > 0 {
+                tokens.sort_by_key(|t| t.token_id);
+                reordered += out_of_order;
+            }
+            let hits = self.search_text(run, &mut outputs, &mut warnings, tokens)?;
+            if let Some(writer) = &mut removed_writer {
+                let mut removed = RemovedCounts::default();
+                for token in tokens.iter() {
+                    removed.add(coha.is_removed(token));
+                }
+                self.write_removed(writer, tokens[0].text_id, tokens.len(), &removed)?;
+            }
             total_hits += hits;
             if hits > 0 {
                 hit_texts += 1;
             }
             count_texts += 1;
             tokens.clear();
+            if let (Some(checkpoint), Some(at)) = (checkpoint, at) {
+                if count_texts.is_multiple_of(interval) && !run.limits.hits_reached() {
+                    let at = FilePosition {
+                        texts: count_texts,
+                        hits: total_hits,
+                        hit_texts,
+                        reordered,
+                        ..at
+                    };
+                    checkpoint.record_progress(
+                        &self.identifier,
+                        at,
+                        &mut outputs,
+                        &warnings,
+                        removed_writer.as_mut(),
+                    )?;
+                }
+            }
             Ok(())
         };
 
-        while br.read_line(&mut s)? > 0 {
-            let token = Token::parse_tsv(path, &s)?;
-            count_tokens += 1;
-            if let Some(prev) = tokens.last() {
-                if prev.text_id != token.text_id {
-                    flush(&mut tokens)?;
+        if stopped_early {
+            info!("{}: not read, hit cutoff reached", path.to_string_lossy());
+        } else if options.two_pass && self.text_files.is_empty() {
+            let pass = self.find_candidates(run, &mut br)?;
+            count_tokens = pass.tokens;
+            malformed_lines = pass.malformed_lines;
+            bytes = pass.bytes;
+            hash = pass.hash;
+            let mut file = File::open(path)?;
+            for text in &pass.texts {
+                if text.candidate {
+                    self.read_range(&mut file, text.range, &mut attributes, &mut tokens)?;
+                    bytes_again += text.range.end - text.range.start;
+                    flush(&mut tokens, text.out_of_order, None, None)?;
+                } else {
+                    flush(&mut tokens, 0, Some(text), None)?;
+                }
+                if run.limits.time_exceeded()? {
+                    truncated = true;
+                    break;
+                }
+                if run.limits.hits_reached() {
+                    stopped_early = true;
+                    break;
                 }
             }
-            if let Some(prev) = tokens.last() {
-                if prev.token_id >= token.token_id {
-                    bail!(tsv_err(path, "token IDs not increasing"));
+        } else {
+            loop {
+                let n = br.read_line(&mut s)?;
+                if n == 0 {
+                    break;
                 }
+                let before = hash;
+                hash.write(s.as_bytes());
+                bytes += n as u64;
+                let token = match self.parse_token(&s, &mut attributes) {
+                    Ok(token) => token,
+                    Err(e) if coha.load_options.lenient => {
+                        debug!("{}: skipping malformed line: {}", path.to_string_lossy(), e);
+                        malformed_lines += 1;
+                        s.clear();
+                        continue;
+                    }
+                    Err(e) => return Err(e),
+                };
+                if let Some(prev) = tokens.last() {
+                    if prev.text_id != token.text_id {
+                        let at = FilePosition {
+                            offset: bytes - n as u64,
+                            hash: before,
+                            tokens: count_tokens,
+                            malformed_lines,
+                            ..Default::default()
+                        };
+                        let out_of_order = std::mem::take(&mut out_of_order);
+                        flush(&mut tokens, out_of_order, None, Some(at))?;
+                        if run.limits.time_exceeded()? {
+                            truncated = true;
+                            break;
+                        }
+                        if run.limits.hits_reached() {
+                            stopped_early = true;
+                            break;
+                        }
+                    }
+                }
+                if let Some(prev) = tokens.last() {
+                    if prev.token_id >= token.token_id {
+                        if !coha.load_options.sort_tokens {
+                            bail!(tsv_err(path, "token IDs not increasing"));
+                        }
+                        out_of_order += 1;
+                    }
+                }
+                count_tokens += 1;
+                tokens.push(token);
+                s.clear();
             }
-            tokens.push(token);
-            s.clear();
         }
         if !tokens.is_empty() {
-            flush(&mut tokens)?;
+            flush(&mut tokens, out_of_order, None, None)?;
         }
         info!(
             "{}: {} tokens in {} texts, {} hits in {} texts",
@@ -497,126 +2015,535 @@ impl CohaFile {
             total_hits,
             hit_texts,
         );
-        for mut writer in writers {
+        if let Some(mut writer) = removed_writer {
             writer.flush()?;
         }
+        warnings.add_count(
+            WarningKind::MalformedLine,
+            format!("{}: malformed line", path.to_string_lossy()),
+            malformed_lines,
+        );
+        warnings.add_count(
+            WarningKind::TokensReordered,
+            format!("{}: token out of order", path.to_string_lossy()),
+            reordered,
+        );
+        let elapsed = start_time.elapsed().as_secs_f64();
+        let busy: f64 = outputs
+            .iter()
+            .map(|o| o.stats.match_secs + o.stats.write_secs)
+            .sum();
+        let parse_secs = (elapsed - busy).max(0.0);
+        debug!(
+            "{}: {:.1} MB in {:.3} s: parse {:.3} s, match and write {:.3} s",
+            path.to_string_lossy(),
+            (bytes + bytes_again) as f64 / 1e6,
+            elapsed,
+            parse_secs,
+            busy,
+        );
+        for output in &mut outputs {
+            output.stats.elapsed_secs = elapsed;
+            output.stats.bytes = bytes + bytes_again;
+            output.stats.parse_secs = parse_secs;
+            output.stats.malformed_lines = malformed_lines;
+            output.stats.truncated |= truncated;
+            output.stats.stopped_early = stopped_early;
+        }
+        Ok((
+            outputs,
+            warnings,
+            FileChecksum::new(path, bytes, Some(&hash)),
+        ))
+    }
+
+    fn write_removed(
+        &self,
+        writer: &mut csv::Writer<File>,
+        text_id: TextId,
+        tokens: usize,
+        removed: &RemovedCounts,
+    ) -> Result<()> {
+        writer.write_record([
+            text_id.0.to_string(),
+            tokens.to_string(),
+            removed.removed.to_string(),
+            removed.gaps.to_string(),
+        ])?;
         Ok(())
     }
 
     fn search_text(
         &self,
-        coha: &Coha,
-        writers: &mut [csv::Writer<File>],
-        searches: &[&CohaSearch],
+        run: &Run,
+        outputs: &mut [SearchOutput],
+        warnings: &mut Warnings,
         tokens: &[Token],
     ) -> Result<usize> {
+        let coha = run.coha;
         assert!(!tokens.is_empty());
         assert!(tokens.first().unwrap().text_id == tokens.last().unwrap().text_id);
         let text_id = tokens.first().unwrap().text_id;
         let mut hits = 0;
         match coha.sources.get(&text_id) {
-            None => warn!(
-                "{}: uknown text ID {}",
-                self.corpus_path.to_string_lossy(),
-                text_id.0
-            ),
+            None => {
+                let message = format!(
+                    "{}: unknown text ID {}",
+                    self.corpus_path.to_string_lossy(),
+                    text_id.0
+                );
+                warn!("{}", message);
+                warnings.add(WarningKind::UnknownTextId, message);
+            }
             Some(source) => {
-                for (writer, search) in writers.iter_mut().zip(searches) {
-                    hits += self.search_text_one(coha, writer, search, source, tokens)?;
+                for (i, (output, search)) in outputs.iter_mut().zip(run.searches).enumerate() {
+                    if !search.restrictions.allows(source) {
+                        continue;
+                    }
+                    let match_start = Instant::now();
+                    let mut spans = coha.find_spans(search, tokens);
+                    output.stats.match_secs += match_start.elapsed().as_secs_f64();
+                    if let Some(thinning) = &run.options.thinning {
+                        output.stats.thinned += thinning.thin(text_id, tokens.len(), &mut spans);
+                    }
+                    let allowed = run.limits.reserve(i, spans.len())?;
+                    if allowed < spans.len() {
+                        output.stats.truncated = true;
+                        spans.truncate(allowed);
+                    }
+                    if run.options.burstiness && !spans.is_empty() {
+                        let positions: Vec<usize> = spans.iter().map(|s| s.start).collect();
+                        output
+                            .counts
+                            .burstiness
+                            .insert(source.text_id, Burstiness::new(tokens.len(), &positions));
+                    }
+                    let write_start = Instant::now();
+                    let h = self.write_hits(run, output, search, source, tokens, &spans)?;
+                    output.stats.write_secs += write_start.elapsed().as_secs_f64();
+                    let metadata = coha.metadata.values(source.text_id);
+                    output.counts.add(
+                        &self.identifier,
+                        source,
+                        coha.metadata.columns(),
+                        &metadata,
+                        h,
+                    );
+                    output.stats.add(tokens.len(), h);
+                    hits += h;
                 }
             }
         }
         Ok(hits)
     }
 
-    fn search_text_one(
+    fn write_hits(
         &self,
-        coha: &Coha,
-        writer: &mut csv::Writer<File>,
+        run: &Run,
+        output: &mut SearchOutput,
         search: &CohaSearch,
         source: &Source,
         tokens: &[Token],
+        spans: &[Span],
     ) -> Result<usize> {
-        let m = search.filter_list.len();
-        let n = tokens.len();
-        let mut hits = 0;
-        if n >= m {
-            'outer: for i in 0..(n - m + 1) {
-                for j in 0..m {
-                    let word_id = tokens[i + j].word_id;
-                    if !match search.filter_list[j] {
-                        CohaFilter::Any => true,
-                        CohaFilter::Hash(x) => x.contains(&word_id),
-                    } {
-                        continue 'outer;
-                    }
-                }
-                self.write_hit(coha, writer, source, tokens, i, m)?;
-                hits += 1;
+        let options = run.options;
+        if !options.count_only {
+            let sentences = ((options.sentence_numbers || options.paragraph_numbers)
+                && !spans.is_empty())
+            .then(|| Sentences::new(run.coha, tokens));
+            for &span in spans {
+                let row = self.hit_row(run, search, source, tokens, sentences.as_ref(), span);
+                output.push(options, row)?;
             }
         }
-        Ok(hits)
+        Ok(spans.len())
     }
 
-    fn write_header(&self, writer: &mut csv::Writer<File>, m: usize) -> Result<()> {
-        let mut row = vec![
-            "text ID".to_owned(),
-            "genre".to_owned(),
-            "year".to_owned(),
-            "title".to_owned(),
-            "author".to_owned(),
-            "position".to_owned(),
-        ];
+    /// The result file of a hit in the given text. Without a text, `None`
+    /// if the file name depends on the text.
+    fn file_name(
+        &self,
+        template: &str,
+        label: &str,
+        options: &SearchOptions,
+        source: Option<&Source>,
+    ) -> Option<String> {
+        let file_name = template
+            .replace("{label}", label)
+            .replace("{decade}", &self.identifier);
+        let Some(source) = source else {
+            return (!["{genre}", "{year}", "{year_bin}"]
+                .iter()
+                .any(|p| file_name.contains(p)))
+            .then_some(file_name);
+        };
+        let year_bin = match &options.split_by_year {
+            Some(bins) => bins.label(source.year.0),
+            None => source.year.0.to_string(),
+        };
+        Some(
+            file_name
+                .replace("{genre}", &source.genre.to_string())
+                .replace("{year}", &source.year.0.to_string())
+                .replace("{year_bin}", &year_bin),
+        )
+    }
+
+    fn header(&self, coha: &Coha, search: &CohaSearch, options: &SearchOptions) -> Vec<String> {
+        let kwic = match options.layout {
+            ResultLayout::Full => None,
+            ResultLayout::AntConc => Some(["before", "match", "after", "file"]),
+            ResultLayout::CqpWeb => {
+                Some(["Text ID", "Context before", "Query item", "Context after"])
+            }
+            ResultLayout::LancsBox => Some(["File", "Left", "Node", "Right"]),
+        };
+        if let Some(kwic) = kwic {
+            return kwic.map(|x| x.to_owned()).to_vec();
+        }
+        let mut row = Vec::new();
+        if options.hit_id_column {
+            row.push("hit ID".to_owned());
+        }
+        row.extend(["text ID", "genre", "year"].map(|x| x.to_owned()));
+        if options.date_column {
+            row.push("date".to_owned());
+        }
+        row.push("title".to_owned());
+        row.push("author".to_owned());
+        row.extend(coha.metadata.columns().iter().cloned());
+        if options.citation_template.is_some() {
+            row.push("citation".to_owned());
+        }
+        row.push("position".to_owned());
+        if options.sentence_numbers {
+            row.push("sentence".to_owned());
+            row.push("sentence position".to_owned());
+        }
+        if options.paragraph_numbers {
+            row.push("paragraph".to_owned());
+        }
+        if options.line_markers.is_some() {
+            row.push("line".to_owned());
+        }
         row.push("before".to_owned());
-        for j in 0..m {
-            row.push(format!("wordCS {}", j + 1));
+        if search.has_slots() {
+            for j in search.shown_slots() {
+                row.push(format!("wordCS {}", j + 1));
+            }
+        } else {
+            row.push("match".to_owned());
         }
         row.push("after".to_owned());
         row.push("before_pos".to_owned());
-        for j in 0..m {
-            row.push(format!("word {}", j + 1));
-            row.push(format!("lemma {}", j + 1));
-            row.push(format!("pos {}", j + 1));
+        if search.has_slots() {
+            for j in search.shown_slots() {
+                row.push(format!("word {}", j + 1));
+                row.push(format!("lemma {}", j + 1));
+                row.push(format!("pos {}", j + 1));
+                if options.attribute_columns {
+                    for name in coha.token_attributes() {
+                        row.push(format!("{name} {}", j + 1));
+                    }
+                }
+            }
+        } else {
+            row.push("match_pos".to_owned());
+            if options.attribute_columns {
+                for name in coha.token_attributes() {
+                    row.push(format!("match_{name}"));
+                }
+            }
         }
         row.push("after_pos".to_owned());
-        writer.write_record(row)?;
-        Ok(())
+        if options.sort_key_columns {
+            row.push("L1".to_owned());
+            row.push("R1".to_owned());
+            row.push("node lemma".to_owned());
+        }
+        if let Some(annotator) = &search.annotator {
+            row.extend(annotator.columns.iter().cloned());
+        }
+        if options.near_duplicates.is_some() {
+            row.push("duplicate cluster".to_owned());
+            row.push("cluster size".to_owned());
+        }
+        row
     }
 
-    fn write_hit(
+    fn hit_row<'t>(
         &self,
-        coha: &Coha,
-        writer: &mut csv::Writer<File>,
+        run: &Run,
+        search: &CohaSearch,
         source: &Source,
-        tokens: &[Token],
-        pos: usize,
-        m: usize,
-    ) -> Result<()> {
-        let mut row = vec![
+        tokens: &'t [Token],
+        sentences: Option<&Sentences>,
+        span: Span,
+    ) -> HitRow {
+        let Run { coha, options, .. } = *run;
+        let pos = span.start;
+        let m = span.len;
+        let skip = |t: &Token| {
+            (options.skip_removed_context && coha.is_removed(t))
+                || (options.skip_marker_context && coha.is_marker(t))
+        };
+        let (start, end) = match options.context_chars {
+            None => (
+                pos.saturating_sub(CONTEXT),
+                tokens.len().min(pos + m + CONTEXT),
+            ),
+            Some(chars) => (
+                pos - coha.context_tokens(tokens[..pos].iter().rev(), chars, skip),
+                pos + m + coha.context_tokens(tokens[pos + m..].iter(), chars, skip),
+            ),
+        };
+        let context = |range: &'t [Token]| range.iter().filter(move |t| !skip(t));
+        let tidy = options.tidy_text;
+        let filler = if search.has_slots() {
+            coha.get_filler(search.shown_slots().map(|j| &tokens[pos + j]))
+        } else {
+            coha.get_filler(tokens[pos..pos + m].iter())
+        };
+        let file = self
+            .file_name(&run.file_template, &search.label, options, Some(source))
+            .expect("file name of a hit");
+        let word = |t: &Token| coha.get_word(t.word_id).word.clone();
+        let node_lemma = || {
+            tokens[pos..pos + m]
+                .iter()
+                .map(|t| &coha.get_word(t.word_id).lemma)
+                .join(" ")
+        };
+        let sort_key = match options.order {
+            HitOrder::Corpus | HitOrder::FillerFrequency => Vec::new(),
+            HitOrder::RightContext => context(&tokens[pos + m..end])
+                .take(SORT_KEY_WORDS)
+                .map(word)
+                .collect(),
+            HitOrder::LeftContext => context(&tokens[start..pos])
+                .rev()
+                .take(SORT_KEY_WORDS)
+                .map(word)
+                .collect(),
+            HitOrder::NodeLemma => std::iter::once(node_lemma())
+                .chain(context(&tokens[pos + m..end]).take(1).map(word))
+                .collect(),
+        };
+        let fingerprint = options.near_duplicates.as_ref().map(|nd| {
+            let words: Vec<&str> = context(&tokens[start..pos])
+                .chain(&tokens[pos..pos + m])
+                .chain(context(&tokens[pos + m..end]))
+                .map(|t| coha.get_word(t.word_id).word.as_str())
+                .collect();
+            Fingerprint::new(source.text_id, &words, nd.shingle_words)
+        });
+        if options.layout != ResultLayout::Full {
+            let before = coha.get_text_with(context(&tokens[start..pos]), tidy);
+            let matched = coha.get_text_with(tokens[pos..pos + m].iter(), tidy);
+            let after = coha.get_text_with(context(&tokens[pos + m..end]), tidy);
+            let text_id = source.text_id.0.to_string();
+            let record = match options.layout {
+                ResultLayout::AntConc => vec![before, matched, after, format!("{text_id}.txt")],
+                _ => vec![text_id, before, matched, after],
+            };
+            return HitRow {
+                filler,
+                sort_key,
+                fingerprint,
+                duplicate: false,
+                file,
+                record,
+            };
+        }
+        let mut row = Vec::new();
+        if options.hit_id_column {
+            row.push(hit_id(source.text_id, pos, &search.label));
+        }
+        row.extend([
             source.text_id.0.to_string(),
             source.genre.to_string(),
             source.year.0.to_string(),
-            source.title.to_owned(),
-            source.author.to_owned(),
-            pos.to_string(),
+        ]);
+        if options.date_column {
+            row.push(source.date.map(|d| d.to_string()).unwrap_or_default());
+        }
+        row.push(source.title.to_owned());
+        row.push(source.author.to_owned());
+        row.extend(
+            coha.metadata
+                .values(source.text_id)
+                .into_iter()
+                .map(|x| x.to_owned()),
+        );
+        if let Some(template) = &options.citation_template {
+            row.push(source.citation(template));
+        }
+        row.push(pos.to_string());
+        if let Some(sentences) = sentences {
+            if options.sentence_numbers {
+                let (sentence, position) = sentences.locate(pos);
+                row.push(sentence.to_string());
+                row.push(position.to_string());
+            }
+            if options.paragraph_numbers {
+                row.push(sentences.paragraph(pos).to_string());
+            }
+        }
+        let before = coha.get_text_with(context(&tokens[start..pos]), tidy);
+        let after = coha.get_text_with(context(&tokens[pos + m..end]), tidy);
+        if let Some((open, close)) = &options.line_markers {
+            let matched = coha.get_text_with(tokens[pos..pos + m].iter(), tidy);
+            let line = [before.as_str(), &format!("{open}{matched}{close}"), &after]
+                .into_iter()
+                .filter(|x| !x.is_empty())
+                .join(" ");
+            row.push(line);
+        }
+        row.push(before);
+        if search.has_slots() {
+            for j in search.shown_slots() {
+                let word = coha.get_word(tokens[pos + j].word_id);
+                row.push(if tidy {
+                    normalize::tidy_word(&word.word_cs)
+                } else {
+                    word.word_cs.to_owned()
+                });
+            }
+        } else {
+            row.push(coha.get_text_with(tokens[pos..pos + m].iter(), tidy));
+        }
+        row.push(after);
+        row.push(coha.get_lemma_pos(context(&tokens[start..pos])));
+        if search.has_slots() {
+            for j in search.shown_slots() {
+                let word = coha.get_word(tokens[pos + j].word_id);
+                row.push(word.word.to_owned());
+                row.push(word.lemma.to_owned());
+                row.push(word.pos.to_owned());
+                if options.attribute_columns {
+                    row.extend(tokens[pos + j].attributes.iter().map(|v| v.to_string()));
+                }
+            }
+        } else {
+            row.push(coha.get_lemma_pos(tokens[pos..pos + m].iter()));
+            if options.attribute_columns {
+                for i in 0..coha.token_attributes().len() {
+                    row.push(
+                        tokens[pos..pos + m]
+                            .iter()
+                            .map(|t| &t.attributes[i])
+                            .join(" "),
+                    );
+                }
+            }
+        }
+        row.push(coha.get_lemma_pos(context(&tokens[pos + m..end])));
+        if options.sort_key_columns {
+            row.push(
+                context(&tokens[start..pos])
+                    .last()
+                    .map(word)
+                    .unwrap_or_default(),
+            );
+            row.push(
+                context(&tokens[pos + m..end])
+                    .next()
+                    .map(word)
+                    .unwrap_or_default(),
+            );
+            row.push(node_lemma());
+        }
+        if let Some(annotator) = &search.annotator {
+            let hit = Hit {
+                label: &search.label,
+                text_id: source.text_id,
+                genre: source.genre.clone(),
+                year: source.year.0,
+                date: source.date,
+                title: &source.title,
+                author: &source.author,
+                position: pos,
+                words: tokens[pos..pos + m]
+                    .iter()
+                    .map(|t| coha.get_word(t.word_id))
+                    .collect(),
+                before: context(&tokens[start..pos])
+                    .map(|t| coha.get_word(t.word_id))
+                    .collect(),
+                after: context(&tokens[pos + m..end])
+                    .map(|t| coha.get_word(t.word_id))
+                    .collect(),
+            };
+            row.extend(annotator.values(&hit));
+        }
+        HitRow {
+            filler,
+            sort_key,
+            fingerprint,
+            duplicate: false,
+            file,
+            record: row,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn capitalization() {
+        use Capitalization::*;
+        let cases = [
+            ("dog", [true, false, false, false]),
+            ("God", [false, true, true, false]),
+            ("USA", [false, true, false, true]),
+            ("I", [false, true, true, true]),
+            ("McDonald", [false, true, false, false]),
+            ("U.S.A.", [false, true, false, true]),
+            ("n't", [true, false, false, false]),
+            ("1850s", [true, false, false, false]),
+            ("Éire", [false, true, true, false]),
         ];
-        let start = if pos < CONTEXT { 0 } else { pos - CONTEXT };
-        let end = tokens.len().min(pos + m + CONTEXT);
-        row.push(coha.get_text(&tokens[start..pos]));
-        for j in 0..m {
-            let word = coha.get_word(tokens[pos + j].word_id);
-            row.push(word.word_cs.to_owned());
-        }
-        row.push(coha.get_text(&tokens[pos + m..end]));
-        row.push(coha.get_lemma_pos(&tokens[start..pos]));
-        for j in 0..m {
-            let word = coha.get_word(tokens[pos + j].word_id);
-            row.push(word.word.to_owned());
-            row.push(word.lemma.to_owned());
-            row.push(word.pos.to_owned());
-        }
-        row.push(coha.get_lemma_pos(&tokens[pos + m..end]));
-        writer.write_record(row)?;
-        Ok(())
+        for (s, expected) in cases {
+            let matches = [Lowercase, InitialCapital, TitleCase, AllCaps].map(|c| c.matches(s));
+            assert_eq!(matches, expected, "{s}");
+        }
+    }
+
+    #[test]
+    fn capitalization_without_letters() {
+        use Capitalization::*;
+        for s in ["", ",", "1850", "--"] {
+            for c in [Lowercase, InitialCapital, TitleCase, AllCaps] {
+                assert!(!c.matches(s), "{c:?} {s:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn genres() {
+        assert_eq!(Genre::parse("NEWS"), Some(Genre::NEWS));
+        assert!(Genre::parse("NEWS").unwrap().is_well_known());
+        assert_eq!(Genre::parse(""), None);
+        assert_eq!(Genre::parse("SPOKEN WORD"), None);
+        let spoken = Genre::parse("SPOK").unwrap();
+        assert!(!spoken.is_well_known());
+        assert_eq!(spoken.as_str(), "SPOK");
+        assert_eq!(spoken, Genre::parse("SPOK").unwrap());
+        assert!(Genre::MAG < spoken && spoken < Genre::parse("TV").unwrap());
+    }
+
+    #[test]
+    fn genres_of_texts_share_names() {
+        let path = Path::new("sources.txt");
+        let mut genres = FxHashSet::default();
+        let a = Genre::parse_for_files(path, "SPOK", &mut genres).unwrap();
+        let b = Genre::parse_for_files(path, "SPOK", &mut genres).unwrap();
+        let (GenreName::Other(a), GenreName::Other(b)) = (a.0, b.0) else {
+            panic!("not a well-known genre");
+        };
+        assert!(Arc::ptr_eq(&a, &b));
+        assert!(Genre::parse_for_files(path, "", &mut genres).is_err());
     }
 }
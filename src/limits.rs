@@ -0,0 +1,125 @@
+use crate::{CohaSearch, SearchOptions};
+use anyhow::{bail, Result};
+use serde::{Deserialize, Serialize};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+
+/// What to do when a search exceeds [`SearchOptions::max_hits`] or
+/// [`SearchOptions::time_limit_secs`]. Reaching
+/// [`SearchOptions::stop_after_hits`] always stops the search.
+#[derive(Copy, Debug, Clone, Default, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LimitAction {
+    /// Stop searching and keep the results so far. The report contains a
+    /// [`crate::WarningKind::LimitReached`] warning and the statistics of
+    /// the affected files are marked as truncated.
+    #[default]
+    Stop,
+    /// Fail with an error.
+    Abort,
+}
+
+/// Limits shared by all corpus files of a search run.
+pub(crate) struct Limits {
+    labels: Vec<String>,
+    action: LimitAction,
+    max_hits: Option<usize>,
+    stop_after_hits: Option<usize>,
+    deadline: Option<Instant>,
+    hits: Vec<AtomicUsize>,
+    hits_exceeded: Vec<AtomicBool>,
+    hits_reached: Vec<AtomicBool>,
+    time_exceeded: AtomicBool,
+}
+
+impl Limits {
+    pub fn new(searches: &[&CohaSearch], options: &SearchOptions) -> Self {
+        Self {
+            labels: searches.iter().map(|s| s.label.clone()).collect(),
+            action: options.on_limit,
+            max_hits: options.max_hits,
+            stop_after_hits: options.stop_after_hits,
+            deadline: options
+                .time_limit_secs
+                .map(|secs| Instant::now() + Duration::from_secs(secs)),
+            hits: searches.iter().map(|_| AtomicUsize::new(0)).collect(),
+            hits_exceeded: searches.iter().map(|_| AtomicBool::new(false)).collect(),
+            hits_reached: searches.iter().map(|_| AtomicBool::new(false)).collect(),
+            time_exceeded: AtomicBool::new(false),
+        }
+    }
+
+    /// Should the search of all corpus files stop now?
+    pub fn time_exceeded(&self) -> Result<bool> {
+        if self.time_exceeded.load(Ordering::Relaxed) {
+            return Ok(true);
+        }
+        let Some(deadline) = self.deadline else {
+            return Ok(false);
+        };
+        if Instant::now() < deadline {
+            return Ok(false);
+        }
+        if self.action == LimitAction::Abort {
+            bail!("time limit exceeded");
+        }
+        self.time_exceeded.store(true, Ordering::Relaxed);
+        Ok(true)
+    }
+
+    /// Should no more texts be read because every search has reached
+    /// [`SearchOptions::stop_after_hits`]?
+    pub fn hits_reached(&self) -> bool {
+        self.stop_after_hits.is_some()
+            && self.hits_reached.iter().all(|r| r.load(Ordering::Relaxed))
+    }
+
+    /// Reserve room for `n` more hits of search `i`; returns how many of them
+    /// fit within the hit limits.
+    pub fn reserve(&self, i: usize, n: usize) -> Result<usize> {
+        let Some(limit) = self.max_hits.into_iter().chain(self.stop_after_hits).min() else {
+            return Ok(n);
+        };
+        let prev = self.hits[i]
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |h| {
+                Some(limit.min(h + n))
+            })
+            .expect("update always succeeds");
+        let allowed = limit.min(prev + n) - prev;
+        if self
+            .stop_after_hits
+            .is_some_and(|stop| prev + allowed >= stop)
+        {
+            self.hits_reached[i].store(true, Ordering::Relaxed);
+        } else if allowed < n {
+            if self.action == LimitAction::Abort {
+                bail!("search {}: more than {} hits", self.labels[i], limit);
+            }
+            self.hits_exceeded[i].store(true, Ordering::Relaxed);
+        }
+        Ok(allowed)
+    }
+
+    /// Descriptions of the limits that were reached.
+    pub fn reached(&self) -> Vec<String> {
+        let mut reached = Vec::new();
+        if let Some(max_hits) = self.max_hits {
+            for (label, exceeded) in self.labels.iter().zip(&self.hits_exceeded) {
+                if exceeded.load(Ordering::Relaxed) {
+                    reached.push(format!("search {label}: hit limit of {max_hits} reached"));
+                }
+            }
+        }
+        if let Some(stop) = self.stop_after_hits {
+            for (label, reached_stop) in self.labels.iter().zip(&self.hits_reached) {
+                if reached_stop.load(Ordering::Relaxed) {
+                    reached.push(format!("search {label}: stopped after {stop} hits"));
+                }
+            }
+        }
+        if self.time_exceeded.load(Ordering::Relaxed) {
+            reached.push("time limit reached".to_owned());
+        }
+        reached
+    }
+}
@@ -0,0 +1,65 @@
+use std::io::{self, BufRead};
+
+const BOM: &[u8] = b"\xef\xbb\xbf";
+
+/// Reads lines that end in "\n", "\r\n", or a lone "\r", as in files saved
+/// on different platforms, and skips a UTF-8 byte order mark at the start.
+pub(crate) struct LineReader<R> {
+    inner: R,
+    buf: Vec<u8>,
+    first: bool,
+}
+
+impl<R: BufRead> LineReader<R> {
+    pub(crate) fn new(inner: R) -> Self {
+        LineReader {
+            inner,
+            buf: Vec::new(),
+            first: true,
+        }
+    }
+
+    /// Append the next line, including its line ending, to `s`. Returns the
+    /// number of bytes read from the input, including a byte order mark; 0
+    /// at the end of the input.
+    pub(crate) fn read_line(&mut self, s: &mut String) -> io::Result<usize> {
+        self.buf.clear();
+        loop {
+            let available = self.inner.fill_buf()?;
+            if available.is_empty() {
+                break;
+            }
+            match available.iter().position(|&b| b == b'\n' || b == b'\r') {
+                Some(i) => {
+                    let cr = available[i] == b'\r';
+                    self.buf.extend_from_slice(&available[..=i]);
+                    self.inner.consume(i + 1);
+                    if cr && self.inner.fill_buf()?.first() == Some(&b'\n') {
+                        self.buf.push(b'\n');
+                        self.inner.consume(1);
+                    }
+                    break;
+                }
+                None => {
+                    let n = available.len();
+                    self.buf.extend_from_slice(available);
+                    self.inner.consume(n);
+                }
+            }
+        }
+        let mut line = self.buf.as_slice();
+        if self.first {
+            self.first = false;
+            line = line.strip_prefix(BOM).unwrap_or(line);
+        }
+        let line =
+            std::str::from_utf8(line).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        s.push_str(line);
+        Ok(self.buf.len())
+    }
+}
+
+/// `bytes` without a leading UTF-8 byte order mark.
+pub(crate) fn strip_bom(bytes: &[u8]) -> &[u8] {
+    bytes.strip_prefix(BOM).unwrap_or(bytes)
+}
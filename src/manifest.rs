@@ -0,0 +1,207 @@
+use crate::{
+    Coha, CohaFilter, CohaSearch, LoadOptions, Restrictions, SearchOptions, LEXICON_FILE,
+    SOURCES_FILE,
+};
+use anyhow::Result;
+use log::debug;
+use serde::{Deserialize, Serialize};
+use std::fs::File;
+use std::io::{BufWriter, Read, Write};
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// A 64-bit FNV-1a hash.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub(crate) struct Fnv1a(u64);
+
+impl Default for Fnv1a {
+    fn default() -> Self {
+        Fnv1a(0xcbf29ce484222325)
+    }
+}
+
+impl Fnv1a {
+    pub(crate) fn write(&mut self, bytes: &[u8]) {
+        for &b in bytes {
+            self.0 ^= b as u64;
+            self.0 = self.0.wrapping_mul(0x100000001b3);
+        }
+    }
+
+    pub(crate) fn finish(&self) -> u64 {
+        self.0
+    }
+}
+
+/// Size and checksum of one input file.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FileChecksum {
+    pub path: String,
+    pub bytes: u64,
+    /// FNV-1a hash of the contents as 16 hex digits; `None` if the file was
+    /// missing.
+    pub checksum: Option<String>,
+}
+
+impl FileChecksum {
+    pub(crate) fn new(path: &Path, bytes: u64, hash: Option<&Fnv1a>) -> Self {
+        FileChecksum {
+            path: path.to_string_lossy().into_owned(),
+            bytes,
+            checksum: hash.map(|h| format!("{:016x}", h.finish())),
+        }
+    }
+
+    fn read(path: &Path) -> Result<Self> {
+        let mut file = File::open(path)?;
+        let mut hash = Fnv1a::default();
+        let mut bytes = 0;
+        let mut buf = vec![0u8; 1 << 16];
+        loop {
+            let n = file.read(&mut buf)?;
+            if n == 0 {
+                break;
+            }
+            hash.write(&buf[..n]);
+            bytes += n as u64;
+        }
+        Ok(Self::new(path, bytes, Some(&hash)))
+    }
+}
+
+/// One slot of a search: the kind of filter, the number of word IDs in it,
+/// and a hash of the sorted word IDs.
+#[derive(Debug, Clone, Serialize)]
+struct FilterDescription {
+    kind: &'static str,
+    words: usize,
+    checksum: String,
+}
+
+impl FilterDescription {
+    fn new(filter: &CohaFilter) -> Self {
+        let (kind, set) = match filter {
+            CohaFilter::Any => ("any", None),
+            CohaFilter::Hash(x) => ("hash", Some(x)),
+            CohaFilter::Complement(x) => ("complement", Some(x)),
+        };
+        let mut ids: Vec<usize> = set.into_iter().flatten().map(|&id| id.into()).collect();
+        ids.sort();
+        let mut hash = Fnv1a::default();
+        for id in &ids {
+            hash.write(&(*id as u64).to_le_bytes());
+        }
+        FilterDescription {
+            kind,
+            words: ids.len(),
+            checksum: format!("{:016x}", hash.finish()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+struct SearchDescription<'a> {
+    label: &'a str,
+    slots: Vec<FilterDescription>,
+    hidden_slots: &'a [usize],
+    surface_regex: Option<&'a str>,
+    tagged_regex: Option<&'a str>,
+    restrictions: &'a Restrictions,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    annotation_columns: Option<&'a [String]>,
+    /// The slots (0-based) with a condition on a token attribute, and the
+    /// name of the attribute.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    attribute_filters: Vec<(usize, &'a str)>,
+}
+
+/// A description of a search run, written as `manifest.json` next to the
+/// results. Random seeds are part of the search options.
+#[derive(Serialize)]
+struct Manifest<'a> {
+    crate_version: &'a str,
+    created_unix_secs: u64,
+    corpus_dir: String,
+    lexicon_fingerprint: String,
+    load_options: &'a LoadOptions,
+    sources: FileChecksum,
+    lexicon: FileChecksum,
+    corpus_files: &'a [FileChecksum],
+    searches: Vec<SearchDescription<'a>>,
+    options: &'a SearchOptions,
+}
+
+fn describe<'a>(searches: &[&'a CohaSearch]) -> Vec<SearchDescription<'a>> {
+    searches
+        .iter()
+        .map(|s| SearchDescription {
+            label: &s.label,
+            slots: s
+                .filter_list
+                .iter()
+                .map(|f| FilterDescription::new(f))
+                .collect(),
+            hidden_slots: &s.hidden_slots,
+            surface_regex: s.surface_regex.as_ref().map(|re| re.as_str()),
+            tagged_regex: s.tagged_regex.as_ref().map(|re| re.as_str()),
+            restrictions: &s.restrictions,
+            annotation_columns: s.annotator.as_ref().map(|a| a.columns.as_slice()),
+            attribute_filters: s
+                .attribute_filters
+                .iter()
+                .map(|(j, f)| (*j, f.name()))
+                .collect(),
+        })
+        .collect()
+}
+
+impl Coha {
+    /// A hash of everything that determines the contents of the result
+    /// files: the lexicon, the metadata columns, the searches, and the
+    /// options.
+    pub(crate) fn run_key(&self, searches: &[&CohaSearch], options: &SearchOptions) -> String {
+        let options = SearchOptions {
+            checkpoint: false,
+            checkpoint_interval: None,
+            ..options.clone()
+        };
+        let described = serde_json::to_string(&(
+            self.lexicon_fingerprint(),
+            self.metadata.columns(),
+            describe(searches),
+            options,
+        ))
+        .expect("serializable");
+        let mut hash = Fnv1a::default();
+        hash.write(described.as_bytes());
+        format!("{:016x}", hash.finish())
+    }
+
+    pub(crate) fn write_manifest(
+        &self,
+        path: &Path,
+        searches: &[&CohaSearch],
+        options: &SearchOptions,
+        corpus_files: &[FileChecksum],
+    ) -> Result<()> {
+        debug!("{}: writing...", path.to_string_lossy());
+        let manifest = Manifest {
+            crate_version: env!("CARGO_PKG_VERSION"),
+            created_unix_secs: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |d| d.as_secs()),
+            corpus_dir: self.root_dir.to_string_lossy().into_owned(),
+            lexicon_fingerprint: format!("{:016x}", self.lexicon_fingerprint()),
+            load_options: &self.load_options,
+            sources: FileChecksum::read(&self.root_dir.join(SOURCES_FILE))?,
+            lexicon: FileChecksum::read(&self.root_dir.join(LEXICON_FILE))?,
+            corpus_files,
+            searches: describe(searches),
+            options,
+        };
+        let mut writer = BufWriter::new(File::create(path)?);
+        serde_json::to_writer_pretty(&mut writer, &manifest)?;
+        writer.flush()?;
+        Ok(())
+    }
+}
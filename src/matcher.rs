@@ -0,0 +1,150 @@
+use crate::{Coha, CohaSearch, Token};
+use regex::Regex;
+use std::ops::Range;
+
+/// A hit: `len` tokens starting at token index `start` of a text.
+#[derive(Copy, Debug, Clone, Eq, PartialEq)]
+pub(crate) struct Span {
+    pub start: usize,
+    pub len: usize,
+}
+
+/// Tokens that attach to the preceding token without a space.
+pub(crate) fn attaches_left(word_cs: &str) -> bool {
+    word_cs.starts_with('\'')
+        || word_cs.eq_ignore_ascii_case("n't")
+        || matches!(word_cs, "." | "," | ";" | ":" | "!" | "?" | ")" | "]" | "}")
+}
+
+/// Tokens that attach to the following token without a space.
+pub(crate) fn attaches_right(word_cs: &str) -> bool {
+    matches!(word_cs, "(" | "[" | "{")
+}
+
+impl Coha {
+    /// Reconstruct the running text of a token sequence, together with the
+    /// byte range of each token in the result.
+    pub(crate) fn surface_text(&self, tokens: &[Token]) -> (String, Vec<Range<usize>>) {
+        let mut text = String::new();
+        let mut offsets = Vec::with_capacity(tokens.len());
+        let mut prev_right = true;
+        for token in tokens {
+            let word_cs = &self.get_word(token.word_id).word_cs;
+            if !prev_right && !attaches_left(word_cs) {
+                text.push(' ');
+            }
+            let start = text.len();
+            text.push_str(word_cs);
+            offsets.push(start..text.len());
+            prev_right = attaches_right(word_cs);
+        }
+        (text, offsets)
+    }
+
+    /// Encode a token sequence as `word/lemma/pos ` for each token, together
+    /// with the byte range of each token in the result.
+    pub(crate) fn tagged_text(&self, tokens: &[Token]) -> (String, Vec<Range<usize>>) {
+        let mut text = String::new();
+        let mut offsets = Vec::with_capacity(tokens.len());
+        for token in tokens {
+            let word = self.get_word(token.word_id);
+            let start = text.len();
+            text.push_str(&word.word);
+            text.push('/');
+            text.push_str(&word.lemma);
+            text.push('/');
+            text.push_str(&word.pos);
+            text.push(' ');
+            offsets.push(start..text.len());
+        }
+        (text, offsets)
+    }
+
+    pub(crate) fn find_spans(&self, search: &CohaSearch, tokens: &[Token]) -> Vec<Span> {
+        let spans = if let Some(re) = &search.surface_regex {
+            let (text, offsets) = self.surface_text(tokens);
+            regex_spans(re, &text, &offsets)
+        } else if let Some(re) = &search.tagged_regex {
+            let (text, offsets) = self.tagged_text(tokens);
+            regex_spans(re, &text, &offsets)
+        } else {
+            self.find_slot_spans(search, tokens)
+        };
+        // never match across removed material or structural markers; a
+        // marker alone may still be searched for
+        spans
+            .into_iter()
+            .filter(|s| {
+                let span = &tokens[s.start..s.start + s.len];
+                !span.iter().any(|t| self.is_removed(t))
+                    && (s.len == 1 || !span.iter().any(|t| self.is_marker(t)))
+            })
+            .collect()
+    }
+
+    fn find_slot_spans(&self, search: &CohaSearch, tokens: &[Token]) -> Vec<Span> {
+        let m = search.filter_list.len();
+        let n = tokens.len();
+        let mut spans = Vec::new();
+        if n >= m {
+            'outer: for i in 0..(n - m + 1) {
+                for j in 0..m {
+                    if !search.filter_list[j].matches(tokens[i + j].word_id) {
+                        continue 'outer;
+                    }
+                }
+                for (j, filter) in &search.attribute_filters {
+                    if !filter.matches(&tokens[i + j]) {
+                        continue 'outer;
+                    }
+                }
+                spans.push(Span { start: i, len: m });
+            }
+        }
+        spans
+    }
+}
+
+fn regex_spans(re: &Regex, text: &str, offsets: &[Range<usize>]) -> Vec<Span> {
+    re.find_iter(text)
+        .filter(|m| !m.is_empty())
+        .map(|m| covering_span(offsets, m.range()))
+        .collect()
+}
+
+/// The tokens that overlap the byte range `r`.
+fn covering_span(offsets: &[Range<usize>], r: Range<usize>) -> Span {
+    let start = offsets.partition_point(|o| o.end <= r.start);
+    let end = offsets.partition_point(|o| o.start < r.end);
+    Span {
+        start,
+        len: end.max(start + 1) - start,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Byte ranges of the tokens of "the dog barks".
+    const OFFSETS: &[Range<usize>] = &[0..3, 4..7, 8..13];
+
+    #[test]
+    fn covering_span_of_tokens() {
+        assert_eq!(covering_span(OFFSETS, 0..3), Span { start: 0, len: 1 });
+        assert_eq!(covering_span(OFFSETS, 4..13), Span { start: 1, len: 2 });
+        assert_eq!(covering_span(OFFSETS, 0..13), Span { start: 0, len: 3 });
+    }
+
+    #[test]
+    fn covering_span_of_partial_tokens() {
+        assert_eq!(covering_span(OFFSETS, 5..6), Span { start: 1, len: 1 });
+        assert_eq!(covering_span(OFFSETS, 2..9), Span { start: 0, len: 3 });
+    }
+
+    #[test]
+    fn covering_span_between_tokens() {
+        // the space after "dog" is covered by the next token
+        assert_eq!(covering_span(OFFSETS, 7..8), Span { start: 2, len: 1 });
+    }
+}
@@ -0,0 +1,64 @@
+use crate::{Coha, Token};
+use serde::{Deserialize, Serialize};
+use std::fs;
+
+/// Rough memory needed per token of the text that a worker is searching:
+/// the token itself plus the running text and offsets built for regular
+/// expression searches.
+const WORKER_BYTES_PER_TOKEN: u64 = std::mem::size_of::<Token>() as u64 + 64;
+/// Memory needed per worker independently of the text, e.g. I/O buffers.
+const WORKER_BYTES_FIXED: u64 = 1 << 20;
+
+/// Peak resident memory of the process in each phase, as reported by the
+/// operating system; `None` where this is not available (e.g. outside
+/// Linux).
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct MemoryUsage {
+    /// Peak while loading the corpus.
+    pub load_peak_bytes: Option<u64>,
+    /// Peak while searching. Includes the memory of the loaded corpus.
+    pub search_peak_bytes: Option<u64>,
+}
+
+fn proc_status(field: &str) -> Option<u64> {
+    let status = fs::read_to_string("/proc/self/status").ok()?;
+    let line = status.lines().find(|l| l.starts_with(field))?;
+    let kb: u64 = line[field.len()..]
+        .trim()
+        .strip_suffix("kB")?
+        .trim()
+        .parse()
+        .ok()?;
+    Some(kb * 1024)
+}
+
+pub(crate) fn peak_rss() -> Option<u64> {
+    proc_status("VmHWM:")
+}
+
+pub(crate) fn current_rss() -> Option<u64> {
+    proc_status("VmRSS:")
+}
+
+/// Reset the peak so that the next phase is measured separately. If this
+/// is not possible, the peak of the next phase includes earlier phases.
+pub(crate) fn reset_peak() {
+    let _ = fs::write("/proc/self/clear_refs", "5");
+}
+
+impl Coha {
+    /// Estimated memory needed by one search worker: enough to buffer the
+    /// largest text.
+    pub fn worker_memory_estimate(&self) -> u64 {
+        let largest = self.sources.values().map(|s| s.words).max().unwrap_or(0);
+        largest as u64 * WORKER_BYTES_PER_TOKEN + WORKER_BYTES_FIXED
+    }
+
+    /// The number of corpus files that can be searched concurrently within
+    /// `budget` bytes in addition to the memory already in use; at least one.
+    pub(crate) fn workers_for_budget(&self, budget: u64) -> usize {
+        let available = budget.saturating_sub(current_rss().unwrap_or(0));
+        let workers = (available / self.worker_memory_estimate()) as usize;
+        workers.clamp(1, self.coha_files.len().max(1))
+    }
+}
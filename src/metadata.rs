@@ -0,0 +1,72 @@
+use crate::{Coha, TextId};
+use anyhow::{bail, Result};
+use log::{debug, info};
+use rustc_hash::FxHashMap;
+use std::path::Path;
+
+/// Extra per-text metadata supplied by the user, e.g. author gender or a
+/// custom genre classification.
+///
+/// The metadata is read from a CSV file with a header; the first column is
+/// the COHA text ID and the remaining columns are copied to the output.
+#[derive(Debug, Clone, Default)]
+pub struct Metadata {
+    columns: Vec<String>,
+    rows: FxHashMap<TextId, Vec<String>>,
+}
+
+impl Metadata {
+    pub fn read(path: &Path) -> Result<Self> {
+        debug!("{}: reading...", path.to_string_lossy());
+        let mut reader = csv::Reader::from_path(path)?;
+        let header = reader.headers()?;
+        if header.is_empty() {
+            bail!("{}: header missing", path.to_string_lossy());
+        }
+        let columns: Vec<String> = header.iter().skip(1).map(|x| x.to_owned()).collect();
+        let mut rows = FxHashMap::default();
+        for record in reader.records() {
+            let record = record?;
+            let text_id = match record.get(0).map(|x| x.trim().parse()) {
+                Some(Ok(x)) => TextId(x),
+                _ => bail!("{}: invalid text ID", path.to_string_lossy()),
+            };
+            let mut values: Vec<String> = record.iter().skip(1).map(|x| x.to_owned()).collect();
+            values.resize(columns.len(), String::new());
+            if rows.insert(text_id, values).is_some() {
+                bail!(
+                    "{}: duplicate text ID {}",
+                    path.to_string_lossy(),
+                    text_id.0
+                );
+            }
+        }
+        info!(
+            "{}: {} columns for {} texts",
+            path.to_string_lossy(),
+            columns.len(),
+            rows.len()
+        );
+        Ok(Self { columns, rows })
+    }
+
+    pub fn columns(&self) -> &[String] {
+        &self.columns
+    }
+
+    /// The values for a text; empty strings if the text is not listed.
+    pub fn values(&self, text_id: TextId) -> Vec<&str> {
+        match self.rows.get(&text_id) {
+            Some(values) => values.iter().map(|x| x.as_str()).collect(),
+            None => vec![""; self.columns.len()],
+        }
+    }
+}
+
+impl Coha {
+    /// Attach user-supplied metadata; its columns are added to every hit and
+    /// to the hit counts.
+    pub fn attach_metadata(&mut self, metadata: Metadata) {
+        self.metadata = metadata;
+    }
+}
@@ -0,0 +1,143 @@
+use crate::matcher::{attaches_left, attaches_right};
+use serde::Serialize;
+use unicode_normalization::UnicodeNormalization;
+
+/// Unicode normalization form applied to lexicon strings.
+#[derive(Copy, Debug, Clone, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum NormalizationForm {
+    Nfc,
+    Nfkc,
+}
+
+/// Replace typographic quotes with their ASCII equivalents.
+pub fn fold_quotes(s: &str) -> String {
+    s.chars()
+        .map(|c| match c {
+            '\u{2018}' | '\u{2019}' | '\u{201A}' | '\u{201B}' | '\u{2032}' => '\'',
+            '\u{201C}' | '\u{201D}' | '\u{201E}' | '\u{201F}' | '\u{2033}' => '"',
+            '\u{00AB}' | '\u{00BB}' => '"',
+            _ => c,
+        })
+        .collect()
+}
+
+/// A token for publication: typographic quotes become ASCII quotes and
+/// dashes written as "--" become em dashes.
+pub(crate) fn tidy_word(word: &str) -> String {
+    fold_quotes(word).replace("--", "\u{2014}")
+}
+
+/// Tokens joined as running text for publication: see [`tidy_word`];
+/// punctuation and contractions are attached to the preceding token, em
+/// dashes to both neighbours, and double quotes alternately to the
+/// following and the preceding token.
+pub(crate) fn tidy_text<'a>(words: impl Iterator<Item = &'a str>) -> String {
+    let mut text = String::new();
+    let mut prev_right = true;
+    let mut open_quote = false;
+    for word in words {
+        let word = tidy_word(word);
+        let (left, right) = match word.as_str() {
+            "\"" => {
+                open_quote = !open_quote;
+                (!open_quote, open_quote)
+            }
+            "\u{2014}" => (true, true),
+            w => (attaches_left(w), attaches_right(w)),
+        };
+        if !prev_right && !left {
+            text.push(' ');
+        }
+        text.push_str(&word);
+        prev_right = right;
+    }
+    text
+}
+
+pub(crate) fn normalize(s: &str, form: Option<NormalizationForm>, quotes: bool) -> String {
+    let s = match form {
+        None => s.to_owned(),
+        Some(NormalizationForm::Nfc) => s.nfc().collect(),
+        Some(NormalizationForm::Nfkc) => s.nfkc().collect(),
+    };
+    if quotes {
+        fold_quotes(&s)
+    } else {
+        s
+    }
+}
+
+/// How junk in the lexicon is cleaned up at load time.
+///
+/// Junk means control characters in word forms and lemmas, backslash escapes
+/// such as `\'`, and missing lemmas (empty or `NULL`).
+#[derive(Copy, Debug, Clone, Default, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CleanupPolicy {
+    /// Remove control characters from word forms; lemmas and the sources
+    /// table are kept as they are.
+    #[default]
+    WordForms,
+    /// Remove control characters and escapes; a missing lemma is replaced by
+    /// the word form.
+    Strip,
+    /// Replace each control character and each missing lemma with the given
+    /// placeholder; escapes are removed.
+    Replace(char),
+    /// Keep everything as in the lexicon file.
+    Keep,
+}
+
+fn unescape(s: &str) -> String {
+    let mut result = String::with_capacity(s.len());
+    let mut chars = s.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c == '\\' {
+            if let Some(&next) = chars.peek() {
+                if matches!(next, '\'' | '"' | '\\') {
+                    result.push(next);
+                    chars.next();
+                    continue;
+                }
+            }
+        }
+        result.push(c);
+    }
+    result
+}
+
+fn is_null_lemma(s: &str) -> bool {
+    s.is_empty() || s == "NULL"
+}
+
+impl CleanupPolicy {
+    pub(crate) fn clean(&self, s: &str) -> String {
+        match self {
+            CleanupPolicy::WordForms | CleanupPolicy::Keep => s.to_owned(),
+            CleanupPolicy::Strip => unescape(&s.replace(|c: char| c.is_control(), "")),
+            CleanupPolicy::Replace(p) => unescape(
+                &s.chars()
+                    .map(|c| if c.is_control() { *p } else { c })
+                    .collect::<String>(),
+            ),
+        }
+    }
+
+    /// Clean a word form; control characters are removed unless the policy
+    /// is [`CleanupPolicy::Keep`].
+    pub(crate) fn clean_word(&self, s: &str) -> String {
+        match self {
+            CleanupPolicy::WordForms => s.replace(|c: char| c.is_control(), ""),
+            _ => self.clean(s),
+        }
+    }
+
+    pub(crate) fn clean_lemma(&self, lemma: &str, word: &str) -> String {
+        match self {
+            CleanupPolicy::Strip if is_null_lemma(lemma) => word.to_owned(),
+            CleanupPolicy::Replace(p) if is_null_lemma(lemma) => p.to_string(),
+            _ => self.clean(lemma),
+        }
+    }
+}
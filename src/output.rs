@@ -0,0 +1,576 @@
+use crate::duplicates::Fingerprint;
+use crate::sink::PARTIAL_SUFFIX;
+use crate::{
+    CohaSearch, FileSink, FileStats, HitOrder, OutputSink, SearchCounts, SearchOptions, SinkWriter,
+};
+use anyhow::{bail, Result};
+use itertools::Itertools;
+use log::debug;
+use rustc_hash::FxHashMap;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+/// Per-corpus-file parts of result files that span several corpus files,
+/// `{result dir}/.parts/{identifier}/{file}`. Parts are always CSV.
+const PARTS_DIR: &str = ".parts";
+
+pub(crate) struct HitRow {
+    pub filler: String,
+    /// Key for the concordance orders, e.g. the words after the hit.
+    pub sort_key: Vec<String>,
+    /// Signature of the context, for [`SearchOptions::near_duplicates`].
+    pub fingerprint: Option<Fingerprint>,
+    /// A near-duplicate of an earlier hit.
+    pub duplicate: bool,
+    /// The result file of this hit, relative to the result directory.
+    pub file: String,
+    pub record: Vec<String>,
+}
+
+/// Format of a result file, chosen by its extension.
+#[derive(Copy, Debug, Clone, Eq, PartialEq)]
+pub(crate) enum FileFormat {
+    Csv,
+    /// One JSON object per line, with the header fields as keys.
+    Jsonl,
+    /// Tab-separated values with a header.
+    Tsv,
+    /// Tab-separated values without a header or quoting; tabs and line
+    /// breaks within fields are replaced by spaces.
+    Text,
+}
+
+impl FileFormat {
+    pub fn from_path(path: &str) -> Result<Self> {
+        match Path::new(path).extension().and_then(|e| e.to_str()) {
+            Some("csv") => Ok(FileFormat::Csv),
+            Some("jsonl") => Ok(FileFormat::Jsonl),
+            Some("tsv") => Ok(FileFormat::Tsv),
+            Some("txt") => Ok(FileFormat::Text),
+            _ => bail!("{path}: result files must end in .csv, .jsonl, .tsv, or .txt"),
+        }
+    }
+}
+
+/// A result file in a sink and the number of bytes written to it, so that
+/// it can be resumed at the same point, see [`OutputSink::resume`].
+struct Counted {
+    writer: Box<dyn SinkWriter>,
+    bytes: u64,
+}
+
+impl Write for Counted {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let n = self.writer.write(buf)?;
+        self.bytes += n as u64;
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.writer.flush()
+    }
+}
+
+enum RecordWriter {
+    Csv(Box<csv::Writer<Counted>>),
+    Jsonl(BufWriter<Counted>),
+    Text(BufWriter<Counted>),
+}
+
+impl RecordWriter {
+    /// Start the result file `file` in `sink`, see [`RecordWriter::finish`].
+    fn create(
+        sink: &dyn OutputSink,
+        file: &Path,
+        header: &[String],
+        format: FileFormat,
+        buffer_bytes: usize,
+    ) -> Result<Self> {
+        let writer = Counted {
+            writer: sink.create(file)?,
+            bytes: 0,
+        };
+        Self::new(writer, Some(header), format, buffer_bytes)
+    }
+
+    /// Write records to `writer`, starting with `header` if given.
+    fn new(
+        writer: Counted,
+        header: Option<&[String]>,
+        format: FileFormat,
+        buffer_bytes: usize,
+    ) -> Result<Self> {
+        match format {
+            FileFormat::Csv | FileFormat::Tsv => {
+                let delimiter = if format == FileFormat::Tsv {
+                    b'\t'
+                } else {
+                    b','
+                };
+                let mut writer = csv::WriterBuilder::new()
+                    .delimiter(delimiter)
+                    .buffer_capacity(buffer_bytes)
+                    .from_writer(writer);
+                if let Some(header) = header {
+                    writer.write_record(header)?;
+                }
+                Ok(RecordWriter::Csv(Box::new(writer)))
+            }
+            FileFormat::Jsonl => Ok(RecordWriter::Jsonl(BufWriter::with_capacity(
+                buffer_bytes,
+                writer,
+            ))),
+            FileFormat::Text => Ok(RecordWriter::Text(BufWriter::with_capacity(
+                buffer_bytes,
+                writer,
+            ))),
+        }
+    }
+
+    fn write_record(&mut self, header: &[String], record: &[String]) -> Result<()> {
+        match self {
+            RecordWriter::Csv(writer) => writer.write_record(record)?,
+            RecordWriter::Jsonl(writer) => {
+                // written by hand to keep the fields in header order
+                writer.write_all(b"{")?;
+                for (i, (key, value)) in header.iter().zip(record).enumerate() {
+                    if i > 0 {
+                        writer.write_all(b",")?;
+                    }
+                    serde_json::to_writer(&mut *writer, key)?;
+                    writer.write_all(b":")?;
+                    serde_json::to_writer(&mut *writer, value)?;
+                }
+                writer.write_all(b"}\n")?;
+            }
+            RecordWriter::Text(writer) => {
+                for (i, value) in record.iter().enumerate() {
+                    if i > 0 {
+                        writer.write_all(b"\t")?;
+                    }
+                    writer.write_all(value.replace(['\t', '\r', '\n'], " ").as_bytes())?;
+                }
+                writer.write_all(b"\n")?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Write out the buffer.
+    fn into_inner(self) -> Result<Counted> {
+        Ok(match self {
+            RecordWriter::Csv(writer) => writer.into_inner().map_err(|e| e.into_error())?,
+            RecordWriter::Jsonl(writer) | RecordWriter::Text(writer) => {
+                writer.into_inner().map_err(|e| e.into_error())?
+            }
+        })
+    }
+
+    /// Write out the buffer and make the file durable, see
+    /// [`SinkWriter::sync`]; returns the writer and the size of the file.
+    fn sync(self, format: FileFormat, buffer_bytes: usize) -> Result<(Self, u64)> {
+        let mut writer = self.into_inner()?;
+        writer.writer.sync()?;
+        let bytes = writer.bytes;
+        Ok((Self::new(writer, None, format, buffer_bytes)?, bytes))
+    }
+
+    /// Write out the buffer and complete the file.
+    fn finish(self) -> Result<()> {
+        self.into_inner()?.writer.finish()
+    }
+}
+
+/// How far a result file had been written when the progress of a run was
+/// recorded, see [`OutputFiles::progress`].
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct WrittenFile {
+    /// The result file, as in [`OutputFiles::open`].
+    file: String,
+    chunk: usize,
+    rows: usize,
+    /// Size of the current chunk.
+    bytes: u64,
+}
+
+/// A result file that is optionally split into numbered chunks of at most
+/// `max_rows` rows each, every chunk starting with the header.
+pub(crate) struct ChunkedWriter {
+    sink: Arc<dyn OutputSink>,
+    /// The file relative to the result directory.
+    path: PathBuf,
+    format: FileFormat,
+    header: Vec<String>,
+    max_rows: Option<usize>,
+    buffer_bytes: usize,
+    chunk: usize,
+    rows: usize,
+    writer: RecordWriter,
+}
+
+impl ChunkedWriter {
+    pub fn new(
+        sink: Arc<dyn OutputSink>,
+        path: &Path,
+        format: FileFormat,
+        header: Vec<String>,
+        max_rows: Option<usize>,
+        buffer_bytes: usize,
+    ) -> Result<Self> {
+        let path_1 = chunk_path(path, max_rows, 1);
+        let writer = RecordWriter::create(&*sink, &path_1, &header, format, buffer_bytes)?;
+        Ok(Self {
+            sink,
+            path: path.to_owned(),
+            format,
+            header,
+            max_rows,
+            buffer_bytes,
+            chunk: 1,
+            rows: 0,
+            writer,
+        })
+    }
+
+    pub fn write_record(&mut self, record: Vec<String>) -> Result<()> {
+        if let Some(max_rows) = self.max_rows {
+            if self.rows >= max_rows {
+                self.chunk += 1;
+                self.rows = 0;
+                let path = chunk_path(&self.path, self.max_rows, self.chunk);
+                let writer = RecordWriter::create(
+                    &*self.sink,
+                    &path,
+                    &self.header,
+                    self.format,
+                    self.buffer_bytes,
+                )?;
+                let prev = std::mem::replace(&mut self.writer, writer);
+                prev.finish()?;
+            }
+        }
+        self.writer.write_record(&self.header, &record)?;
+        self.rows += 1;
+        Ok(())
+    }
+
+    /// Continue a result file that had been written as far as `written`;
+    /// `None` if the sink cannot resume files.
+    fn resume(
+        sink: Arc<dyn OutputSink>,
+        path: &Path,
+        format: FileFormat,
+        header: Vec<String>,
+        max_rows: Option<usize>,
+        buffer_bytes: usize,
+        written: &WrittenFile,
+    ) -> Result<Option<Self>> {
+        let chunk_path = chunk_path(path, max_rows, written.chunk);
+        let Some(writer) = sink.resume(&chunk_path, written.bytes)? else {
+            return Ok(None);
+        };
+        let writer = Counted {
+            writer,
+            bytes: written.bytes,
+        };
+        let writer = RecordWriter::new(writer, None, format, buffer_bytes)?;
+        Ok(Some(Self {
+            sink,
+            path: path.to_owned(),
+            format,
+            header,
+            max_rows,
+            buffer_bytes,
+            chunk: written.chunk,
+            rows: written.rows,
+            writer,
+        }))
+    }
+
+    /// Make the rows written so far durable, see [`OutputFiles::progress`].
+    fn sync(self, file: &str) -> Result<(Self, WrittenFile)> {
+        let (writer, bytes) = self.writer.sync(self.format, self.buffer_bytes)?;
+        let written = WrittenFile {
+            file: file.to_owned(),
+            chunk: self.chunk,
+            rows: self.rows,
+            bytes,
+        };
+        Ok((Self { writer, ..self }, written))
+    }
+
+    /// Complete the current chunk.
+    pub fn finish(self) -> Result<()> {
+        self.writer.finish()
+    }
+}
+
+/// `dir/stem.ext`, or `dir/stem-001.ext` etc. if the file is split into
+/// chunks.
+fn chunk_path(path: &Path, max_rows: Option<usize>, chunk: usize) -> PathBuf {
+    if max_rows.is_none() {
+        return path.to_owned();
+    }
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    let name = match path.extension() {
+        Some(ext) => format!("{stem}-{chunk:03}.{}", ext.to_string_lossy()),
+        None => format!("{stem}-{chunk:03}"),
+    };
+    path.with_file_name(name)
+}
+
+/// The result files of one search in one corpus file, opened as needed.
+pub(crate) struct OutputFiles {
+    result_dir: PathBuf,
+    sink: Arc<dyn OutputSink>,
+    header: Vec<String>,
+    max_rows: Option<usize>,
+    buffer_bytes: usize,
+    /// Write parts for this corpus file, to be merged by [`merge_parts`].
+    part: Option<String>,
+    writers: BTreeMap<String, ChunkedWriter>,
+}
+
+impl OutputFiles {
+    pub fn new(
+        result_dir: &Path,
+        sink: Arc<dyn OutputSink>,
+        header: Vec<String>,
+        max_rows: Option<usize>,
+        buffer_bytes: usize,
+        part: Option<&str>,
+    ) -> Self {
+        Self {
+            result_dir: result_dir.to_owned(),
+            sink,
+            header,
+            max_rows,
+            buffer_bytes,
+            part: part.map(|p| p.to_owned()),
+            writers: BTreeMap::new(),
+        }
+    }
+
+    /// Where the result file `file` is written: the sink, the path in it,
+    /// the format, and the maximum number of rows per chunk.
+    #[allow(clippy::type_complexity)]
+    fn target(
+        &self,
+        file: &str,
+    ) -> Result<(Arc<dyn OutputSink>, PathBuf, FileFormat, Option<usize>)> {
+        Ok(match &self.part {
+            None => (
+                self.sink.clone(),
+                PathBuf::from(file),
+                FileFormat::from_path(file)?,
+                self.max_rows,
+            ),
+            // parts are local files, as they are read back
+            Some(part) => (
+                Arc::new(FileSink::new(&self.result_dir)),
+                Path::new(PARTS_DIR).join(part).join(file),
+                FileFormat::Csv,
+                None,
+            ),
+        })
+    }
+
+    /// The writer of the result file `file`; the file is created if needed.
+    pub fn open(&mut self, file: &str) -> Result<&mut ChunkedWriter> {
+        if !self.writers.contains_key(file) {
+            let (sink, path, format, max_rows) = self.target(file)?;
+            let writer = ChunkedWriter::new(
+                sink,
+                &path,
+                format,
+                self.header.clone(),
+                max_rows,
+                self.buffer_bytes,
+            )?;
+            self.writers.insert(file.to_owned(), writer);
+        }
+        Ok(self.writers.get_mut(file).expect("writer was just opened"))
+    }
+
+    /// Make everything written so far durable and return how far each
+    /// result file has been written, for [`OutputFiles::resume`].
+    pub fn progress(&mut self) -> Result<Vec<WrittenFile>> {
+        let mut written = Vec::new();
+        for (file, writer) in std::mem::take(&mut self.writers) {
+            let (writer, w) = writer.sync(&file)?;
+            self.writers.insert(file, writer);
+            written.push(w);
+        }
+        Ok(written)
+    }
+
+    /// Continue the result files that had been written as far as
+    /// `written`; false if the sink cannot resume files.
+    pub fn resume(&mut self, written: &[WrittenFile]) -> Result<bool> {
+        for w in written {
+            let (sink, path, format, max_rows) = self.target(&w.file)?;
+            let header = self.header.clone();
+            let buffer_bytes = self.buffer_bytes;
+            let Some(writer) =
+                ChunkedWriter::resume(sink, &path, format, header, max_rows, buffer_bytes, w)?
+            else {
+                return Ok(false);
+            };
+            self.writers.insert(w.file.clone(), writer);
+        }
+        Ok(true)
+    }
+
+    pub fn write_record(&mut self, file: &str, record: Vec<String>) -> Result<()> {
+        self.open(file)?.write_record(record)
+    }
+
+    /// Complete all result files; they must not be written to again.
+    pub fn finish(&mut self) -> Result<()> {
+        for writer in std::mem::take(&mut self.writers).into_values() {
+            writer.finish()?;
+        }
+        Ok(())
+    }
+}
+
+/// All complete files below `dir`, relative to `base`.
+fn files_below(dir: &Path, base: &Path, files: &mut Vec<PathBuf>) -> Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            files_below(&path, base, files)?;
+        } else if !path.to_string_lossy().ends_with(PARTIAL_SUFFIX) {
+            files.push(path.strip_prefix(base)?.to_owned());
+        }
+    }
+    Ok(())
+}
+
+/// Remove the parts left by an earlier run, except those of the corpus
+/// files for which `keep` is true.
+pub(crate) fn clear_parts(result_dir: &Path, keep: impl Fn(&str) -> bool) -> Result<()> {
+    let parts_dir = result_dir.join(PARTS_DIR);
+    if !parts_dir.is_dir() {
+        return Ok(());
+    }
+    for entry in fs::read_dir(&parts_dir)? {
+        let entry = entry?;
+        if !keep(&entry.file_name().to_string_lossy()) {
+            debug!("{}: removing...", entry.path().to_string_lossy());
+            fs::remove_dir_all(entry.path())?;
+        }
+    }
+    Ok(())
+}
+
+/// Merge the parts written by [`OutputFiles`] into one result file each in
+/// `sink`, in the order of the corpus file identifiers.
+pub(crate) fn merge_parts(
+    result_dir: &Path,
+    sink: &Arc<dyn OutputSink>,
+    max_rows: Option<usize>,
+    buffer_bytes: usize,
+) -> Result<()> {
+    let parts_dir = result_dir.join(PARTS_DIR);
+    if !parts_dir.is_dir() {
+        return Ok(());
+    }
+    let mut part_dirs = Vec::new();
+    for entry in fs::read_dir(&parts_dir)? {
+        part_dirs.push(entry?.path());
+    }
+    part_dirs.sort();
+    let mut parts: BTreeMap<PathBuf, Vec<PathBuf>> = BTreeMap::new();
+    for part_dir in part_dirs {
+        let mut files = Vec::new();
+        files_below(&part_dir, &part_dir, &mut files)?;
+        for file in files {
+            let part = part_dir.join(&file);
+            parts.entry(file).or_default().push(part);
+        }
+    }
+    for (file, part_files) in parts {
+        let format = FileFormat::from_path(&file.to_string_lossy())?;
+        let mut writer: Option<ChunkedWriter> = None;
+        for part in part_files {
+            debug!("{}: reading...", part.to_string_lossy());
+            let mut reader = csv::Reader::from_path(&part)?;
+            if writer.is_none() {
+                let header = reader.headers()?.iter().map(|x| x.to_owned()).collect();
+                writer = Some(ChunkedWriter::new(
+                    sink.clone(),
+                    &file,
+                    format,
+                    header,
+                    max_rows,
+                    buffer_bytes,
+                )?);
+            }
+            let writer = writer.as_mut().expect("writer was just opened");
+            for record in reader.records() {
+                writer.write_record(record?.iter().map(|x| x.to_owned()).collect())?;
+            }
+        }
+        if let Some(writer) = writer {
+            writer.finish()?;
+        }
+    }
+    fs::remove_dir_all(parts_dir)?;
+    Ok(())
+}
+
+pub(crate) struct SearchOutput {
+    pub files: Option<OutputFiles>,
+    pub pending: Vec<HitRow>,
+    pub counts: SearchCounts,
+    pub stats: FileStats,
+}
+
+impl SearchOutput {
+    pub fn push(&mut self, options: &SearchOptions, row: HitRow) -> Result<()> {
+        match options.order {
+            HitOrder::Corpus if options.near_duplicates.is_none() => self.write(row)?,
+            _ => self.pending.push(row),
+        }
+        Ok(())
+    }
+
+    pub fn write(&mut self, row: HitRow) -> Result<()> {
+        if let Some(files) = &mut self.files {
+            files.write_record(&row.file, row.record)?;
+        }
+        Ok(())
+    }
+
+    pub fn finish(&mut self) -> Result<()> {
+        if let Some(files) = &mut self.files {
+            files.finish()?;
+        }
+        Ok(())
+    }
+}
+
+pub(crate) fn write_filler_table(
+    result_dir: &Path,
+    search: &CohaSearch,
+    freq: &FxHashMap<String, usize>,
+) -> Result<()> {
+    let outpath = result_dir.join(&search.label);
+    let outpath = outpath.join(format!("{}-fillers.csv", &search.label));
+    debug!("{}: writing...", outpath.to_string_lossy());
+    let mut writer = csv::Writer::from_path(outpath)?;
+    writer.write_record(["filler", "hits"])?;
+    for (filler, hits) in freq
+        .iter()
+        .sorted_by(|a, b| b.1.cmp(a.1).then_with(|| a.0.cmp(b.0)))
+    {
+        writer.write_record([filler.as_str(), &hits.to_string()])?;
+    }
+    writer.flush()?;
+    Ok(())
+}
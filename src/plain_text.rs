@@ -0,0 +1,166 @@
+use crate::sentences::Sentences;
+use crate::{hit_id, Coha, CohaSearch, Token};
+use anyhow::Result;
+use log::{debug, info};
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::fs::{self, File};
+use std::io::{BufWriter, Write};
+use std::ops::Range;
+use std::path::Path;
+
+/// The text written for each hit by [`Coha::export_plain_text`].
+#[derive(Copy, Debug, Clone, Default, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PlainTextSpan {
+    /// The sentence (or sentences) that contain the hit.
+    #[default]
+    Sentence,
+    /// Only the matched tokens.
+    Match,
+    /// The matched tokens and this many tokens on each side.
+    Context(usize),
+}
+
+/// Options of [`Coha::export_plain_text`].
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PlainTextOptions {
+    pub span: PlainTextSpan,
+}
+
+impl PlainTextSpan {
+    /// The tokens written for the tokens `hit` of a text of `len` tokens.
+    fn range(&self, sentences: Option<&Sentences>, len: usize, hit: Range<usize>) -> Range<usize> {
+        match self {
+            PlainTextSpan::Match => hit,
+            PlainTextSpan::Context(n) => hit.start.saturating_sub(*n)..len.min(hit.end + n),
+            PlainTextSpan::Sentence => {
+                let sentences = sentences.expect("sentences are segmented");
+                let mut ranges = sentences.ranges(len);
+                let first = ranges.find(|r| r.end > hit.start);
+                let start = first.as_ref().map_or(hit.start, |r| r.start.min(hit.start));
+                let mut end = first.map_or(hit.end, |r| r.end);
+                for r in ranges {
+                    if end >= hit.end {
+                        break;
+                    }
+                    end = r.end;
+                }
+                start..end.max(hit.end)
+            }
+        }
+    }
+}
+
+impl Coha {
+    /// One line of running text, without paragraph and heading markers,
+    /// and the character range of `hit` in it.
+    fn plain_line(&self, tokens: &[Token], hit: Range<usize>) -> (String, Range<usize>) {
+        let (positions, kept): (Vec<usize>, Vec<Token>) = tokens
+            .iter()
+            .enumerate()
+            .filter(|(_, t)| !self.is_marker(t))
+            .map(|(i, t)| (i, t.clone()))
+            .unzip();
+        let (line, offsets) = self.surface_text(&kept);
+        let chars = |byte: usize| line[..byte].chars().count();
+        let in_hit: Vec<&Range<usize>> = positions
+            .iter()
+            .zip(&offsets)
+            .filter(|(i, _)| hit.contains(i))
+            .map(|(_, o)| o)
+            .collect();
+        let range = match (in_hit.first(), in_hit.last()) {
+            (Some(first), Some(last)) => chars(first.start)..chars(last.end),
+            _ => 0..0,
+        };
+        (line, range)
+    }
+
+    /// Write the hits of each search as plain UTF-8 text for external
+    /// tools such as parsers, taggers, or annotation workflows: for each
+    /// search and corpus file, `{dir}/{label}/{label}-{decade}.txt` with the
+    /// sentence (or other span, see [`PlainTextSpan`]) of one hit per line,
+    /// and `{label}-{decade}-ids.tsv` with the hit ID (see
+    /// [`crate::SearchOptions::hit_id_column`]), the text ID, and the
+    /// character range of the match within the line, one row per line of
+    /// the text file. Paragraph and heading markers are left out. This
+    /// reads all corpus files.
+    pub fn export_plain_text(
+        &self,
+        dir: &Path,
+        searches: &[&CohaSearch],
+        options: &PlainTextOptions,
+    ) -> Result<()> {
+        for search in searches {
+            fs::create_dir_all(dir.join(&search.label))?;
+        }
+        let lenient = self.load_options.lenient;
+        let lines = self
+            .coha_files
+            .par_iter()
+            .map(|cf| {
+                let mut writers = Vec::new();
+                for search in searches {
+                    let dir = dir.join(&search.label);
+                    let name = format!("{}-{}", search.label, cf.identifier);
+                    let text_path = dir.join(format!("{name}.txt"));
+                    let ids_path = dir.join(format!("{name}-ids.tsv"));
+                    debug!("{}: writing...", text_path.to_string_lossy());
+                    let text = BufWriter::new(File::create(text_path)?);
+                    let mut ids = BufWriter::new(File::create(ids_path)?);
+                    writeln!(ids, "hit ID\ttext ID\tstart\tend")?;
+                    writers.push((text, ids));
+                }
+                let mut lines = 0;
+                cf.scan_texts(lenient, |text_id, tokens| {
+                    let Some(source) = self.sources.get(&text_id) else {
+                        return Ok(());
+                    };
+                    let mut sentences = None;
+                    for (search, (text, ids)) in searches.iter().zip(&mut writers) {
+                        if !search.restrictions.allows(source) {
+                            continue;
+                        }
+                        let spans = self.find_spans(search, tokens);
+                        if options.span == PlainTextSpan::Sentence && !spans.is_empty() {
+                            sentences.get_or_insert_with(|| Sentences::new(self, tokens));
+                        }
+                        for span in spans {
+                            let hit = span.start..span.start + span.len;
+                            let range =
+                                options
+                                    .span
+                                    .range(sentences.as_ref(), tokens.len(), hit.clone());
+                            let hit = hit.start - range.start..hit.end - range.start;
+                            let (line, chars) = self.plain_line(&tokens[range], hit);
+                            writeln!(text, "{line}")?;
+                            writeln!(
+                                ids,
+                                "{}\t{}\t{}\t{}",
+                                hit_id(text_id, span.start, &search.label),
+                                text_id.0,
+                                chars.start,
+                                chars.end
+                            )?;
+                            lines += 1;
+                        }
+                    }
+                    Ok(())
+                })?;
+                for (mut text, mut ids) in writers {
+                    text.flush()?;
+                    ids.flush()?;
+                }
+                Ok(lines)
+            })
+            .collect::<Result<Vec<usize>>>()?;
+        info!(
+            "{}: {} lines",
+            dir.to_string_lossy(),
+            lines.iter().sum::<usize>()
+        );
+        Ok(())
+    }
+}
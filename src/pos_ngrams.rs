@@ -0,0 +1,185 @@
+use crate::Coha;
+use anyhow::{bail, Result};
+use itertools::Itertools;
+use log::info;
+use rayon::prelude::*;
+use rustc_hash::FxHashMap;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::io::Write;
+
+/// Options of [`Coha::pos_ngrams`].
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PosNgramOptions {
+    /// Number of tags per n-gram.
+    pub n: usize,
+    /// Leave out n-grams that occur fewer times than this in a (decade,
+    /// genre); they are still included in the totals.
+    pub min_count: u64,
+}
+
+impl Default for PosNgramOptions {
+    fn default() -> Self {
+        Self { n: 3, min_count: 1 }
+    }
+}
+
+/// Number of occurrences of a POS n-gram in one (decade, genre).
+#[derive(Debug, Clone, Serialize)]
+pub struct PosNgramCount {
+    pub decade: String,
+    pub genre: String,
+    /// Tags separated by spaces, e.g. "at jj nn1".
+    pub ngram: String,
+    pub count: u64,
+}
+
+/// POS n-gram frequencies per decade and genre, see [`Coha::pos_ngrams`].
+#[derive(Debug, Clone)]
+pub struct PosNgramProfile {
+    pub n: usize,
+    /// Sorted by decade, genre, and n-gram.
+    pub counts: Vec<PosNgramCount>,
+    /// Number of n-grams in each (decade, genre).
+    pub totals: BTreeMap<(String, String), u64>,
+}
+
+impl PosNgramProfile {
+    /// Number of occurrences per million n-grams of the same (decade,
+    /// genre).
+    pub fn per_million(&self, count: &PosNgramCount) -> f64 {
+        let total = self
+            .totals
+            .get(&(count.decade.clone(), count.genre.clone()))
+            .copied()
+            .unwrap_or(0);
+        if total == 0 {
+            0.0
+        } else {
+            count.count as f64 * 1e6 / total as f64
+        }
+    }
+
+    /// Write the counts as CSV, in long format with one row per (decade,
+    /// genre, n-gram).
+    pub fn write<W: Write>(&self, writer: W) -> Result<()> {
+        let mut writer = csv::Writer::from_writer(writer);
+        writer.write_record(["decade", "genre", "ngram", "count", "per million"])?;
+        for c in &self.counts {
+            writer.write_record([
+                c.decade.clone(),
+                c.genre.clone(),
+                c.ngram.clone(),
+                c.count.to_string(),
+                format!("{:.3}", self.per_million(c)),
+            ])?;
+        }
+        writer.flush()?;
+        Ok(())
+    }
+}
+
+type Counts = FxHashMap<(String, String), FxHashMap<Vec<u32>, u64>>;
+
+impl Coha {
+    /// Count the n-grams of POS tags in each decade and genre, independent
+    /// of any search, e.g. as a syntactic profile of the corpus or as a
+    /// baseline for construction frequencies. N-grams do not span texts or
+    /// removed tokens (`@`). This reads all corpus files.
+    pub fn pos_ngrams(&self, options: &PosNgramOptions) -> Result<PosNgramProfile> {
+        if options.n == 0 {
+            bail!("POS n-grams: n must be positive");
+        }
+        let mut tags: Vec<&str> = Vec::new();
+        let mut tag_ids: FxHashMap<&str, u32> = FxHashMap::default();
+        let tag_of: Vec<Option<u32>> = self
+            .lexicon
+            .iter()
+            .map(|w| {
+                w.as_ref().map(|w| {
+                    *tag_ids.entry(&w.pos).or_insert_with(|| {
+                        tags.push(&w.pos);
+                        tags.len() as u32 - 1
+                    })
+                })
+            })
+            .collect();
+        let lenient = self.load_options.lenient;
+        let per_file = self
+            .coha_files
+            .par_iter()
+            .map(|cf| {
+                let mut counts = Counts::default();
+                let mut totals: FxHashMap<(String, String), u64> = FxHashMap::default();
+                cf.scan_texts(lenient, |text_id, tokens| {
+                    let Some(source) = self.sources.get(&text_id) else {
+                        return Ok(());
+                    };
+                    let key = (
+                        format!("{}s", source.year.0 / 10 * 10),
+                        source.genre.to_string(),
+                    );
+                    let text_counts = counts.entry(key.clone()).or_default();
+                    let total = totals.entry(key).or_default();
+                    let ids: Vec<Option<u32>> = tokens
+                        .iter()
+                        .map(|t| {
+                            if self.is_removed(t) {
+                                None
+                            } else {
+                                tag_of.get(t.word_id.0).copied().flatten()
+                            }
+                        })
+                        .collect();
+                    for window in ids.windows(options.n) {
+                        if let Some(ngram) = window.iter().copied().collect::<Option<Vec<u32>>>() {
+                            *text_counts.entry(ngram).or_default() += 1;
+                            *total += 1;
+                        }
+                    }
+                    Ok(())
+                })?;
+                Ok((counts, totals))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        let mut counts = Counts::default();
+        let mut totals = BTreeMap::new();
+        for (file_counts, file_totals) in per_file {
+            for (key, ngrams) in file_counts {
+                let c = counts.entry(key).or_default();
+                for (ngram, n) in ngrams {
+                    *c.entry(ngram).or_default() += n;
+                }
+            }
+            for (key, n) in file_totals {
+                *totals.entry(key).or_default() += n;
+            }
+        }
+        let mut result = Vec::new();
+        for ((decade, genre), ngrams) in counts {
+            for (ngram, count) in ngrams {
+                if count >= options.min_count {
+                    result.push(PosNgramCount {
+                        decade: decade.clone(),
+                        genre: genre.clone(),
+                        ngram: ngram.iter().map(|&i| tags[i as usize]).join(" "),
+                        count,
+                    });
+                }
+            }
+        }
+        result
+            .sort_by(|a, b| (&a.decade, &a.genre, &a.ngram).cmp(&(&b.decade, &b.genre, &b.ngram)));
+        info!(
+            "POS {}-grams: {} (decade, genre, n-gram) counts",
+            options.n,
+            result.len()
+        );
+        Ok(PosNgramProfile {
+            n: options.n,
+            counts: result,
+            totals,
+        })
+    }
+}
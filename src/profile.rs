@@ -0,0 +1,176 @@
+use crate::manifest::Fnv1a;
+use crate::{Coha, ExportFormat, TextId};
+use anyhow::Result;
+use log::{debug, info, warn};
+use rayon::prelude::*;
+use rustc_hash::FxHashMap;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Write};
+use std::path::Path;
+
+/// Size of one (decade, genre) part of the corpus, as found in the corpus
+/// files.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ProfileEntry {
+    pub decade: String,
+    pub genre: String,
+    /// Texts with at least one token.
+    pub texts: usize,
+    pub tokens: usize,
+    /// Tokens removed from the corpus (`@`), included in `tokens`.
+    pub removed_tokens: usize,
+}
+
+/// Number of texts and tokens per decade and genre, see [`Coha::profile`].
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct CorpusProfile {
+    /// Identifies the corpus files and the lexicon that the profile was
+    /// computed from.
+    pub corpus_key: String,
+    /// Sorted by decade and genre.
+    pub entries: Vec<ProfileEntry>,
+}
+
+impl CorpusProfile {
+    /// Number of tokens in the given decade (e.g. "1850s") and genre (e.g.
+    /// "FIC"), or in all decades or genres if `None`, for normalizing hit
+    /// counts.
+    pub fn tokens(&self, decade: Option<&str>, genre: Option<&str>) -> usize {
+        self.entries
+            .iter()
+            .filter(|e| decade.is_none_or(|d| e.decade == d))
+            .filter(|e| genre.is_none_or(|g| e.genre == g))
+            .map(|e| e.tokens)
+            .sum()
+    }
+
+    /// Write the profile as a table of corpus composition, with a row per
+    /// (decade, genre) and a total row.
+    pub fn write(&self, path: &Path, format: ExportFormat) -> Result<()> {
+        debug!("{}: writing...", path.to_string_lossy());
+        match format {
+            ExportFormat::Csv => {
+                let mut writer = csv::Writer::from_path(path)?;
+                writer.write_record(["decade", "genre", "texts", "tokens", "removed tokens"])?;
+                let total = ProfileEntry {
+                    decade: "all".to_owned(),
+                    genre: "all".to_owned(),
+                    texts: self.entries.iter().map(|e| e.texts).sum(),
+                    tokens: self.entries.iter().map(|e| e.tokens).sum(),
+                    removed_tokens: self.entries.iter().map(|e| e.removed_tokens).sum(),
+                };
+                for e in self.entries.iter().chain([&total]) {
+                    writer.write_record([
+                        &e.decade,
+                        &e.genre,
+                        &e.texts.to_string(),
+                        &e.tokens.to_string(),
+                        &e.removed_tokens.to_string(),
+                    ])?;
+                }
+                writer.flush()?;
+            }
+            ExportFormat::Json => {
+                let mut writer = BufWriter::new(File::create(path)?);
+                serde_json::to_writer(&mut writer, self)?;
+                writer.flush()?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Coha {
+    /// Key for the corpus files and lexicon, based on the file names and
+    /// sizes and the lexicon fingerprint.
+    fn corpus_key(&self) -> Result<String> {
+        let mut h = Fnv1a::default();
+        h.write(&self.lexicon_fingerprint().to_le_bytes());
+        for cf in &self.coha_files {
+            h.write(cf.identifier.as_bytes());
+            h.write(&cf.size().to_le_bytes());
+        }
+        Ok(format!("{:016x}", h.finish()))
+    }
+
+    fn compute_profile(&self) -> Result<CorpusProfile> {
+        let lenient = self.load_options.lenient;
+        let per_file = self
+            .coha_files
+            .par_iter()
+            .map(|cf| {
+                let mut counts: FxHashMap<TextId, (usize, usize)> = FxHashMap::default();
+                cf.scan_tokens(lenient, |token| {
+                    let c = counts.entry(token.text_id).or_default();
+                    c.0 += 1;
+                    if self.is_removed(&token) {
+                        c.1 += 1;
+                    }
+                })?;
+                Ok(counts)
+            })
+            .collect::<Result<Vec<_>>>()?;
+        let mut entries: BTreeMap<(String, String), ProfileEntry> = BTreeMap::new();
+        for (text_id, (tokens, removed)) in per_file.into_iter().flatten() {
+            let Some(source) = self.sources.get(&text_id) else {
+                continue;
+            };
+            let decade = format!("{}s", source.year.0 / 10 * 10);
+            let genre = source.genre.to_string();
+            let entry = entries
+                .entry((decade.clone(), genre.clone()))
+                .or_insert_with(|| ProfileEntry {
+                    decade,
+                    genre,
+                    ..Default::default()
+                });
+            entry.texts += 1;
+            entry.tokens += tokens;
+            entry.removed_tokens += removed;
+        }
+        let profile = CorpusProfile {
+            corpus_key: self.corpus_key()?,
+            entries: entries.into_values().collect(),
+        };
+        info!("corpus profile: {} tokens", profile.tokens(None, None));
+        Ok(profile)
+    }
+
+    /// Number of texts and tokens per decade and genre, counted in the
+    /// corpus files; unlike [`Coha::source_summary`], this reflects the
+    /// corpus as it is on disk. The first call reads all corpus files, and
+    /// the result is kept for later calls.
+    pub fn profile(&self) -> Result<&CorpusProfile> {
+        if let Some(profile) = self.profile.get() {
+            return Ok(profile);
+        }
+        let profile = self.compute_profile()?;
+        Ok(self.profile.get_or_init(|| profile))
+    }
+
+    /// [`Coha::profile`], cached in a JSON file: the profile is read from
+    /// `path` if it was computed from the same corpus files and lexicon, and
+    /// otherwise computed and written to `path`.
+    pub fn profile_cached(&self, path: &Path) -> Result<&CorpusProfile> {
+        let mut valid = false;
+        if path.exists() {
+            debug!("{}: reading...", path.to_string_lossy());
+            let file = BufReader::new(File::open(path)?);
+            match serde_json::from_reader::<_, CorpusProfile>(file) {
+                Ok(profile) if profile.corpus_key == self.corpus_key()? => {
+                    valid = true;
+                    let _ = self.profile.set(profile);
+                }
+                Ok(_) => info!("{}: corpus changed", path.to_string_lossy()),
+                Err(e) => warn!("{}: ignoring invalid cache: {e}", path.to_string_lossy()),
+            }
+        }
+        let profile = self.profile()?;
+        if !valid {
+            profile.write(path, ExportFormat::Json)?;
+        }
+        Ok(profile)
+    }
+}
@@ -0,0 +1,42 @@
+//! Deterministic pseudo-random numbers and hash mixing (SplitMix64).
+
+/// The increment of SplitMix64, the golden ratio in fixed point.
+const GAMMA: u64 = 0x9e37_79b9_7f4a_7c15;
+
+/// The output function of SplitMix64, which scrambles the bits of `z`.
+fn finalize(mut z: u64) -> u64 {
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
+/// A hash function of a family indexed by `i`.
+pub(crate) fn mix(x: u64, i: usize) -> u64 {
+    finalize(x ^ (i as u64).wrapping_mul(GAMMA))
+}
+
+/// A small deterministic pseudo-random number generator (SplitMix64).
+pub(crate) struct Rng(pub(crate) u64);
+
+impl Rng {
+    /// A generator for item `i`, e.g. a text, independent of the
+    /// generators of the other items with the same seed.
+    pub(crate) fn for_item(seed: u64, i: usize) -> Self {
+        Rng(seed ^ (i as u64).wrapping_mul(GAMMA))
+    }
+
+    fn next(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(GAMMA);
+        finalize(self.0)
+    }
+
+    /// A number in `0..n`.
+    pub(crate) fn below(&mut self, n: usize) -> usize {
+        (self.next() % n as u64) as usize
+    }
+
+    /// A number in `min..=max`.
+    pub(crate) fn range(&mut self, min: usize, max: usize) -> usize {
+        min + self.below(max - min + 1)
+    }
+}
@@ -0,0 +1,218 @@
+use crate::sentences::{is_structure_marker, Sentences};
+use crate::Coha;
+use anyhow::Result;
+use log::info;
+use rayon::prelude::*;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::io::Write;
+
+/// Distribution of sentence lengths (in words) and word lengths (in
+/// characters) in one (decade, genre), see [`Coha::length_statistics`].
+///
+/// Punctuation, paragraph markers, and removed tokens (`@`) are not words,
+/// and sentences with removed tokens are left out.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct LengthStatistics {
+    pub decade: String,
+    pub genre: String,
+    /// Number of sentences of each length.
+    pub sentence_lengths: BTreeMap<usize, u64>,
+    /// Number of words of each length.
+    pub word_lengths: BTreeMap<usize, u64>,
+}
+
+/// Mean and standard deviation of a distribution.
+fn mean_sd(distribution: &BTreeMap<usize, u64>) -> (f64, f64) {
+    let n: u64 = distribution.values().sum();
+    if n == 0 {
+        return (0.0, 0.0);
+    }
+    let n = n as f64;
+    let mean = distribution
+        .iter()
+        .map(|(&x, &c)| x as f64 * c as f64)
+        .sum::<f64>()
+        / n;
+    let var = distribution
+        .iter()
+        .map(|(&x, &c)| (x as f64 - mean).powi(2) * c as f64)
+        .sum::<f64>()
+        / n;
+    (mean, var.sqrt())
+}
+
+/// The smallest value at or above quantile `q` of a distribution.
+fn quantile(distribution: &BTreeMap<usize, u64>, q: f64) -> usize {
+    let n: u64 = distribution.values().sum();
+    let target = (q * n as f64).ceil().max(1.0) as u64;
+    let mut seen = 0;
+    for (&x, &c) in distribution {
+        seen += c;
+        if seen >= target {
+            return x;
+        }
+    }
+    0
+}
+
+impl LengthStatistics {
+    pub fn sentences(&self) -> u64 {
+        self.sentence_lengths.values().sum()
+    }
+
+    pub fn words(&self) -> u64 {
+        self.word_lengths.values().sum()
+    }
+
+    /// Automated Readability Index, 4.71 × characters per word + 0.5 ×
+    /// words per sentence − 21.43, roughly a US school grade level.
+    pub fn automated_readability_index(&self) -> f64 {
+        let (chars_per_word, _) = mean_sd(&self.word_lengths);
+        let (words_per_sentence, _) = mean_sd(&self.sentence_lengths);
+        4.71 * chars_per_word + 0.5 * words_per_sentence - 21.43
+    }
+
+    fn merge(&mut self, other: &LengthStatistics) {
+        for (&x, &c) in &other.sentence_lengths {
+            *self.sentence_lengths.entry(x).or_default() += c;
+        }
+        for (&x, &c) in &other.word_lengths {
+            *self.word_lengths.entry(x).or_default() += c;
+        }
+    }
+}
+
+/// Is the token a word rather than punctuation or a marker?
+fn is_word(word_cs: &str) -> bool {
+    !is_structure_marker(word_cs) && word_cs.chars().any(|c| c.is_alphanumeric())
+}
+
+impl Coha {
+    /// Sentence and word lengths per decade and genre, sorted by decade and
+    /// genre. Sentences are delimited by sentence-final punctuation and
+    /// paragraph markers. This reads all corpus files.
+    pub fn length_statistics(&self) -> Result<Vec<LengthStatistics>> {
+        let lenient = self.load_options.lenient;
+        let per_file = self
+            .coha_files
+            .par_iter()
+            .map(|cf| {
+                let mut stats: BTreeMap<(String, String), LengthStatistics> = BTreeMap::new();
+                cf.scan_texts(lenient, |text_id, tokens| {
+                    let Some(source) = self.sources.get(&text_id) else {
+                        return Ok(());
+                    };
+                    let decade = format!("{}s", source.year.0 / 10 * 10);
+                    let genre = source.genre.to_string();
+                    let entry = stats
+                        .entry((decade.clone(), genre.clone()))
+                        .or_insert_with(|| LengthStatistics {
+                            decade,
+                            genre,
+                            ..Default::default()
+                        });
+                    let sentences = Sentences::new(self, tokens);
+                    for range in sentences.ranges(tokens.len()) {
+                        let sentence = &tokens[range];
+                        if sentence.iter().any(|t| self.is_removed(t)) {
+                            continue;
+                        }
+                        let mut words = 0;
+                        for t in sentence {
+                            let word_cs = &self.get_word(t.word_id).word_cs;
+                            if is_word(word_cs) {
+                                words += 1;
+                                *entry
+                                    .word_lengths
+                                    .entry(word_cs.chars().count())
+                                    .or_default() += 1;
+                            }
+                        }
+                        if words > 0 {
+                            *entry.sentence_lengths.entry(words).or_default() += 1;
+                        }
+                    }
+                    Ok(())
+                })?;
+                Ok(stats)
+            })
+            .collect::<Result<Vec<_>>>()?;
+        let mut stats: BTreeMap<(String, String), LengthStatistics> = BTreeMap::new();
+        for (key, s) in per_file.into_iter().flatten() {
+            match stats.get_mut(&key) {
+                Some(entry) => entry.merge(&s),
+                None => {
+                    stats.insert(key, s);
+                }
+            }
+        }
+        let stats: Vec<LengthStatistics> = stats.into_values().collect();
+        info!(
+            "length statistics: {} sentences",
+            stats.iter().map(|s| s.sentences()).sum::<u64>()
+        );
+        Ok(stats)
+    }
+}
+
+/// Write a summary of the statistics as CSV, with a row per (decade,
+/// genre).
+pub fn write_length_summary<W: Write>(writer: W, stats: &[LengthStatistics]) -> Result<()> {
+    let mut writer = csv::Writer::from_writer(writer);
+    writer.write_record([
+        "decade",
+        "genre",
+        "sentences",
+        "mean sentence length",
+        "sd sentence length",
+        "median sentence length",
+        "90th percentile sentence length",
+        "words",
+        "mean word length",
+        "sd word length",
+        "ARI",
+    ])?;
+    for s in stats {
+        let (sentence_mean, sentence_sd) = mean_sd(&s.sentence_lengths);
+        let (word_mean, word_sd) = mean_sd(&s.word_lengths);
+        writer.write_record([
+            s.decade.clone(),
+            s.genre.clone(),
+            s.sentences().to_string(),
+            format!("{sentence_mean:.3}"),
+            format!("{sentence_sd:.3}"),
+            quantile(&s.sentence_lengths, 0.5).to_string(),
+            quantile(&s.sentence_lengths, 0.9).to_string(),
+            s.words().to_string(),
+            format!("{word_mean:.3}"),
+            format!("{word_sd:.3}"),
+            format!("{:.3}", s.automated_readability_index()),
+        ])?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+/// Write the full distributions as CSV in long format, with a row per
+/// (decade, genre, unit, length), where the unit is "sentence" (length in
+/// words) or "word" (length in characters).
+pub fn write_length_distributions<W: Write>(writer: W, stats: &[LengthStatistics]) -> Result<()> {
+    let mut writer = csv::Writer::from_writer(writer);
+    writer.write_record(["decade", "genre", "unit", "length", "count"])?;
+    for s in stats {
+        for (unit, distribution) in [("sentence", &s.sentence_lengths), ("word", &s.word_lengths)] {
+            for (length, count) in distribution {
+                writer.write_record([
+                    &s.decade,
+                    &s.genre,
+                    unit,
+                    &length.to_string(),
+                    &count.to_string(),
+                ])?;
+            }
+        }
+    }
+    writer.flush()?;
+    Ok(())
+}
@@ -0,0 +1,526 @@
+use crate::{MemoryUsage, Source, TextId};
+use anyhow::Result;
+use log::debug;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+/// Summary of a search run.
+#[derive(Debug, Clone, Default)]
+pub struct SearchReport {
+    /// Hit counts, one entry per search.
+    pub searches: Vec<SearchCounts>,
+    /// Statistics per search and corpus file.
+    pub files: Vec<FileStats>,
+    /// Problems encountered during the run, deduplicated.
+    pub warnings: Vec<Warning>,
+    pub memory: MemoryUsage,
+}
+
+#[derive(Copy, Debug, Clone, Eq, PartialEq, Ord, PartialOrd, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum WarningKind {
+    /// A corpus file contains a text that is not in the sources table.
+    UnknownTextId,
+    /// A malformed line was skipped in lenient mode.
+    MalformedLine,
+    /// The sources table has texts from a decade without a corpus file, or a
+    /// corpus file disappeared before it was searched.
+    MissingDecade,
+    /// A hit limit or time limit was reached; the results are incomplete.
+    LimitReached,
+    /// Tokens of a text were not in increasing order and were sorted, see
+    /// [`crate::LoadOptions::sort_tokens`].
+    TokensReordered,
+}
+
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub struct Warning {
+    pub kind: WarningKind,
+    pub message: String,
+    /// How many times this warning occurred.
+    pub count: usize,
+}
+
+/// Collects warnings, counting duplicates.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Warnings(BTreeMap<(WarningKind, String), usize>);
+
+impl Warnings {
+    pub fn add(&mut self, kind: WarningKind, message: String) {
+        self.add_count(kind, message, 1);
+    }
+
+    pub fn add_count(&mut self, kind: WarningKind, message: String, count: usize) {
+        if count > 0 {
+            *self.0.entry((kind, message)).or_default() += count;
+        }
+    }
+
+    pub fn merge(&mut self, other: Warnings) {
+        for (k, v) in other.0 {
+            *self.0.entry(k).or_default() += v;
+        }
+    }
+
+    pub fn add_all(&mut self, warnings: &[Warning]) {
+        for w in warnings {
+            self.add_count(w.kind, w.message.clone(), w.count);
+        }
+    }
+
+    pub fn into_vec(self) -> Vec<Warning> {
+        self.0
+            .into_iter()
+            .map(|((kind, message), count)| Warning {
+                kind,
+                message,
+                count,
+            })
+            .collect()
+    }
+}
+
+/// Statistics of one search in one corpus file.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct FileStats {
+    pub label: String,
+    /// Corpus file identifier (decade, e.g. "1850s").
+    pub file: String,
+    /// Tokens in the texts that were searched.
+    pub tokens: usize,
+    /// Texts that were searched.
+    pub texts: usize,
+    pub hits: usize,
+    /// Texts with at least one hit.
+    pub hit_texts: usize,
+    /// Malformed lines skipped in the corpus file in lenient mode.
+    pub malformed_lines: usize,
+    /// Wall-clock time spent on the entire corpus file.
+    pub elapsed_secs: f64,
+    /// Some hits were left out because a limit was reached.
+    pub truncated: bool,
+    /// The rest of the corpus file was not read because every search had
+    /// reached [`crate::SearchOptions::stop_after_hits`]; `tokens` and
+    /// `texts` only cover the texts before the cutoff.
+    #[serde(default)]
+    pub stopped_early: bool,
+    /// Hits left out by [`crate::SearchOptions::thinning`]; not included in
+    /// `hits`.
+    #[serde(default)]
+    pub thinned: usize,
+    /// Bytes read from the corpus file, including the texts read again in
+    /// the second pass of [`crate::SearchOptions::two_pass`].
+    #[serde(default)]
+    pub bytes: u64,
+    /// Time spent reading and parsing the corpus file, shared by all
+    /// searches: the part of `elapsed_secs` not spent on matching or
+    /// writing.
+    #[serde(default)]
+    pub parse_secs: f64,
+    /// Time spent matching this search against the texts.
+    #[serde(default)]
+    pub match_secs: f64,
+    /// Time spent formatting and writing the hits of this search.
+    #[serde(default)]
+    pub write_secs: f64,
+}
+
+impl FileStats {
+    pub(crate) fn new(label: &str, file: &str) -> Self {
+        Self {
+            label: label.to_owned(),
+            file: file.to_owned(),
+            ..Default::default()
+        }
+    }
+
+    /// Throughput of reading the corpus file, in megabytes (10⁶ bytes) per
+    /// second.
+    pub fn megabytes_per_sec(&self) -> f64 {
+        if self.elapsed_secs > 0.0 {
+            self.bytes as f64 / 1e6 / self.elapsed_secs
+        } else {
+            0.0
+        }
+    }
+
+    pub(crate) fn add(&mut self, tokens: usize, hits: usize) {
+        self.tokens += tokens;
+        self.texts += 1;
+        self.hits += hits;
+        if hits > 0 {
+            self.hit_texts += 1;
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct StatsFile<'a> {
+    complete: bool,
+    files: &'a [FileStats],
+    warnings: &'a [Warning],
+    memory: &'a MemoryUsage,
+}
+
+impl SearchReport {
+    /// Were all hits found, i.e. no limit was reached?
+    pub fn is_complete(&self) -> bool {
+        !self
+            .warnings
+            .iter()
+            .any(|w| w.kind == WarningKind::LimitReached)
+    }
+
+    /// Write the file statistics as JSON.
+    pub fn write_stats(&self, path: &Path) -> Result<()> {
+        debug!("{}: writing...", path.to_string_lossy());
+        let mut writer = BufWriter::new(File::create(path)?);
+        serde_json::to_writer_pretty(
+            &mut writer,
+            &StatsFile {
+                complete: self.is_complete(),
+                files: &self.files,
+                warnings: &self.warnings,
+                memory: &self.memory,
+            },
+        )?;
+        writer.flush()?;
+        Ok(())
+    }
+
+    /// Write the time spent on each corpus file and search as CSV, to see
+    /// whether reading the files or matching is the bottleneck.
+    pub fn write_timing<W: Write>(&self, writer: W) -> Result<()> {
+        let mut writer = csv::Writer::from_writer(writer);
+        writer.write_record([
+            "search", "file", "bytes", "elapsed", "parse", "match", "write", "MB/s",
+        ])?;
+        for f in &self.files {
+            writer.write_record([
+                f.label.clone(),
+                f.file.clone(),
+                f.bytes.to_string(),
+                format!("{:.3}", f.elapsed_secs),
+                format!("{:.3}", f.parse_secs),
+                format!("{:.3}", f.match_secs),
+                format!("{:.3}", f.write_secs),
+                format!("{:.1}", f.megabytes_per_sec()),
+            ])?;
+        }
+        writer.flush()?;
+        Ok(())
+    }
+}
+
+/// Clustering of the hits of a search within one text, based on the gaps
+/// between the positions of consecutive hits.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Burstiness {
+    /// Tokens in the text.
+    pub tokens: usize,
+    pub hits: usize,
+    /// Mean number of tokens between the starts of consecutive hits; `None`
+    /// with fewer than two hits.
+    pub mean_gap: Option<f64>,
+    /// Standard deviation of the gaps.
+    pub sd_gap: Option<f64>,
+    /// Burstiness index (σ − μ) / (σ + μ) of the gaps: −1 for evenly spaced
+    /// hits, about 0 for hits at random positions, and close to 1 for hits
+    /// in clusters.
+    pub index: Option<f64>,
+    /// Distance between the first and the last hit relative to the length
+    /// of the text, from 0 (all hits in one place) to 1 (from the start to
+    /// the end of the text).
+    pub spread: f64,
+}
+
+impl Burstiness {
+    /// The burstiness of hits at the given positions, in increasing order.
+    pub(crate) fn new(tokens: usize, positions: &[usize]) -> Self {
+        let gaps: Vec<f64> = positions.windows(2).map(|w| (w[1] - w[0]) as f64).collect();
+        let (mut mean_gap, mut sd_gap, mut index) = (None, None, None);
+        if !gaps.is_empty() {
+            let n = gaps.len() as f64;
+            let mean = gaps.iter().sum::<f64>() / n;
+            let sd = (gaps.iter().map(|g| (g - mean).powi(2)).sum::<f64>() / n).sqrt();
+            mean_gap = Some(mean);
+            sd_gap = Some(sd);
+            index = (sd + mean > 0.0).then(|| (sd - mean) / (sd + mean));
+        }
+        let spread = match (positions.first(), positions.last()) {
+            (Some(first), Some(last)) if tokens > 0 => (last - first) as f64 / tokens as f64,
+            _ => 0.0,
+        };
+        Self {
+            tokens,
+            hits: positions.len(),
+            mean_gap,
+            sd_gap,
+            index,
+            spread,
+        }
+    }
+}
+
+/// Hit counts of one search.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SearchCounts {
+    pub label: String,
+    pub hits: usize,
+    /// Hits per corpus file identifier (decade, e.g. "1850s").
+    pub by_decade: BTreeMap<String, usize>,
+    /// Hits per genre.
+    pub by_genre: BTreeMap<String, usize>,
+    /// Hits per (decade, genre).
+    #[serde(with = "pairs")]
+    pub by_decade_genre: BTreeMap<(String, String), usize>,
+    /// Hits per publication year.
+    pub by_year: BTreeMap<u16, usize>,
+    /// Hits per month of publication (e.g. "1925-03"), for texts with a
+    /// [`crate::PublicationDate`].
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub by_month: BTreeMap<String, usize>,
+    /// Hits per text; texts without hits are not included.
+    pub by_text: BTreeMap<TextId, usize>,
+    /// Hits per (metadata column, value) of the user-supplied metadata.
+    #[serde(with = "pairs")]
+    pub by_metadata: BTreeMap<(String, String), usize>,
+    /// Clustering of the hits in each text with hits, if
+    /// [`crate::SearchOptions::burstiness`] is set.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub burstiness: BTreeMap<TextId, Burstiness>,
+}
+
+impl SearchCounts {
+    pub(crate) fn new(label: &str) -> Self {
+        Self {
+            label: label.to_owned(),
+            ..Default::default()
+        }
+    }
+
+    pub(crate) fn add(
+        &mut self,
+        identifier: &str,
+        source: &Source,
+        metadata_columns: &[String],
+        metadata: &[&str],
+        hits: usize,
+    ) {
+        if hits == 0 {
+            return;
+        }
+        let genre = source.genre.to_string();
+        self.hits += hits;
+        *self.by_decade.entry(identifier.to_owned()).or_default() += hits;
+        *self.by_genre.entry(genre.clone()).or_default() += hits;
+        *self
+            .by_decade_genre
+            .entry((identifier.to_owned(), genre))
+            .or_default() += hits;
+        *self.by_year.entry(source.year.0).or_default() += hits;
+        if let Some(date) = &source.date {
+            *self.by_month.entry(date.month_label()).or_default() += hits;
+        }
+        *self.by_text.entry(source.text_id).or_default() += hits;
+        for (column, value) in metadata_columns.iter().zip(metadata) {
+            *self
+                .by_metadata
+                .entry((column.clone(), (*value).to_owned()))
+                .or_default() += hits;
+        }
+    }
+
+    pub(crate) fn merge(&mut self, other: SearchCounts) {
+        self.hits += other.hits;
+        for (k, v) in other.by_decade {
+            *self.by_decade.entry(k).or_default() += v;
+        }
+        for (k, v) in other.by_genre {
+            *self.by_genre.entry(k).or_default() += v;
+        }
+        for (k, v) in other.by_decade_genre {
+            *self.by_decade_genre.entry(k).or_default() += v;
+        }
+        for (k, v) in other.by_year {
+            *self.by_year.entry(k).or_default() += v;
+        }
+        for (k, v) in other.by_month {
+            *self.by_month.entry(k).or_default() += v;
+        }
+        for (k, v) in other.by_text {
+            *self.by_text.entry(k).or_default() += v;
+        }
+        for (k, v) in other.by_metadata {
+            *self.by_metadata.entry(k).or_default() += v;
+        }
+        self.burstiness.extend(other.burstiness);
+    }
+
+    /// Write the burstiness of the hits in each text as
+    /// `{label}-burstiness.csv` in `dir`.
+    pub fn write_burstiness(&self, dir: &Path) -> Result<()> {
+        let outpath = dir.join(format!("{}-burstiness.csv", &self.label));
+        debug!("{}: writing...", outpath.to_string_lossy());
+        let mut writer = csv::Writer::from_path(outpath)?;
+        writer.write_record([
+            "text ID",
+            "tokens",
+            "hits",
+            "mean gap",
+            "sd gap",
+            "burstiness",
+            "spread",
+        ])?;
+        let opt = |x: Option<f64>| x.map(|x| format!("{x:.3}")).unwrap_or_default();
+        for (text_id, b) in &self.burstiness {
+            writer.write_record([
+                text_id.0.to_string(),
+                b.tokens.to_string(),
+                b.hits.to_string(),
+                opt(b.mean_gap),
+                opt(b.sd_gap),
+                opt(b.index),
+                format!("{:.3}", b.spread),
+            ])?;
+        }
+        writer.flush()?;
+        Ok(())
+    }
+
+    /// Write the counts as `{label}-counts.csv` (per decade and genre),
+    /// `{label}-year-counts.csv` (per year), `{label}-text-counts.csv`
+    /// (per text), if some texts have a publication date,
+    /// `{label}-month-counts.csv` (per month), and, if metadata is attached,
+    /// `{label}-metadata-counts.csv` (per metadata column and value) in
+    /// `dir`.
+    pub fn write(&self, dir: &Path) -> Result<()> {
+        let outpath = dir.join(format!("{}-counts.csv", &self.label));
+        debug!("{}: writing...", outpath.to_string_lossy());
+        let mut writer = csv::Writer::from_path(outpath)?;
+        writer.write_record(["decade", "genre", "hits"])?;
+        for ((decade, genre), hits) in &self.by_decade_genre {
+            writer.write_record([decade, genre, &hits.to_string()])?;
+        }
+        writer.flush()?;
+
+        let outpath = dir.join(format!("{}-year-counts.csv", &self.label));
+        debug!("{}: writing...", outpath.to_string_lossy());
+        let mut writer = csv::Writer::from_path(outpath)?;
+        writer.write_record(["year", "hits"])?;
+        for (year, hits) in &self.by_year {
+            writer.write_record([year.to_string(), hits.to_string()])?;
+        }
+        writer.flush()?;
+
+        let outpath = dir.join(format!("{}-text-counts.csv", &self.label));
+        debug!("{}: writing...", outpath.to_string_lossy());
+        let mut writer = csv::Writer::from_path(outpath)?;
+        writer.write_record(["text ID", "hits"])?;
+        for (text_id, hits) in &self.by_text {
+            writer.write_record([text_id.0.to_string(), hits.to_string()])?;
+        }
+        writer.flush()?;
+
+        if !self.by_month.is_empty() {
+            let outpath = dir.join(format!("{}-month-counts.csv", &self.label));
+            debug!("{}: writing...", outpath.to_string_lossy());
+            let mut writer = csv::Writer::from_path(outpath)?;
+            writer.write_record(["month", "hits"])?;
+            for (month, hits) in &self.by_month {
+                writer.write_record([month, &hits.to_string()])?;
+            }
+            writer.flush()?;
+        }
+
+        if !self.by_metadata.is_empty() {
+            let outpath = dir.join(format!("{}-metadata-counts.csv", &self.label));
+            debug!("{}: writing...", outpath.to_string_lossy());
+            let mut writer = csv::Writer::from_path(outpath)?;
+            writer.write_record(["column", "value", "hits"])?;
+            for ((column, value), hits) in &self.by_metadata {
+                writer.write_record([column, value, &hits.to_string()])?;
+            }
+            writer.flush()?;
+        }
+        Ok(())
+    }
+}
+
+/// Maps with tuple keys are serialized as lists of `[key, value]` pairs,
+/// since JSON object keys must be strings.
+mod pairs {
+    use super::{BTreeMap, Deserialize, Deserializer, Serialize, Serializer};
+
+    pub fn serialize<K, V, S>(map: &BTreeMap<K, V>, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        K: Serialize,
+        V: Serialize,
+        S: Serializer,
+    {
+        serializer.collect_seq(map)
+    }
+
+    pub fn deserialize<'de, K, V, D>(deserializer: D) -> Result<BTreeMap<K, V>, D::Error>
+    where
+        K: Deserialize<'de> + Ord,
+        V: Deserialize<'de>,
+        D: Deserializer<'de>,
+    {
+        let pairs: Vec<(K, V)> = Vec::deserialize(deserializer)?;
+        Ok(pairs.into_iter().collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn close(a: Option<f64>, b: f64) -> bool {
+        a.is_some_and(|a| (a - b).abs() < 1e-9)
+    }
+
+    #[test]
+    fn burstiness_of_evenly_spaced_hits() {
+        let b = Burstiness::new(100, &[10, 20, 30]);
+        assert_eq!(b.hits, 3);
+        assert!(close(b.mean_gap, 10.0));
+        assert!(close(b.sd_gap, 0.0));
+        assert!(close(b.index, -1.0));
+        assert!((b.spread - 0.2).abs() < 1e-9);
+    }
+
+    #[test]
+    fn burstiness_of_clustered_hits() {
+        // gaps 1, 1, 88: mean 30, sd √((29² + 29² + 58²) / 3) = √1682
+        let b = Burstiness::new(100, &[0, 1, 2, 90]);
+        let sd = 1682f64.sqrt();
+        assert!(close(b.mean_gap, 30.0));
+        assert!(close(b.sd_gap, sd));
+        assert!(close(b.index, (sd - 30.0) / (sd + 30.0)));
+        assert!((b.spread - 0.9).abs() < 1e-9);
+    }
+
+    #[test]
+    fn burstiness_without_gaps() {
+        let b = Burstiness::new(100, &[5]);
+        assert_eq!(
+            (b.hits, b.mean_gap, b.sd_gap, b.index),
+            (1, None, None, None)
+        );
+        assert_eq!(b.spread, 0.0);
+        let b = Burstiness::new(0, &[]);
+        assert_eq!((b.hits, b.index, b.spread), (0, None, 0.0));
+    }
+
+    #[test]
+    fn burstiness_of_hits_at_one_position() {
+        // all gaps are 0, so the index is undefined
+        let b = Burstiness::new(10, &[3, 3]);
+        assert!(close(b.mean_gap, 0.0));
+        assert_eq!(b.index, None);
+    }
+}
@@ -0,0 +1,36 @@
+use rayon::prelude::*;
+use std::cmp::Reverse;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+
+/// Apply `f` to all items in parallel in the current thread pool and return
+/// the results in the order of the items. The items are started in
+/// decreasing order of `cost`, so that the largest items do not end up
+/// running alone at the end while the other workers are idle.
+pub(crate) fn largest_first<T, R, C, F>(items: &[T], cost: C, f: F) -> Vec<R>
+where
+    T: Sync,
+    R: Send,
+    C: Fn(&T) -> u64,
+    F: Fn(&T) -> R + Sync,
+{
+    let mut order: Vec<usize> = (0..items.len()).collect();
+    order.sort_by_cached_key(|&i| Reverse(cost(&items[i])));
+    let next = AtomicUsize::new(0);
+    let results: Vec<Mutex<Option<R>>> = items.iter().map(|_| Mutex::new(None)).collect();
+    let workers = rayon::current_num_threads().min(items.len());
+    (0..workers).into_par_iter().with_max_len(1).for_each(|_| {
+        while let Some(&i) = order.get(next.fetch_add(1, Ordering::Relaxed)) {
+            let result = f(&items[i]);
+            *results[i].lock().expect("no worker panicked") = Some(result);
+        }
+    });
+    results
+        .into_iter()
+        .map(|r| {
+            r.into_inner()
+                .expect("no worker panicked")
+                .expect("every item was processed")
+        })
+        .collect()
+}
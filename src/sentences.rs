@@ -0,0 +1,85 @@
+use crate::{Coha, Token};
+use std::ops::Range;
+
+/// Is the token a structural marker, written as a tag: a paragraph break
+/// (`<p>`) or a heading (e.g. `<h>`, `</h>`)?
+pub(crate) fn is_structure_marker(word_cs: &str) -> bool {
+    let Some(name) = word_cs.strip_prefix('<').and_then(|s| s.strip_suffix('>')) else {
+        return false;
+    };
+    let name = name.strip_prefix('/').unwrap_or(name);
+    name.starts_with(|c: char| c.is_ascii_alphabetic())
+        && name.chars().all(|c| c.is_ascii_alphanumeric())
+}
+
+/// Tokens that end a sentence.
+fn ends_sentence(word_cs: &str) -> bool {
+    matches!(word_cs, "." | "!" | "?" | "...")
+}
+
+/// The sentences of a text, as segmented by punctuation: a sentence ends
+/// after ".", "!", "?", or "...", and at each structural marker (see
+/// [`is_structure_marker`]), which is not part of any sentence. Runs of
+/// several sentence-ending tokens do not form sentences of their own.
+/// Paragraphs are delimited by structural markers only, so a heading is a
+/// paragraph of its own.
+pub(crate) struct Sentences {
+    /// Token index of the first token of each sentence.
+    starts: Vec<usize>,
+    /// Token index of the first token of each paragraph.
+    paragraph_starts: Vec<usize>,
+}
+
+impl Sentences {
+    pub(crate) fn new(coha: &Coha, tokens: &[Token]) -> Self {
+        let mut starts = Vec::new();
+        let mut paragraph_starts = Vec::new();
+        let mut open = false;
+        let mut paragraph_open = false;
+        for (i, token) in tokens.iter().enumerate() {
+            if coha.is_marker(token) {
+                open = false;
+                paragraph_open = false;
+                continue;
+            }
+            if !paragraph_open {
+                paragraph_starts.push(i);
+                paragraph_open = true;
+            }
+            let word_cs = coha.get_word(token.word_id).word_cs.as_str();
+            if !open && !ends_sentence(word_cs) {
+                starts.push(i);
+                open = true;
+            }
+            if ends_sentence(word_cs) {
+                open = false;
+            }
+        }
+        Sentences {
+            starts,
+            paragraph_starts,
+        }
+    }
+
+    /// Paragraph number of the token at index `pos`, starting from 1.
+    pub(crate) fn paragraph(&self, pos: usize) -> usize {
+        self.paragraph_starts.partition_point(|&s| s <= pos).max(1)
+    }
+
+    /// Sentence number and position within the sentence of the token at
+    /// index `pos`, both starting from 1.
+    pub(crate) fn locate(&self, pos: usize) -> (usize, usize) {
+        let n = self.starts.partition_point(|&s| s <= pos);
+        match n {
+            0 => (1, 1),
+            _ => (n, pos - self.starts[n - 1] + 1),
+        }
+    }
+
+    /// Token ranges of the sentences of a text of `len` tokens. A range may
+    /// end with sentence-ending tokens and structural markers.
+    pub(crate) fn ranges(&self, len: usize) -> impl Iterator<Item = Range<usize>> + '_ {
+        let ends = self.starts.iter().skip(1).copied().chain([len]);
+        self.starts.iter().zip(ends).map(|(&start, end)| start..end)
+    }
+}
@@ -0,0 +1,300 @@
+use anyhow::{bail, Result};
+use log::debug;
+use std::collections::BTreeMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::{ErrorKind, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+/// Suffix of result files that are still being written. A file gets its
+/// final name only when it is complete, so an interrupted run never leaves
+/// truncated files that look complete.
+pub(crate) const PARTIAL_SUFFIX: &str = ".partial";
+
+fn partial_path(path: &Path) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(PARTIAL_SUFFIX);
+    name.into()
+}
+
+/// Give a completely written file its final name, making sure that both the
+/// contents and the new name are on disk.
+fn commit_file(file: File, path: &Path) -> Result<()> {
+    file.sync_all()?;
+    drop(file);
+    fs::rename(partial_path(path), path)?;
+    #[cfg(unix)]
+    {
+        let dir = match path.parent() {
+            Some(parent) if !parent.as_os_str().is_empty() => parent,
+            _ => Path::new("."),
+        };
+        File::open(dir)?.sync_all()?;
+    }
+    Ok(())
+}
+
+/// Where the result files of a search go, see [`crate::Coha::search_to`]:
+/// local files ([`FileSink`]), memory ([`MemorySink`]), one stream
+/// ([`WriterSink`]), or anything else, e.g. a socket or object storage.
+/// A closure that returns a writer for each file is also a sink.
+pub trait OutputSink: Send + Sync {
+    /// Start the result file `file`, a path relative to the result
+    /// directory such as `be-going-to/be-going-to-1850s.csv`. Files of
+    /// different corpus files are written concurrently.
+    fn create(&self, file: &Path) -> Result<Box<dyn SinkWriter>>;
+
+    /// Continue the unfinished result file `file` after its first `len`
+    /// bytes, discarding the rest, when a run is resumed from a checkpoint
+    /// (see [`crate::SearchOptions::checkpoint`]); `None` if the sink
+    /// cannot do that, and the corpus file is then searched from the start.
+    fn resume(&self, _file: &Path, _len: u64) -> Result<Option<Box<dyn SinkWriter>>> {
+        Ok(None)
+    }
+}
+
+/// The contents of one result file, see [`OutputSink::create`].
+pub trait SinkWriter: Write + Send {
+    /// Complete the file after the last write. Files that are never
+    /// finished are incomplete, e.g. because the run failed.
+    fn finish(self: Box<Self>) -> Result<()>;
+
+    /// Make everything written so far durable, before the progress of the
+    /// run is recorded in a checkpoint.
+    fn sync(&mut self) -> Result<()> {
+        self.flush()?;
+        Ok(())
+    }
+}
+
+impl<F> OutputSink for F
+where
+    F: Fn(&Path) -> Result<Box<dyn Write + Send>> + Send + Sync,
+{
+    fn create(&self, file: &Path) -> Result<Box<dyn SinkWriter>> {
+        Ok(Box::new(Flushed(self(file)?)))
+    }
+}
+
+/// A writer that is complete when it has been flushed.
+struct Flushed(Box<dyn Write + Send>);
+
+impl Write for Flushed {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.write(buf)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.0.flush()
+    }
+}
+
+impl SinkWriter for Flushed {
+    fn finish(mut self: Box<Self>) -> Result<()> {
+        self.0.flush()?;
+        Ok(())
+    }
+}
+
+/// Result files in a local directory; the default. A file is written under
+/// a partial name and renamed when it is complete.
+#[derive(Debug, Clone)]
+pub struct FileSink {
+    dir: PathBuf,
+}
+
+impl FileSink {
+    pub fn new(dir: &Path) -> Self {
+        Self {
+            dir: dir.to_owned(),
+        }
+    }
+}
+
+struct PartialFile {
+    file: File,
+    path: PathBuf,
+}
+
+impl Write for PartialFile {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.file.write(buf)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.file.flush()
+    }
+}
+
+impl SinkWriter for PartialFile {
+    fn finish(self: Box<Self>) -> Result<()> {
+        commit_file(self.file, &self.path)
+    }
+
+    fn sync(&mut self) -> Result<()> {
+        self.file.sync_data()?;
+        Ok(())
+    }
+}
+
+impl OutputSink for FileSink {
+    fn create(&self, file: &Path) -> Result<Box<dyn SinkWriter>> {
+        let path = self.dir.join(file);
+        debug!("{}: writing...", path.to_string_lossy());
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let file = File::create(partial_path(&path))?;
+        Ok(Box::new(PartialFile { file, path }))
+    }
+
+    fn resume(&self, file: &Path, len: u64) -> Result<Option<Box<dyn SinkWriter>>> {
+        let path = self.dir.join(file);
+        let partial = partial_path(&path);
+        // the file may have been completed after the progress was recorded
+        if !partial.exists() && path.exists() {
+            fs::rename(&path, &partial)?;
+        }
+        let mut file = match OpenOptions::new().write(true).open(&partial) {
+            Ok(file) => file,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+        if file.metadata()?.len() < len {
+            return Ok(None);
+        }
+        debug!("{}: resuming at {} bytes...", path.to_string_lossy(), len);
+        file.set_len(len)?;
+        file.seek(SeekFrom::End(0))?;
+        Ok(Some(Box::new(PartialFile { file, path })))
+    }
+}
+
+/// A result file that is buffered until it is complete and then handed
+/// over as a whole.
+struct Buffered {
+    buffer: Vec<u8>,
+    done: Box<dyn FnOnce(Vec<u8>) -> Result<()> + Send>,
+}
+
+impl Write for Buffered {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.buffer.write(buf)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+impl SinkWriter for Buffered {
+    fn finish(self: Box<Self>) -> Result<()> {
+        (self.done)(self.buffer)
+    }
+}
+
+/// Result files kept in memory, e.g. for a service that sends them on.
+#[derive(Debug, Clone, Default)]
+pub struct MemorySink {
+    files: Arc<Mutex<BTreeMap<PathBuf, Vec<u8>>>>,
+}
+
+impl MemorySink {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The completed files so far, by their path relative to the result
+    /// directory.
+    pub fn files(&self) -> BTreeMap<PathBuf, Vec<u8>> {
+        self.files.lock().expect("no thread panicked").clone()
+    }
+
+    /// Remove and return the completed files.
+    pub fn take(&self) -> BTreeMap<PathBuf, Vec<u8>> {
+        std::mem::take(&mut *self.files.lock().expect("no thread panicked"))
+    }
+}
+
+impl OutputSink for MemorySink {
+    fn create(&self, file: &Path) -> Result<Box<dyn SinkWriter>> {
+        let files = self.files.clone();
+        let file = file.to_owned();
+        Ok(Box::new(Buffered {
+            buffer: Vec::new(),
+            done: Box::new(move |contents| {
+                let mut files = files.lock().expect("no thread panicked");
+                files.insert(file, contents);
+                Ok(())
+            }),
+        }))
+    }
+}
+
+/// All result files written to one writer, e.g. a socket, as they are
+/// written. The files of different corpus files interleave, so each write
+/// is one frame: a line with the path of the result file and the number of
+/// bytes that follow, separated by a tab, and then the bytes themselves,
+/// e.g. `"g/g-1850s.csv\t4096\n..."`. A frame of 0 bytes completes the
+/// file; the frames of a file that is never completed are incomplete. Paths
+/// with tabs or line breaks are rejected.
+pub struct WriterSink<W: Write + Send + 'static> {
+    writer: Arc<Mutex<W>>,
+}
+
+impl<W: Write + Send + 'static> WriterSink<W> {
+    pub fn new(writer: W) -> Self {
+        Self {
+            writer: Arc::new(Mutex::new(writer)),
+        }
+    }
+}
+
+impl<W: Write + Send + 'static> OutputSink for WriterSink<W> {
+    fn create(&self, file: &Path) -> Result<Box<dyn SinkWriter>> {
+        let file = file.to_string_lossy().into_owned();
+        if file.contains(['\t', '\n', '\r']) {
+            bail!("{file:?}: result file path contains a tab or a line break");
+        }
+        debug!("{file}: writing...");
+        Ok(Box::new(Framed {
+            writer: self.writer.clone(),
+            file,
+        }))
+    }
+}
+
+/// One result file in a [`WriterSink`].
+struct Framed<W: Write + Send> {
+    writer: Arc<Mutex<W>>,
+    file: String,
+}
+
+impl<W: Write + Send> Framed<W> {
+    fn write_frame(&self, buf: &[u8]) -> std::io::Result<()> {
+        let mut writer = self.writer.lock().expect("no thread panicked");
+        writeln!(writer, "{}\t{}", self.file, buf.len())?;
+        writer.write_all(buf)
+    }
+}
+
+impl<W: Write + Send> Write for Framed<W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        if !buf.is_empty() {
+            self.write_frame(buf)?;
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.writer.lock().expect("no thread panicked").flush()
+    }
+}
+
+impl<W: Write + Send> SinkWriter for Framed<W> {
+    fn finish(mut self: Box<Self>) -> Result<()> {
+        self.write_frame(&[])?;
+        self.flush()?;
+        Ok(())
+    }
+}
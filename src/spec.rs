@@ -0,0 +1,310 @@
+//! Declarative search specifications.
+//!
+//! A [`SearchPlan`] can be deserialized from any format that serde supports
+//! and compiled against a loaded corpus into searches.
+
+use crate::{AttributeFilter, Coha, CohaFilter, CohaSearch, Restrictions, SearchOptions, Word};
+use anyhow::{bail, Context, Result};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+
+/// A set of searches together with the options used to run them.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SearchPlan {
+    pub searches: Vec<SearchSpec>,
+    pub options: SearchOptions,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SearchSpec {
+    pub label: String,
+    #[serde(default)]
+    pub slots: Vec<SlotSpec>,
+    /// Regular expression over the running text; used instead of `slots`.
+    #[serde(default)]
+    pub surface_regex: Option<String>,
+    /// Regular expression over the tagged token stream; used instead of `slots`.
+    #[serde(default)]
+    pub tagged_regex: Option<String>,
+    #[serde(default)]
+    pub restrictions: Restrictions,
+}
+
+/// One position of the search pattern.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SlotSpec {
+    pub field: SlotField,
+    #[serde(default)]
+    pub pattern: String,
+    #[serde(default)]
+    pub kind: PatternKind,
+    /// Match the words that do not match the pattern.
+    #[serde(default)]
+    pub negate: bool,
+    /// Leave the columns of this slot out of the output.
+    #[serde(default)]
+    pub hidden: bool,
+    /// Match the pattern against this extra token attribute (see
+    /// [`crate::LoadOptions::token_attributes`]) instead of `field`, which
+    /// is then ignored.
+    #[serde(default)]
+    pub attribute: Option<String>,
+}
+
+/// The lexicon field that a slot pattern is matched against.
+#[derive(Copy, Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SlotField {
+    /// Any word; the pattern is ignored.
+    Any,
+    Word,
+    WordCs,
+    Lemma,
+    Pos,
+}
+
+#[derive(Copy, Debug, Clone, Default, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PatternKind {
+    /// The field equals the pattern.
+    #[default]
+    Exact,
+    /// The field starts with the pattern.
+    Prefix,
+    /// The regular expression matches the field (anywhere, unless anchored).
+    Regex,
+}
+
+/// A search whose filters have been computed; use [`CompiledSearch::search`]
+/// to get a [`CohaSearch`].
+pub struct CompiledSearch {
+    pub label: String,
+    pub filters: Vec<CohaFilter>,
+    pub restrictions: Restrictions,
+    pub hidden_slots: Vec<usize>,
+    pub surface_regex: Option<Regex>,
+    pub tagged_regex: Option<Regex>,
+    pub attribute_filters: Vec<(usize, AttributeFilter)>,
+}
+
+impl CompiledSearch {
+    pub fn search(&self) -> CohaSearch<'_> {
+        CohaSearch {
+            label: self.label.clone(),
+            filter_list: self.filters.iter().collect(),
+            restrictions: self.restrictions.clone(),
+            hidden_slots: self.hidden_slots.clone(),
+            surface_regex: self.surface_regex.clone(),
+            tagged_regex: self.tagged_regex.clone(),
+            annotator: None,
+            attribute_filters: self.attribute_filters.clone(),
+        }
+    }
+}
+
+impl SlotField {
+    pub(crate) fn get<'a>(&self, w: &'a Word) -> &'a str {
+        match self {
+            SlotField::Any => "",
+            SlotField::Word => &w.word,
+            SlotField::WordCs => &w.word_cs,
+            SlotField::Lemma => &w.lemma,
+            SlotField::Pos => &w.pos,
+        }
+    }
+
+    /// Does the field of the word satisfy `p`? POS tags match if any
+    /// alternative of an ambiguous tag does, see [`Coha::pos_matches`].
+    fn matches(&self, coha: &Coha, w: &Word, p: impl Fn(&str) -> bool) -> bool {
+        match self {
+            SlotField::Pos => coha.pos_matches(w, p),
+            _ => p(self.get(w)),
+        }
+    }
+}
+
+impl SlotSpec {
+    pub(crate) fn compile(&self, coha: &Coha) -> Result<CohaFilter> {
+        if self.attribute.is_some() {
+            return Ok(CohaFilter::Any);
+        }
+        if self.field == SlotField::Any {
+            return Ok(if self.negate {
+                !CohaFilter::Any
+            } else {
+                CohaFilter::Any
+            });
+        }
+        let field = self.field;
+        let filter = match self.kind {
+            PatternKind::Exact => match field {
+                SlotField::Word => coha.get_filter_word(&self.pattern),
+                SlotField::WordCs => coha.get_filter_surface_cs(&self.pattern),
+                SlotField::Lemma => coha.get_filter_lemma(&self.pattern),
+                SlotField::Pos => coha.get_filter_pos(&self.pattern),
+                SlotField::Any => unreachable!("handled above"),
+            },
+            PatternKind::Prefix => {
+                coha.get_filter(|w| field.matches(coha, w, |x| x.starts_with(&self.pattern)))
+            }
+            PatternKind::Regex => {
+                let re = Regex::new(&self.pattern)?;
+                coha.get_filter(|w| field.matches(coha, w, |x| re.is_match(x)))
+            }
+        };
+        // a negated slot is the complement of the (usually small) positive
+        // filter, not a filter of nearly the whole lexicon
+        Ok(if self.negate { !filter } else { filter })
+    }
+
+    /// The condition on a token attribute, if the slot has one.
+    fn compile_attribute(&self, coha: &Coha) -> Result<Option<AttributeFilter>> {
+        let Some(name) = &self.attribute else {
+            return Ok(None);
+        };
+        let pattern = self.pattern.clone();
+        let negate = self.negate;
+        let filter = match self.kind {
+            PatternKind::Exact => {
+                coha.get_attribute_filter(name, move |x| (x == pattern) != negate)
+            }
+            PatternKind::Prefix => {
+                coha.get_attribute_filter(name, move |x| x.starts_with(&pattern) != negate)
+            }
+            PatternKind::Regex => {
+                let re = Regex::new(&pattern)?;
+                coha.get_attribute_filter(name, move |x| re.is_match(x) != negate)
+            }
+        };
+        filter.map(Some)
+    }
+}
+
+impl SlotSpec {
+    /// Parse one term of a [`SearchSpec::parse_query`] query, e.g.
+    /// `pos:nn*`, or `@sem:food` for a token attribute.
+    pub fn parse_term(term: &str) -> Result<Self> {
+        let (negate, term) = match term.strip_prefix('!') {
+            Some(rest) => (true, rest),
+            None => (false, term),
+        };
+        let (attribute, term) = match term.strip_prefix('@').and_then(|t| t.split_once(':')) {
+            Some((name, pattern)) if !name.is_empty() => (Some(name.to_owned()), pattern),
+            _ => (None, term),
+        };
+        let (field, pattern) = match term.split_once(':') {
+            _ if attribute.is_some() => (SlotField::Any, term),
+            Some(("word", p)) => (SlotField::Word, p),
+            Some(("cs", p)) => (SlotField::WordCs, p),
+            Some(("lemma", p)) => (SlotField::Lemma, p),
+            Some(("pos", p)) => (SlotField::Pos, p),
+            _ if term == "*" => (SlotField::Any, ""),
+            _ => (SlotField::Word, term),
+        };
+        let (kind, pattern) =
+            if let Some(re) = pattern.strip_prefix('/').and_then(|p| p.strip_suffix('/')) {
+                (PatternKind::Regex, re.to_owned())
+            } else if let Some(prefix) = pattern.strip_suffix('*') {
+                (PatternKind::Prefix, prefix.to_owned())
+            } else {
+                (PatternKind::Exact, pattern.to_owned())
+            };
+        if (field != SlotField::Any || attribute.is_some()) && pattern.is_empty() {
+            bail!("empty pattern in {term:?}");
+        }
+        let pattern = match (field, kind) {
+            (SlotField::Word, PatternKind::Exact | PatternKind::Prefix) => pattern.to_lowercase(),
+            _ => pattern,
+        };
+        Ok(SlotSpec {
+            field,
+            pattern,
+            kind,
+            negate,
+            hidden: false,
+            attribute,
+        })
+    }
+}
+
+impl SearchSpec {
+    /// Parse a one-line query, one slot per whitespace-separated term, e.g.
+    /// `pos:vb* going to pos:/^v.i/`. A term is `*` for any word, or an
+    /// optional field (`word:`, `cs:`, `lemma:`, or `pos:`; the lowercase
+    /// word by default) followed by an exact pattern, a prefix ending in
+    /// `*`, or a regular expression between slashes. A leading `!` negates
+    /// the term. A term `@name:pattern` matches the pattern against a token
+    /// attribute instead.
+    pub fn parse_query(label: &str, query: &str) -> Result<Self> {
+        let slots = query
+            .split_whitespace()
+            .map(SlotSpec::parse_term)
+            .collect::<Result<Vec<_>>>()?;
+        if slots.is_empty() {
+            bail!("empty query");
+        }
+        Ok(SearchSpec {
+            label: label.to_owned(),
+            slots,
+            surface_regex: None,
+            tagged_regex: None,
+            restrictions: Restrictions::default(),
+        })
+    }
+
+    pub fn compile(&self, coha: &Coha) -> Result<CompiledSearch> {
+        let filters = self
+            .slots
+            .iter()
+            .enumerate()
+            .map(|(i, slot)| {
+                slot.compile(coha)
+                    .with_context(|| format!("search {}: slot {}", self.label, i + 1))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        let hidden_slots = self
+            .slots
+            .iter()
+            .enumerate()
+            .filter(|(_, slot)| slot.hidden)
+            .map(|(i, _)| i)
+            .collect();
+        let compile_regex = |re: &Option<String>, what: &str| -> Result<Option<Regex>> {
+            match re {
+                None => Ok(None),
+                Some(re) => {
+                    Ok(Some(Regex::new(re).with_context(|| {
+                        format!("search {}: {what}", self.label)
+                    })?))
+                }
+            }
+        };
+        let mut attribute_filters = Vec::new();
+        for (i, slot) in self.slots.iter().enumerate() {
+            let filter = slot
+                .compile_attribute(coha)
+                .with_context(|| format!("search {}: slot {}", self.label, i + 1))?;
+            if let Some(filter) = filter {
+                attribute_filters.push((i, filter));
+            }
+        }
+        let surface_regex = compile_regex(&self.surface_regex, "surface regex")?;
+        let tagged_regex = compile_regex(&self.tagged_regex, "tagged regex")?;
+        Ok(CompiledSearch {
+            label: self.label.clone(),
+            filters,
+            restrictions: self.restrictions.clone(),
+            hidden_slots,
+            surface_regex,
+            tagged_regex,
+            attribute_filters,
+        })
+    }
+}
+
+impl SearchPlan {
+    pub fn compile(&self, coha: &Coha) -> Result<Vec<CompiledSearch>> {
+        self.searches.iter().map(|s| s.compile(coha)).collect()
+    }
+}
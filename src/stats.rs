@@ -0,0 +1,9 @@
+/// `x * ln(x / e)`, with 0 for `x` = 0: one term of the log-likelihood
+/// statistic G² for an observed frequency `x` with expected frequency `e`.
+pub(crate) fn ll_term(x: f64, e: f64) -> f64 {
+    if x > 0.0 {
+        x * (x / e).ln()
+    } else {
+        0.0
+    }
+}
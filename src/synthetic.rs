@@ -0,0 +1,230 @@
+use crate::random::Rng;
+use crate::{CORPUS_DIR, LEXICON_FILE, SOURCES_FILE};
+use anyhow::{bail, Result};
+use log::info;
+use serde::{Deserialize, Serialize};
+use std::fs::{self, File};
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+/// Vocabulary of the synthetic corpus: (wordCS, lemma, PoS, weight).
+const VOCABULARY: &[(&str, &str, &str, u32)] = &[
+    ("the", "the", "at", 60),
+    ("The", "the", "at", 10),
+    ("a", "a", "at1", 25),
+    ("of", "of", "io", 30),
+    ("and", "and", "cc", 30),
+    ("to", "to", "to", 20),
+    ("in", "in", "ii", 20),
+    ("I", "i", "ppis1", 15),
+    ("he", "he", "pphs1", 15),
+    ("she", "she", "pphs1", 10),
+    ("it", "it", "pph1", 15),
+    ("is", "be", "vbz", 12),
+    ("was", "be", "vbdz", 15),
+    ("am", "be", "vbm", 3),
+    ("are", "be", "vbr", 6),
+    ("were", "be", "vbdr", 6),
+    ("not", "not", "xx", 6),
+    ("n't", "not", "xx", 3),
+    ("do", "do", "vd0", 5),
+    ("did", "do", "vdd", 4),
+    ("go", "go", "vvi", 5),
+    ("going", "go", "vvg", 4),
+    ("went", "go", "vvd", 3),
+    ("eat", "eat", "vvi", 2),
+    ("see", "see", "vvi", 4),
+    ("show", "show", "vvi", 2),
+    ("shew", "shew", "vvi", 1),
+    ("say", "say", "vvi", 3),
+    ("said", "say", "vvd", 5),
+    ("man", "man", "nn1", 6),
+    ("woman", "woman", "nn1", 4),
+    ("house", "house", "nn1", 3),
+    ("day", "day", "nnt1", 4),
+    ("to-day", "to-day", "rt", 1),
+    ("dog", "dog", "nn1", 2),
+    ("time", "time", "nnt1", 4),
+    ("good", "good", "jj", 4),
+    ("old", "old", "jj", 3),
+    ("very", "very", "rg", 3),
+    ("God", "god", "np1", 2),
+    ("America", "america", "np1", 2),
+    ("gon", "gon", "vvg", 1),
+    ("na", "na", "to", 1),
+    (",", ",", "y", 25),
+    ("\"", "\"", "y", 4),
+];
+
+/// Verbs that follow "going to" and "gon na" in the inserted phrases.
+const VERBS: &[&str] = &["go", "eat", "see", "show", "say", "do"];
+
+const GENRES: &[&str] = &["FIC", "MAG", "NEWS", "NF"];
+
+/// A small corpus with random text in the layout of the COHA download
+/// version, for testing search code without access to the licensed corpus.
+///
+/// The same seed and parameters always give the same corpus. The text is a
+/// random sequence of common words with sentence and paragraph breaks,
+/// occasional "be going to V" and "gon na V" phrases, and runs of removed
+/// tokens (`@`) as in COHA.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SyntheticCorpus {
+    pub seed: u64,
+    /// First decade, e.g. 1810.
+    pub first_decade: u16,
+    pub decades: u16,
+    pub texts_per_decade: usize,
+    pub min_words: usize,
+    pub max_words: usize,
+}
+
+impl Default for SyntheticCorpus {
+    fn default() -> Self {
+        Self {
+            seed: 1,
+            first_decade: 1810,
+            decades: 3,
+            texts_per_decade: 8,
+            min_words: 200,
+            max_words: 1000,
+        }
+    }
+}
+
+/// The lexicon of the synthetic corpus: the vocabulary, followed by the
+/// sentence, paragraph, and removed-token markers. Word IDs start at 1.
+fn lexicon() -> Vec<(&'static str, &'static str, &'static str)> {
+    let mut lexicon: Vec<_> = VOCABULARY.iter().map(|&(w, l, p, _)| (w, l, p)).collect();
+    lexicon.extend([(".", ".", "y"), ("<p>", "<p>", "y"), ("@", "@", "fu")]);
+    lexicon
+}
+
+fn word_id(word_cs: &str) -> usize {
+    lexicon()
+        .iter()
+        .position(|&(w, _, _)| w == word_cs)
+        .expect("word in lexicon")
+        + 1
+}
+
+impl SyntheticCorpus {
+    fn check(&self) -> Result<()> {
+        if self.decades == 0 || self.texts_per_decade == 0 {
+            bail!("synthetic corpus: no texts");
+        }
+        if self.min_words == 0 || self.min_words > self.max_words {
+            bail!("synthetic corpus: invalid number of words per text");
+        }
+        if !self.first_decade.is_multiple_of(10) {
+            bail!("synthetic corpus: first decade must be a multiple of 10");
+        }
+        Ok(())
+    }
+
+    /// The word IDs of one text.
+    fn text(&self, rng: &mut Rng, words: usize) -> Vec<usize> {
+        let total_weight: u32 = VOCABULARY.iter().map(|w| w.3).sum();
+        let period = word_id(".");
+        let mut text = Vec::with_capacity(words);
+        while text.len() < words {
+            let r = rng.below(1000);
+            if r < 10 {
+                let be = ["am", "is", "was", "are", "were"][rng.below(5)];
+                let verb = VERBS[rng.below(VERBS.len())];
+                text.extend([be, "going", "to", verb].map(word_id));
+            } else if r < 13 {
+                let verb = VERBS[rng.below(VERBS.len())];
+                text.extend(["gon", "na", verb].map(word_id));
+            } else if r < 15 {
+                text.extend([word_id("@"); 10]);
+            } else if r < 80 {
+                text.push(period);
+            } else if r < 85 && text.last() == Some(&period) {
+                text.push(word_id("<p>"));
+            } else {
+                let mut x = rng.below(total_weight as usize) as u32;
+                let i = VOCABULARY
+                    .iter()
+                    .position(|w| {
+                        if x < w.3 {
+                            true
+                        } else {
+                            x -= w.3;
+                            false
+                        }
+                    })
+                    .expect("weights add up");
+                text.push(i + 1);
+            }
+        }
+        text.truncate(words);
+        text
+    }
+
+    /// Write the corpus to `root_dir`: the sources table, the lexicon, and
+    /// one corpus file per decade.
+    pub fn write(&self, root_dir: &Path) -> Result<()> {
+        self.check()?;
+        let mut rng = Rng(self.seed);
+        let lexicon_path = root_dir.join(LEXICON_FILE);
+        fs::create_dir_all(lexicon_path.parent().expect("shared directory"))?;
+
+        let mut lexicon_writer = BufWriter::new(File::create(lexicon_path)?);
+        write!(
+            lexicon_writer,
+            "wID\twordCS\tword\tlemma\tPoS\r\n----\t----\t----\t----\t----\r\n\r\n"
+        )?;
+        for (i, (word_cs, lemma, pos)) in lexicon().into_iter().enumerate() {
+            let word = word_cs.to_lowercase();
+            write!(
+                lexicon_writer,
+                "{}\t{word_cs}\t{word}\t{lemma}\t{pos}\r\n",
+                i + 1
+            )?;
+        }
+        lexicon_writer.flush()?;
+
+        let mut sources_writer = BufWriter::new(File::create(root_dir.join(SOURCES_FILE))?);
+        writeln!(
+            sources_writer,
+            "textID\t # words \tgenre\tyear\ttitle\tauthor\tPublication information\t\
+             Library of Congress classification (NF)\tFIXED"
+        )?;
+        let mut text_id = 0;
+        let mut tokens = 0;
+        for d in 0..self.decades {
+            let decade = self.first_decade + 10 * d;
+            let dir = root_dir.join(CORPUS_DIR).join(format!("wlp_{decade}s"));
+            fs::create_dir_all(&dir)?;
+            let mut db_writer =
+                BufWriter::new(File::create(dir.join(format!("coha_db_{decade}s.txt")))?);
+            let mut token_id = 0;
+            for i in 0..self.texts_per_decade {
+                text_id += 1;
+                let words = rng.range(self.min_words, self.max_words);
+                let genre = GENRES[i % GENRES.len()];
+                let year = decade + rng.below(10) as u16;
+                let author = rng.below(self.texts_per_decade) + 1;
+                writeln!(
+                    sources_writer,
+                    "{text_id}\t{words}\t{genre}\t{year}\tTitle {text_id}\tAuthor {author}\t\
+                     Synthetic Press, {year}\t\t"
+                )?;
+                for w in self.text(&mut rng, words) {
+                    token_id += 1;
+                    writeln!(db_writer, "{text_id}\t{token_id}\t{w}")?;
+                }
+                tokens += words;
+            }
+            db_writer.flush()?;
+        }
+        sources_writer.flush()?;
+        info!(
+            "{}: synthetic corpus with {text_id} texts, {tokens} tokens",
+            root_dir.to_string_lossy()
+        );
+        Ok(())
+    }
+}
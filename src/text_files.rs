@@ -0,0 +1,109 @@
+use crate::lines::LineReader;
+use crate::{CohaFile, TextId};
+use anyhow::Result;
+use std::fs::File;
+use std::io::{self, BufReader, ErrorKind, Read, Seek, SeekFrom};
+use std::path::PathBuf;
+
+type Lines = LineReader<BufReader<Box<dyn Read>>>;
+
+/// The token lines of a corpus file. In the one-file-per-text layout (see
+/// [`crate::LoadOptions::text_file_pattern`]), the lines of the files of
+/// all texts one after another, each line starting with the text ID taken
+/// from the file name, so that all lines have the same columns as in the
+/// usual layout.
+pub(crate) struct CorpusLines {
+    /// The file being read, and the text ID of its lines if they need one.
+    current: Option<(Option<String>, Lines)>,
+    /// Files with one text each that have not been read yet.
+    rest: std::vec::IntoIter<(TextId, PathBuf)>,
+    /// Number of columns of a line without the text ID.
+    columns: usize,
+    line: String,
+}
+
+impl CorpusLines {
+    /// The lines of one file in the usual layout.
+    pub(crate) fn from_reader(reader: Box<dyn Read>) -> Self {
+        Self {
+            current: Some((None, LineReader::new(BufReader::new(reader)))),
+            rest: Vec::new().into_iter(),
+            columns: 0,
+            line: String::new(),
+        }
+    }
+
+    /// The lines of files with one text each, with `attributes` extra
+    /// columns after the word ID.
+    pub(crate) fn texts(files: Vec<(TextId, PathBuf)>, attributes: usize) -> Self {
+        Self {
+            current: None,
+            rest: files.into_iter(),
+            columns: 2 + attributes,
+            line: String::new(),
+        }
+    }
+
+    /// Append the next line to `s`, see [`LineReader::read_line`]. Returns
+    /// the number of bytes read from the file; 0 at the end of the last
+    /// file.
+    pub(crate) fn read_line(&mut self, s: &mut String) -> io::Result<usize> {
+        loop {
+            if let Some((text_id, br)) = &mut self.current {
+                let Some(text_id) = text_id else {
+                    return br.read_line(s);
+                };
+                self.line.clear();
+                let n = br.read_line(&mut self.line)?;
+                if n > 0 {
+                    // the text ID of the file name replaces a text ID column
+                    let columns = self.line.trim_end_matches(['\n', '\r']).split('\t').count();
+                    let line = match self.line.split_once('\t') {
+                        Some((_, rest)) if columns > self.columns => rest,
+                        _ => &self.line,
+                    };
+                    s.push_str(text_id);
+                    s.push('\t');
+                    s.push_str(line);
+                    return Ok(n);
+                }
+            }
+            let Some((text_id, path)) = self.rest.next() else {
+                self.current = None;
+                return Ok(0);
+            };
+            let reader: Box<dyn Read> = Box::new(File::open(path)?);
+            let br = LineReader::new(BufReader::new(reader));
+            self.current = Some((Some(text_id.0.to_string()), br));
+        }
+    }
+}
+
+impl CohaFile {
+    /// Read the lines of the corpus file; `None` if the file is missing.
+    pub(crate) fn lines(&self) -> Result<Option<CorpusLines>> {
+        if !self.text_files.is_empty() {
+            return Ok(Some(CorpusLines::texts(
+                self.text_files.clone(),
+                self.attribute_columns,
+            )));
+        }
+        match File::open(&self.corpus_path) {
+            Ok(file) => Ok(Some(CorpusLines::from_reader(Box::new(file)))),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// The lines of a corpus file in the usual layout from byte `offset` on.
+    pub(crate) fn lines_from(&self, offset: u64) -> Result<CorpusLines> {
+        let mut file = File::open(&self.corpus_path)?;
+        file.seek(SeekFrom::Start(offset))?;
+        Ok(CorpusLines::from_reader(Box::new(file)))
+    }
+
+    /// Does the corpus file exist?
+    pub(crate) fn exists(&self) -> bool {
+        !self.text_files.is_empty() || self.corpus_path.exists()
+    }
+}
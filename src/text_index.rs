@@ -0,0 +1,288 @@
+use crate::lines::LineReader;
+use crate::manifest::Fnv1a;
+use crate::{Coha, CohaFile, TextId};
+use anyhow::Result;
+use log::{debug, info, warn};
+use rayon::prelude::*;
+use rustc_hash::{FxHashMap, FxHashSet};
+use serde::{Deserialize, Serialize};
+use std::fs::{self, File};
+use std::io::{BufReader, BufWriter, ErrorKind, Write};
+use std::path::Path;
+use std::time::UNIX_EPOCH;
+
+/// Byte range of one text within its corpus file.
+#[derive(Copy, Debug, Clone, Serialize, Deserialize)]
+pub struct TextRange {
+    pub text_id: TextId,
+    /// Offset of the first line of the text.
+    pub start: u64,
+    /// Offset after the last line of the text.
+    pub end: u64,
+}
+
+/// The byte ranges of the texts of one corpus file.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct FileTextIndex {
+    /// Corpus file identifier (decade, e.g. "1850s").
+    pub identifier: String,
+    /// Size of the corpus file when it was indexed.
+    pub bytes: u64,
+    /// Modification time of the corpus file when it was indexed, in
+    /// nanoseconds since the Unix epoch, so that a change within the same
+    /// second is noticed.
+    pub modified_unix_nanos: Option<u64>,
+    /// FNV-1a hash of the lines of the corpus file, to recognize an
+    /// unchanged file with a new modification time.
+    #[serde(default)]
+    pub hash: Option<u64>,
+    /// Sorted by text ID. Texts whose lines are not contiguous in the file
+    /// are not included.
+    pub texts: Vec<TextRange>,
+}
+
+/// Byte ranges of the texts in the corpus files, so that a single text can
+/// be read without scanning its entire corpus file, see
+/// [`Coha::text_index`].
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct TextIndex {
+    pub files: Vec<FileTextIndex>,
+}
+
+impl TextIndex {
+    /// The identifier of the corpus file of a text and its byte range.
+    pub(crate) fn locate(&self, text_id: TextId) -> Option<(&str, TextRange)> {
+        self.files.iter().find_map(|f| {
+            let i = f.texts.binary_search_by_key(&text_id, |t| t.text_id).ok()?;
+            Some((f.identifier.as_str(), f.texts[i]))
+        })
+    }
+}
+
+/// Size and modification time of a file; 0 and `None` if it does not exist.
+fn file_state(path: &Path) -> Result<(u64, Option<u64>)> {
+    match fs::metadata(path) {
+        Ok(m) => {
+            let modified = m
+                .modified()
+                .ok()
+                .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+                .and_then(|d| u64::try_from(d.as_nanos()).ok());
+            Ok((m.len(), modified))
+        }
+        Err(e) if e.kind() == ErrorKind::NotFound => Ok((0, None)),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// FNV-1a hash of the lines of a corpus file, as in
+/// [`FileTextIndex::hash`].
+fn line_hash(cf: &CohaFile) -> Result<u64> {
+    let Some(mut br) = cf.lines()? else {
+        return Ok(Fnv1a::default().finish());
+    };
+    let mut s = String::new();
+    let mut hash = Fnv1a::default();
+    while br.read_line(&mut s)? > 0 {
+        hash.write(s.as_bytes());
+        s.clear();
+    }
+    Ok(hash.finish())
+}
+
+impl FileTextIndex {
+    /// This index if it still describes the corpus file: the size and
+    /// modification time are unchanged, or the size is unchanged and the
+    /// contents have the same hash (then with the new modification time).
+    fn if_current(&self, cf: &CohaFile) -> Result<Option<FileTextIndex>> {
+        if self.identifier != cf.identifier {
+            return Ok(None);
+        }
+        let (bytes, modified_unix_nanos) = cf.state()?;
+        if (bytes, modified_unix_nanos) == (self.bytes, self.modified_unix_nanos) {
+            return Ok(Some(self.clone()));
+        }
+        if bytes != self.bytes || modified_unix_nanos.is_none() || self.hash.is_none() {
+            return Ok(None);
+        }
+        if Some(line_hash(cf)?) != self.hash {
+            return Ok(None);
+        }
+        Ok(Some(FileTextIndex {
+            modified_unix_nanos,
+            ..self.clone()
+        }))
+    }
+}
+
+impl CohaFile {
+    /// Total size and latest modification time of the corpus file, or of
+    /// the files of its texts.
+    fn state(&self) -> Result<(u64, Option<u64>)> {
+        if self.text_files.is_empty() {
+            return file_state(&self.corpus_path);
+        }
+        let mut state = (0, None);
+        for (_, path) in &self.text_files {
+            let (bytes, modified) = file_state(path)?;
+            state.0 += bytes;
+            state.1 = state.1.max(modified);
+        }
+        Ok(state)
+    }
+
+    /// Find the byte range of each text in this corpus file. Files with
+    /// one text each are not indexed.
+    fn index_texts(&self, lenient: bool) -> Result<FileTextIndex> {
+        let path = &self.corpus_path;
+        let (bytes, modified_unix_nanos) = self.state()?;
+        let mut index = FileTextIndex {
+            identifier: self.identifier.clone(),
+            bytes,
+            modified_unix_nanos,
+            hash: None,
+            texts: Vec::new(),
+        };
+        if (bytes == 0 && modified_unix_nanos.is_none()) || !self.text_files.is_empty() {
+            return Ok(index);
+        }
+        debug!("{}: indexing...", path.to_string_lossy());
+        let mut br = LineReader::new(BufReader::new(File::open(path)?));
+        let mut s = String::new();
+        let mut offset = 0;
+        let mut hash = Fnv1a::default();
+        let mut seen: FxHashSet<TextId> = FxHashSet::default();
+        let mut attributes = self.attribute_parser();
+        let mut split: FxHashSet<TextId> = FxHashSet::default();
+        loop {
+            let n = br.read_line(&mut s)? as u64;
+            if n == 0 {
+                break;
+            }
+            hash.write(s.as_bytes());
+            match self.parse_token(&s, &mut attributes) {
+                Ok(token) => match index.texts.last_mut() {
+                    Some(last) if last.text_id == token.text_id => last.end = offset + n,
+                    _ => {
+                        if !seen.insert(token.text_id) {
+                            split.insert(token.text_id);
+                        }
+                        index.texts.push(TextRange {
+                            text_id: token.text_id,
+                            start: offset,
+                            end: offset + n,
+                        });
+                    }
+                },
+                Err(_) if lenient => {
+                    if let Some(last) = index.texts.last_mut() {
+                        last.end = offset + n;
+                    }
+                }
+                Err(e) => return Err(e),
+            }
+            offset += n;
+            s.clear();
+        }
+        if !split.is_empty() {
+            warn!(
+                "{}: {} texts are not contiguous and are not indexed",
+                path.to_string_lossy(),
+                split.len()
+            );
+            index.texts.retain(|t| !split.contains(&t.text_id));
+        }
+        index.texts.sort_by_key(|t| t.text_id);
+        index.hash = Some(hash.finish());
+        Ok(index)
+    }
+}
+
+impl Coha {
+    /// Scan all corpus files and find the byte range of each text.
+    fn build_text_index(&self) -> Result<TextIndex> {
+        let lenient = self.load_options.lenient;
+        let files = self
+            .coha_files
+            .par_iter()
+            .map(|cf| cf.index_texts(lenient))
+            .collect::<Result<Vec<_>>>()?;
+        let index = TextIndex { files };
+        info!(
+            "text index: {} texts",
+            index.files.iter().map(|f| f.texts.len()).sum::<usize>()
+        );
+        Ok(index)
+    }
+
+    /// Byte ranges of the texts in the corpus files. Once the index exists,
+    /// [`Coha::extract_text`] and [`Coha::hit_context`] read only the lines
+    /// of the requested text instead of scanning its corpus file. The first
+    /// call reads all corpus files, and the result is kept for later calls.
+    pub fn text_index(&self) -> Result<&TextIndex> {
+        if let Some(index) = self.text_index.get() {
+            return Ok(index);
+        }
+        let index = self.build_text_index()?;
+        Ok(self.text_index.get_or_init(|| index))
+    }
+
+    /// [`Coha::text_index`], kept in a JSON file, e.g. next to the corpus.
+    /// The index of each corpus file is read from `path` if the file has
+    /// not changed (same size and modification time, or same size and
+    /// contents), and only the corpus files that have changed or are new
+    /// are indexed again. The updated index is written to `path`.
+    pub fn text_index_cached(&self, path: &Path) -> Result<&TextIndex> {
+        let mut cached = TextIndex::default();
+        if path.exists() {
+            debug!("{}: reading...", path.to_string_lossy());
+            let file = BufReader::new(File::open(path)?);
+            match serde_json::from_reader::<_, TextIndex>(file) {
+                Ok(index) => cached = index,
+                Err(e) => warn!("{}: ignoring invalid index: {e}", path.to_string_lossy()),
+            }
+        }
+        let by_identifier: FxHashMap<&str, &FileTextIndex> = cached
+            .files
+            .iter()
+            .map(|f| (f.identifier.as_str(), f))
+            .collect();
+        let lenient = self.load_options.lenient;
+        let files = self
+            .coha_files
+            .par_iter()
+            .map(|cf| {
+                if let Some(f) = by_identifier.get(cf.identifier.as_str()) {
+                    if let Some(f) = f.if_current(cf)? {
+                        return Ok((f, false));
+                    }
+                }
+                Ok((cf.index_texts(lenient)?, true))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        let reindexed = files.iter().filter(|(_, reindexed)| *reindexed).count();
+        info!(
+            "{}: {} of {} corpus files indexed again",
+            path.to_string_lossy(),
+            reindexed,
+            files.len()
+        );
+        let index = TextIndex {
+            files: files.into_iter().map(|(f, _)| f).collect(),
+        };
+        let changed = reindexed > 0
+            || index.files.len() != cached.files.len()
+            || index.files.iter().zip(&cached.files).any(|(a, b)| {
+                a.identifier != b.identifier || a.modified_unix_nanos != b.modified_unix_nanos
+            });
+        let _ = self.text_index.set(index);
+        let index = self.text_index()?;
+        if changed {
+            debug!("{}: writing...", path.to_string_lossy());
+            let mut writer = BufWriter::new(File::create(path)?);
+            serde_json::to_writer(&mut writer, index)?;
+            writer.flush()?;
+        }
+        Ok(index)
+    }
+}
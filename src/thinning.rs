@@ -0,0 +1,63 @@
+use crate::matcher::Span;
+use crate::random::Rng;
+use crate::TextId;
+use anyhow::{bail, Result};
+use serde::{Deserialize, Serialize};
+
+/// Keep hits at a fixed rate per amount of text instead of all hits, for a
+/// sample that is proportional to the size of the corpus: a text of `t`
+/// tokens keeps `t / tokens_per_hit` of its hits, chosen at random, with a
+/// remainder kept with the corresponding probability. E.g. with the default
+/// rate a text of 25,000 tokens keeps two hits and a third one with
+/// probability 1/2.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Thinning {
+    /// Tokens of text per kept hit.
+    pub tokens_per_hit: usize,
+    /// Seed of the random number generator; the same seed gives the same
+    /// sample. Each text is sampled independently of the other texts, so
+    /// the sample does not depend on the order in which texts are searched.
+    pub seed: u64,
+}
+
+impl Default for Thinning {
+    fn default() -> Self {
+        Self {
+            tokens_per_hit: 10_000,
+            seed: 1,
+        }
+    }
+}
+
+impl Thinning {
+    pub(crate) fn check(&self) -> Result<()> {
+        if self.tokens_per_hit == 0 {
+            bail!("thinning: tokens per hit must be positive");
+        }
+        Ok(())
+    }
+
+    /// Keep a random subset of the hits of a text of `tokens` tokens, in
+    /// their original order. Returns the number of hits left out.
+    pub(crate) fn thin(&self, text_id: TextId, tokens: usize, spans: &mut Vec<Span>) -> usize {
+        let mut rng = Rng::for_item(self.seed, text_id.0);
+        let rate = self.tokens_per_hit;
+        let mut keep = tokens / rate;
+        if rng.below(rate) < tokens % rate {
+            keep += 1;
+        }
+        if keep >= spans.len() {
+            return 0;
+        }
+        // a partial Fisher–Yates shuffle picks the kept hits
+        for i in 0..keep {
+            let j = i + rng.below(spans.len() - i);
+            spans.swap(i, j);
+        }
+        let removed = spans.len() - keep;
+        spans.truncate(keep);
+        spans.sort_by_key(|s| s.start);
+        removed
+    }
+}
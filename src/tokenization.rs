@@ -0,0 +1,133 @@
+use crate::{Coha, CohaFilter};
+
+const NEGATION: &str = "n't";
+const CLITICS: &[&str] = &["'s", "'ll", "'re", "'ve", "'d", "'m", "'"];
+
+/// How phrases in natural orthography are split into COHA tokens.
+///
+/// COHA tokenizes e.g. "don't" as "do" + "n't" and "John's" as "John" + "'s".
+#[derive(Debug, Clone)]
+pub struct Tokenization {
+    /// Split negative contractions: "don't" → "do", "n't".
+    pub split_negation: bool,
+    /// Split clitics and possessives: "John's" → "John", "'s".
+    pub split_clitics: bool,
+    /// Split at hyphens: "to-day" → "to", "-", "day".
+    pub split_hyphens: bool,
+}
+
+impl Default for Tokenization {
+    fn default() -> Self {
+        Self {
+            split_negation: true,
+            split_clitics: true,
+            split_hyphens: false,
+        }
+    }
+}
+
+impl Tokenization {
+    /// Split a phrase into the tokens that COHA would use for it.
+    pub fn tokenize(&self, phrase: &str) -> Vec<String> {
+        let mut tokens = Vec::new();
+        for word in phrase.split_whitespace() {
+            if self.split_hyphens {
+                for (i, part) in word.split('-').enumerate() {
+                    if i > 0 {
+                        tokens.push("-".to_owned());
+                    }
+                    self.split_word(part, &mut tokens);
+                }
+            } else {
+                self.split_word(word, &mut tokens);
+            }
+        }
+        tokens
+    }
+
+    fn split_word(&self, word: &str, tokens: &mut Vec<String>) {
+        if word.is_empty() {
+            return;
+        }
+        let mut suffixes: Vec<&str> = Vec::new();
+        if self.split_negation {
+            suffixes.push(NEGATION);
+        }
+        if self.split_clitics {
+            suffixes.extend(CLITICS);
+        }
+        for suffix in suffixes {
+            if let Some((stem, rest)) = split_suffix(word, suffix) {
+                tokens.push(stem.to_owned());
+                tokens.push(rest.to_owned());
+                return;
+            }
+        }
+        tokens.push(word.to_owned());
+    }
+}
+
+/// Split off `suffix` (ignoring ASCII case), leaving a non-empty stem.
+fn split_suffix<'a>(word: &'a str, suffix: &str) -> Option<(&'a str, &'a str)> {
+    if word.len() <= suffix.len() {
+        return None;
+    }
+    let i = word.len() - suffix.len();
+    if !word.is_char_boundary(i) || !word[i..].eq_ignore_ascii_case(suffix) {
+        return None;
+    }
+    Some(word.split_at(i))
+}
+
+impl Coha {
+    /// Filters that match a phrase written in natural orthography, one filter
+    /// per COHA token. Matching is case-insensitive.
+    pub fn get_filters_for_phrase(
+        &self,
+        phrase: &str,
+        tokenization: &Tokenization,
+    ) -> Vec<CohaFilter> {
+        tokenization
+            .tokenize(phrase)
+            .into_iter()
+            .map(|token| self.get_filter_word(&token.to_lowercase()))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tokens(tokenization: &Tokenization, phrase: &str) -> Vec<String> {
+        tokenization.tokenize(phrase)
+    }
+
+    #[test]
+    fn default_splits_negation_and_clitics() {
+        let t = Tokenization::default();
+        assert_eq!(tokens(&t, "don't go"), ["do", "n't", "go"]);
+        assert_eq!(tokens(&t, "John's  dog"), ["John", "'s", "dog"]);
+        assert_eq!(tokens(&t, "we'll"), ["we", "'ll"]);
+        assert_eq!(tokens(&t, "DON'T"), ["DO", "N'T"]);
+        assert_eq!(tokens(&t, "to-day"), ["to-day"]);
+    }
+
+    #[test]
+    fn suffix_needs_a_stem() {
+        let t = Tokenization::default();
+        assert_eq!(tokens(&t, "'s n't"), ["'s", "n't"]);
+    }
+
+    #[test]
+    fn options() {
+        let t = Tokenization {
+            split_negation: false,
+            split_clitics: false,
+            split_hyphens: true,
+        };
+        assert_eq!(tokens(&t, "don't John's"), ["don't", "John's"]);
+        assert_eq!(tokens(&t, "to-day"), ["to", "-", "day"]);
+        assert_eq!(tokens(&t, "-ish"), ["-", "ish"]);
+    }
+}
@@ -0,0 +1,242 @@
+use crate::random::Rng;
+use crate::{Coha, Restrictions, SearchReport, WarningKind};
+use anyhow::{bail, Result};
+use rustc_hash::FxHashSet;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::io::Write;
+
+/// Parameters of text-level bootstrap confidence intervals: the texts of
+/// each decade are resampled with replacement, and the interval is given by
+/// the percentiles of the resampled frequencies.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Bootstrap {
+    /// Number of resamples.
+    pub samples: usize,
+    /// Seed of the random number generator; the same seed gives the same
+    /// intervals.
+    pub seed: u64,
+    /// Confidence level, e.g. 0.95.
+    pub confidence: f64,
+}
+
+impl Default for Bootstrap {
+    fn default() -> Self {
+        Self {
+            samples: 1000,
+            seed: 1,
+            confidence: 0.95,
+        }
+    }
+}
+
+/// Hits per million words in one decade, see [`Coha::decade_frequencies`].
+#[derive(Debug, Clone, Serialize)]
+pub struct DecadeFrequency {
+    /// Decade, e.g. "1850s".
+    pub decade: String,
+    pub texts: usize,
+    pub words: usize,
+    pub hits: usize,
+    pub per_million: f64,
+    /// Bootstrap confidence interval of `per_million`, if requested.
+    pub interval: Option<(f64, f64)>,
+}
+
+fn per_million(hits: usize, words: usize) -> f64 {
+    if words == 0 {
+        0.0
+    } else {
+        hits as f64 * 1e6 / words as f64
+    }
+}
+
+/// The value at quantile `q` of sorted `values`, by linear interpolation.
+fn quantile(values: &[f64], q: f64) -> f64 {
+    let x = q * (values.len() - 1) as f64;
+    let i = x.floor() as usize;
+    let j = (i + 1).min(values.len() - 1);
+    values[i] + (x - i as f64) * (values[j] - values[i])
+}
+
+impl Bootstrap {
+    fn check(&self) -> Result<()> {
+        if self.samples == 0 {
+            bail!("bootstrap: no samples");
+        }
+        if !(self.confidence > 0.0 && self.confidence < 1.0) {
+            bail!("bootstrap: confidence level must be between 0 and 1");
+        }
+        Ok(())
+    }
+
+    /// Confidence interval of the hits per million words in `texts`, given
+    /// as (hits, words) per text.
+    fn interval(&self, rng: &mut Rng, texts: &[(usize, usize)]) -> (f64, f64) {
+        let mut values: Vec<f64> = (0..self.samples)
+            .map(|_| {
+                let (mut hits, mut words) = (0, 0);
+                for _ in 0..texts.len() {
+                    let (h, w) = texts[rng.below(texts.len())];
+                    hits += h;
+                    words += w;
+                }
+                per_million(hits, words)
+            })
+            .collect();
+        values.sort_by(f64::total_cmp);
+        let alpha = (1.0 - self.confidence) / 2.0;
+        (quantile(&values, alpha), quantile(&values, 1.0 - alpha))
+    }
+}
+
+impl Coha {
+    /// Hits per million words in each decade of the search `label` in
+    /// `report`, relative to the words of the texts that `restrictions`
+    /// allows (normally the restrictions of the search), according to the
+    /// sources table. Only the decades with a corpus file are included. With
+    /// `bootstrap`, each decade also gets a confidence interval from
+    /// resampling its texts, which reflects that hits cluster in texts.
+    ///
+    /// Fails if not all hits were counted, because a limit was reached or
+    /// hits were thinned.
+    pub fn decade_frequencies(
+        &self,
+        report: &SearchReport,
+        label: &str,
+        restrictions: &Restrictions,
+        bootstrap: Option<&Bootstrap>,
+    ) -> Result<Vec<DecadeFrequency>> {
+        if let Some(bootstrap) = bootstrap {
+            bootstrap.check()?;
+        }
+        let Some(counts) = report.searches.iter().find(|s| s.label == label) else {
+            bail!("{label}: no such search in the report");
+        };
+        let limited = report
+            .warnings
+            .iter()
+            .any(|w| w.kind == WarningKind::LimitReached);
+        let files = report.files.iter().filter(|f| f.label == label);
+        if limited || files.clone().any(|f| f.truncated || f.stopped_early) {
+            bail!("{label}: a limit was reached, so not all hits were counted");
+        }
+        if files.clone().any(|f| f.thinned > 0) {
+            bail!("{label}: hits were thinned, so not all hits were counted");
+        }
+        let decades: FxHashSet<&str> = self
+            .coha_files
+            .iter()
+            .map(|cf| cf.identifier.as_str())
+            .collect();
+        let mut by_decade: BTreeMap<String, Vec<(usize, usize)>> = BTreeMap::new();
+        let mut sources: Vec<_> = self
+            .sources
+            .values()
+            .filter(|s| restrictions.allows(s))
+            .collect();
+        sources.sort_by_key(|s| s.text_id);
+        for source in sources {
+            let decade = format!("{}s", source.year.0 / 10 * 10);
+            if !decades.contains(decade.as_str()) {
+                continue;
+            }
+            let hits = counts.by_text.get(&source.text_id).copied().unwrap_or(0);
+            by_decade
+                .entry(decade)
+                .or_default()
+                .push((hits, source.words));
+        }
+        let mut rng = Rng(bootstrap.map_or(0, |b| b.seed));
+        let mut result = Vec::new();
+        for (decade, texts) in by_decade {
+            let hits = texts.iter().map(|t| t.0).sum();
+            let words = texts.iter().map(|t| t.1).sum();
+            result.push(DecadeFrequency {
+                decade,
+                texts: texts.len(),
+                words,
+                hits,
+                per_million: per_million(hits, words),
+                interval: bootstrap.map(|b| b.interval(&mut rng, &texts)),
+            });
+        }
+        Ok(result)
+    }
+}
+
+/// Write the frequencies as CSV, with empty interval columns if no interval
+/// was computed.
+pub fn write_decade_frequencies<W: Write>(
+    writer: W,
+    frequencies: &[DecadeFrequency],
+) -> Result<()> {
+    let mut writer = csv::Writer::from_writer(writer);
+    writer.write_record([
+        "decade",
+        "texts",
+        "words",
+        "hits",
+        "per million",
+        "lower",
+        "upper",
+    ])?;
+    for f in frequencies {
+        let (lower, upper) = match f.interval {
+            Some((lower, upper)) => (format!("{lower:.3}"), format!("{upper:.3}")),
+            None => (String::new(), String::new()),
+        };
+        writer.write_record([
+            f.decade.clone(),
+            f.texts.to_string(),
+            f.words.to_string(),
+            f.hits.to_string(),
+            format!("{:.3}", f.per_million),
+            lower,
+            upper,
+        ])?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn quantile_interpolates() {
+        let values = [1.0, 2.0, 3.0, 4.0, 5.0];
+        assert_eq!(quantile(&values, 0.0), 1.0);
+        assert_eq!(quantile(&values, 0.25), 2.0);
+        assert_eq!(quantile(&values, 0.5), 3.0);
+        assert!((quantile(&values, 0.1) - 1.4).abs() < 1e-9);
+        assert!((quantile(&values, 0.975) - 4.9).abs() < 1e-9);
+        assert_eq!(quantile(&values, 1.0), 5.0);
+        assert_eq!(quantile(&[7.0], 0.3), 7.0);
+    }
+
+    #[test]
+    fn interval_of_uniform_texts() {
+        // every resample has 5000 hits per million words
+        let bootstrap = Bootstrap::default();
+        let texts = [(5, 1000), (10, 2000), (15, 3000)];
+        let (low, high) = bootstrap.interval(&mut Rng(bootstrap.seed), &texts);
+        assert!((low - 5000.0).abs() < 1e-6);
+        assert!((high - 5000.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn interval_contains_estimate() {
+        let bootstrap = Bootstrap::default();
+        let texts = [(0, 1000), (2, 1000), (10, 1000), (4, 1000)];
+        let (low, high) = bootstrap.interval(&mut Rng(bootstrap.seed), &texts);
+        assert!(low <= 4000.0 && 4000.0 <= high);
+        assert!(low >= 0.0 && high <= 10000.0);
+        assert_eq!(
+            bootstrap.interval(&mut Rng(bootstrap.seed), &texts),
+            (low, high)
+        );
+    }
+}
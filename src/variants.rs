@@ -0,0 +1,145 @@
+use crate::{Coha, CohaFilter};
+use anyhow::{bail, Result};
+use log::{debug, info};
+use rustc_hash::{FxHashMap, FxHashSet};
+use std::path::Path;
+
+/// A starter list of (modern form, historical variant) pairs found in the
+/// early decades of COHA.
+const BUILTIN: &[(&str, &str)] = &[
+    ("today", "to-day"),
+    ("tomorrow", "to-morrow"),
+    ("tonight", "to-night"),
+    ("show", "shew"),
+    ("shows", "shews"),
+    ("showed", "shewed"),
+    ("shown", "shewn"),
+    ("showing", "shewing"),
+    ("anything", "any thing"),
+    ("everything", "every thing"),
+    ("something", "some thing"),
+    ("anyone", "any one"),
+    ("connection", "connexion"),
+    ("reflection", "reflexion"),
+    ("stayed", "staid"),
+    ("clue", "clew"),
+    ("jail", "gaol"),
+    ("wagon", "waggon"),
+    ("choose", "chuse"),
+    ("complete", "compleat"),
+    ("public", "publick"),
+    ("music", "musick"),
+    ("ankle", "ancle"),
+    ("develop", "develope"),
+    ("control", "controul"),
+    ("recall", "recal"),
+    ("befall", "befal"),
+    ("ecstasy", "extacy"),
+    ("negotiate", "negociate"),
+    ("frenzy", "phrenzy"),
+    ("cigar", "segar"),
+    ("honor", "honour"),
+    ("color", "colour"),
+    ("favor", "favour"),
+    ("labor", "labour"),
+];
+
+/// Historical spelling variants of word forms, e.g. "shew" for "show".
+///
+/// Forms and variants are lowercase, as in the `word` field of the lexicon.
+#[derive(Debug, Clone, Default)]
+pub struct SpellingVariants {
+    variants: FxHashMap<String, Vec<String>>,
+}
+
+impl SpellingVariants {
+    /// The built-in starter list.
+    pub fn builtin() -> Self {
+        let mut variants = Self::default();
+        for (form, variant) in BUILTIN {
+            variants.add(form, variant);
+        }
+        variants
+    }
+
+    /// Read variants from a CSV file with a header and two columns: the
+    /// modern form and a variant.
+    pub fn read(path: &Path) -> Result<Self> {
+        debug!("{}: reading...", path.to_string_lossy());
+        let mut variants = Self::default();
+        let mut reader = csv::Reader::from_path(path)?;
+        for record in reader.records() {
+            let record = record?;
+            match (record.get(0), record.get(1)) {
+                (Some(form), Some(variant)) => variants.add(form.trim(), variant.trim()),
+                _ => bail!("{}: expected two columns", path.to_string_lossy()),
+            }
+        }
+        info!(
+            "{}: variants for {} forms",
+            path.to_string_lossy(),
+            variants.variants.len()
+        );
+        Ok(variants)
+    }
+
+    pub fn add(&mut self, form: &str, variant: &str) {
+        let variants = self.variants.entry(form.to_lowercase()).or_default();
+        let variant = variant.to_lowercase();
+        if !variants.contains(&variant) {
+            variants.push(variant);
+        }
+    }
+
+    /// Add all variants of `other`, e.g. user-supplied variants to the
+    /// built-in list.
+    pub fn extend(&mut self, other: &SpellingVariants) {
+        for (form, variants) in &other.variants {
+            for variant in variants {
+                self.add(form, variant);
+            }
+        }
+    }
+
+    pub fn variants(&self, form: &str) -> &[String] {
+        self.variants.get(form).map_or(&[], |v| v.as_slice())
+    }
+}
+
+impl Coha {
+    /// Filter for words whose form is `form` or one of its variants, e.g.
+    /// "show" and "shew".
+    pub fn get_filter_with_variants(&self, form: &str, variants: &SpellingVariants) -> CohaFilter {
+        let form = form.to_lowercase();
+        std::iter::once(&form)
+            .chain(variants.variants(&form))
+            .flat_map(|f| self.word_ids_by_word(f))
+            .collect()
+    }
+
+    /// Add to the filter the words whose form is a variant of the form of a
+    /// word in the filter. Only variants that occur in the lexicon are added;
+    /// variants that COHA splits into several tokens (e.g. "any thing") can
+    /// not be matched by a single filter and are skipped. Filters other than
+    /// [`CohaFilter::Hash`] are returned unchanged.
+    pub fn expand_variants(&self, filter: &CohaFilter, variants: &SpellingVariants) -> CohaFilter {
+        let CohaFilter::Hash(word_ids) = filter else {
+            return filter.clone();
+        };
+        let forms: FxHashSet<&str> = word_ids
+            .iter()
+            .filter_map(|&id| self.word(id))
+            .flat_map(|w| variants.variants(&w.word))
+            .filter(|v| !v.contains(' '))
+            .map(|v| v.as_str())
+            .collect();
+        if forms.is_empty() {
+            return filter.clone();
+        }
+        let expanded: CohaFilter = forms
+            .into_iter()
+            .flat_map(|f| self.word_ids_by_word(f))
+            .collect();
+        filter | &expanded
+    }
+}
@@ -0,0 +1,122 @@
+use crate::{Coha, TextId};
+use anyhow::Result;
+use log::{debug, info, warn};
+use rayon::prelude::*;
+use rustc_hash::{FxHashMap, FxHashSet};
+use serde::Serialize;
+use std::io::Write;
+
+/// A text whose number of tokens in the corpus files differs from the
+/// number of words in the sources table.
+#[derive(Debug, Clone, Serialize)]
+pub struct WordCountMismatch {
+    pub text_id: TextId,
+    /// Corpus file identifier (decade, e.g. "1850s").
+    pub decade: String,
+    /// Number of words according to the sources table.
+    pub expected: usize,
+    /// Number of tokens found in the corpus files.
+    pub found: usize,
+}
+
+impl WordCountMismatch {
+    /// Difference relative to the expected number of words.
+    pub fn relative_difference(&self) -> f64 {
+        let diff = self.found.abs_diff(self.expected) as f64;
+        if self.expected == 0 {
+            f64::INFINITY
+        } else {
+            diff / self.expected as f64
+        }
+    }
+}
+
+impl Coha {
+    /// Count the tokens of each text in the corpus files and compare with
+    /// the number of words in the sources table, e.g. to detect damaged or
+    /// truncated corpus files. Returns the texts whose relative difference
+    /// exceeds `max_relative_difference`, sorted by text ID; texts of a
+    /// decade whose corpus file exists but that have no tokens are included
+    /// with `found` 0. This reads all corpus files.
+    ///
+    /// COHA does not count all tokens (e.g. punctuation) as words, so small
+    /// differences are normal.
+    pub fn verify_word_counts(
+        &self,
+        max_relative_difference: f64,
+    ) -> Result<Vec<WordCountMismatch>> {
+        let lenient = self.load_options.lenient;
+        let per_file = self
+            .coha_files
+            .par_iter()
+            .filter(|cf| cf.exists())
+            .map(|cf| {
+                let mut counts: FxHashMap<TextId, usize> = FxHashMap::default();
+                cf.scan_tokens(lenient, |token| {
+                    *counts.entry(token.text_id).or_default() += 1
+                })?;
+                Ok((cf.identifier.as_str(), counts))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        let found: FxHashMap<TextId, usize> = per_file
+            .iter()
+            .flat_map(|(_, counts)| counts.iter().map(|(&id, &n)| (id, n)))
+            .collect();
+        let present: FxHashSet<&str> = per_file.iter().map(|(id, _)| *id).collect();
+        let mut mismatches = Vec::new();
+        for source in self.sources.values() {
+            let decade = format!("{}s", source.year.0 / 10 * 10);
+            if !present.contains(decade.as_str()) {
+                continue;
+            }
+            let mismatch = WordCountMismatch {
+                text_id: source.text_id,
+                decade,
+                expected: source.words,
+                found: found.get(&source.text_id).copied().unwrap_or(0),
+            };
+            if mismatch.relative_difference() > max_relative_difference {
+                mismatches.push(mismatch);
+            }
+        }
+        mismatches.sort_by_key(|m| m.text_id);
+        for m in &mismatches {
+            debug!(
+                "text {}: {} words in sources, {} tokens found",
+                m.text_id.0, m.expected, m.found
+            );
+        }
+        if mismatches.is_empty() {
+            info!("word counts: no discrepancies");
+        } else {
+            warn!("word counts: {} texts with discrepancies", mismatches.len());
+        }
+        Ok(mismatches)
+    }
+}
+
+/// Write the mismatches as CSV.
+pub fn write_word_count_mismatches<W: Write>(
+    writer: W,
+    mismatches: &[WordCountMismatch],
+) -> Result<()> {
+    let mut writer = csv::Writer::from_writer(writer);
+    writer.write_record([
+        "text ID",
+        "decade",
+        "expected",
+        "found",
+        "relative difference",
+    ])?;
+    for m in mismatches {
+        writer.write_record([
+            m.text_id.0.to_string(),
+            m.decade.clone(),
+            m.expected.to_string(),
+            m.found.to_string(),
+            format!("{:.3}", m.relative_difference()),
+        ])?;
+    }
+    writer.flush()?;
+    Ok(())
+}
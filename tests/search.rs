@@ -0,0 +1,925 @@
+use coha_filter::{
+    Coha, CollocationOptions, Estimate, FileSink, HitEstimate, HitOrder, LoadOptions, MemorySink,
+    OutputSink, Restrictions, ResultLayout, SearchOptions, SearchReport, SearchSpec, SinkWriter,
+    SyntheticCorpus, TextIndex, Thinning, WriterSink,
+};
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+/// A synthetic corpus in a fresh directory, removed when dropped.
+struct TempCorpus(PathBuf);
+
+impl TempCorpus {
+    fn new(name: &str) -> Self {
+        let dir = std::env::temp_dir().join(format!("coha-filter-{name}-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        SyntheticCorpus::default().write(&dir).unwrap();
+        Self(dir)
+    }
+}
+
+impl Drop for TempCorpus {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
+
+/// Run `query` on the corpus with `options`; returns the report and the
+/// result files.
+fn search(
+    coha: &Coha,
+    root_dir: &Path,
+    query: &str,
+    options: &SearchOptions,
+) -> (SearchReport, BTreeMap<PathBuf, Vec<u8>>) {
+    let spec = SearchSpec::parse_query("q", query).unwrap();
+    let compiled = spec.compile(coha).unwrap();
+    let search = compiled.search();
+    let sink = MemorySink::new();
+    let result_dir = root_dir.join("results");
+    let report = coha
+        .search_to(&result_dir, &[&search], options, Arc::new(sink.clone()))
+        .unwrap();
+    (report, sink.files())
+}
+
+/// The header and the rows of a CSV result file.
+fn read_csv(contents: &[u8]) -> (csv::StringRecord, Vec<csv::StringRecord>) {
+    let mut reader = csv::Reader::from_reader(contents);
+    let header = reader.headers().unwrap().clone();
+    let rows = reader.records().map(|r| r.unwrap()).collect();
+    (header, rows)
+}
+
+/// Occurrences of "going to" in each decade, read directly from the corpus
+/// files.
+fn count_going_to(root_dir: &Path) -> BTreeMap<String, usize> {
+    let lexicon = fs::read_to_string(root_dir.join("shared/coha_lexicon.txt")).unwrap();
+    let id = |word: &str| {
+        lexicon
+            .lines()
+            .find_map(|line| {
+                let fields: Vec<&str> = line.split('\t').collect();
+                (fields.get(2) == Some(&word)).then(|| fields[0].to_owned())
+            })
+            .unwrap()
+    };
+    let (going, to) = (id("going"), id("to"));
+    let mut counts = BTreeMap::new();
+    for entry in fs::read_dir(root_dir.join("db")).unwrap() {
+        let dir = entry.unwrap().path();
+        let decade = dir.file_name().unwrap().to_string_lossy()[4..].to_owned();
+        let file = dir.join(format!("coha_db_{decade}.txt"));
+        let lines: Vec<Vec<String>> = fs::read_to_string(file)
+            .unwrap()
+            .lines()
+            .map(|line| line.split('\t').map(|x| x.to_owned()).collect())
+            .collect();
+        let hits = lines
+            .windows(2)
+            .filter(|w| w[0][0] == w[1][0] && w[0][2] == going && w[1][2] == to)
+            .count();
+        counts.insert(decade, hits);
+    }
+    counts
+}
+
+#[test]
+fn search_synthetic_corpus() {
+    let corpus = TempCorpus::new("search");
+    let coha = Coha::load(&corpus.0).unwrap();
+    let spec = SearchSpec::parse_query("going-to", "going to").unwrap();
+    let compiled = spec.compile(&coha).unwrap();
+    let search = compiled.search();
+    let sink = MemorySink::new();
+    let result_dir = corpus.0.join("results");
+    let report = coha
+        .search_to(
+            &result_dir,
+            &[&search],
+            &SearchOptions::default(),
+            Arc::new(sink.clone()),
+        )
+        .unwrap();
+
+    let expected = count_going_to(&corpus.0);
+    let total: usize = expected.values().sum();
+    assert!(total > 0);
+    assert_eq!(report.searches[0].hits, total);
+    assert_eq!(report.searches[0].by_decade, expected);
+
+    let files = sink.files();
+    assert_eq!(files.len(), expected.len());
+    for (decade, hits) in &expected {
+        let path = Path::new("going-to").join(format!("going-to-{decade}.csv"));
+        let mut reader = csv::Reader::from_reader(&files[&path][..]);
+        let header = reader.headers().unwrap().clone();
+        let column = |name: &str| header.iter().position(|h| h == name).unwrap();
+        let (word_1, word_2) = (column("wordCS 1"), column("wordCS 2"));
+        let rows: Vec<csv::StringRecord> = reader.records().map(|r| r.unwrap()).collect();
+        assert_eq!(rows.len(), *hits, "{decade}");
+        for row in rows {
+            assert_eq!((&row[word_1], &row[word_2]), ("going", "to"));
+        }
+    }
+}
+
+#[test]
+fn filler_frequency_order_in_merged_file() {
+    let corpus = TempCorpus::new("filler-frequency");
+    let coha = Coha::load(&corpus.0).unwrap();
+    let options = SearchOptions {
+        order: HitOrder::FillerFrequency,
+        merge_decades: true,
+        ..Default::default()
+    };
+    let (report, files) = search(&coha, &corpus.0, "going to *", &options);
+    let (header, rows) = read_csv(&files[Path::new("q/q.csv")]);
+    assert_eq!(rows.len(), report.searches[0].hits);
+    let column = |name: &str| header.iter().position(|h| h == name).unwrap();
+    let (year, word_3) = (column("year"), column("word 3"));
+    let decades: BTreeSet<&str> = rows.iter().map(|r| &r[year][..3]).collect();
+    assert!(decades.len() > 1);
+    let mut freq: BTreeMap<&str, usize> = BTreeMap::new();
+    for row in &rows {
+        *freq.entry(&row[word_3]).or_default() += 1;
+    }
+    // sorted across all decades, not only within each part
+    let keys: Vec<(Reverse<usize>, &str)> = rows
+        .iter()
+        .map(|r| (Reverse(freq[&r[word_3]]), &r[word_3]))
+        .collect();
+    assert!(keys.is_sorted());
+}
+
+#[test]
+fn row_limit_chunks() {
+    let corpus = TempCorpus::new("row-limit");
+    let coha = Coha::load(&corpus.0).unwrap();
+    let options = SearchOptions {
+        max_rows_per_file: Some(10),
+        ..Default::default()
+    };
+    let (report, files) = search(&coha, &corpus.0, "going to *", &options);
+    let mut total = 0;
+    for decade in ["1810s", "1820s", "1830s"] {
+        let chunks: Vec<usize> = (1..)
+            .map(|chunk| PathBuf::from(format!("q/q-{decade}-{chunk:03}.csv")))
+            .map_while(|path| files.get(&path))
+            .map(|contents| read_csv(contents).1.len())
+            .collect();
+        // every chunk but the last is full
+        let (last, full) = chunks.split_last().unwrap();
+        assert!(full.iter().all(|&rows| rows == 10), "{decade}: {chunks:?}");
+        assert!(*last <= 10);
+        total += chunks.iter().sum::<usize>();
+    }
+    assert_eq!(total, report.searches[0].hits);
+
+    let options = SearchOptions {
+        max_rows_per_file: Some(0),
+        ..Default::default()
+    };
+    let spec = SearchSpec::parse_query("q", "going to").unwrap();
+    let compiled = spec.compile(&coha).unwrap();
+    let sink = Arc::new(MemorySink::new());
+    let result_dir = corpus.0.join("results");
+    assert!(coha
+        .search_to(&result_dir, &[&compiled.search()], &options, sink)
+        .is_err());
+}
+
+/// The files below `dir` and their contents.
+fn read_files(dir: &Path) -> BTreeMap<PathBuf, Vec<u8>> {
+    let mut files = BTreeMap::new();
+    for entry in fs::read_dir(dir).unwrap() {
+        let path = entry.unwrap().path();
+        files.insert(path.clone(), fs::read(&path).unwrap());
+    }
+    files
+}
+
+/// Local result files that are never completed, as after a crash.
+struct CrashingSink(FileSink);
+
+struct CrashingWriter(Box<dyn SinkWriter>);
+
+impl OutputSink for CrashingSink {
+    fn create(&self, file: &Path) -> anyhow::Result<Box<dyn SinkWriter>> {
+        Ok(Box::new(CrashingWriter(self.0.create(file)?)))
+    }
+}
+
+impl Write for CrashingWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.0.flush()
+    }
+}
+
+impl SinkWriter for CrashingWriter {
+    fn finish(self: Box<Self>) -> anyhow::Result<()> {
+        anyhow::bail!("crashed")
+    }
+
+    fn sync(&mut self) -> anyhow::Result<()> {
+        self.0.sync()
+    }
+}
+
+#[test]
+fn resume_from_checkpoint() {
+    let corpus = TempCorpus::new("checkpoint");
+    let coha = Coha::load(&corpus.0).unwrap();
+    let spec = SearchSpec::parse_query("q", "going to *").unwrap();
+    let compiled = spec.compile(&coha).unwrap();
+    let searches = [&compiled.search()];
+    let options = SearchOptions {
+        checkpoint: true,
+        checkpoint_interval: Some(2),
+        ..Default::default()
+    };
+    let expected_dir = corpus.0.join("expected");
+    let expected = coha
+        .search_with(&expected_dir, &searches, &options)
+        .unwrap();
+
+    // the run fails when the first corpus file is complete, after its
+    // progress and that of others has been recorded
+    let result_dir = corpus.0.join("results");
+    let sink = Arc::new(CrashingSink(FileSink::new(&result_dir)));
+    assert!(coha
+        .search_to(&result_dir, &searches, &options, sink)
+        .is_err());
+    assert!(read_files(&result_dir.join("q"))
+        .keys()
+        .any(|p| p.to_string_lossy().ends_with(".partial")));
+
+    // the texts before the recorded offsets must not be read again, so
+    // overwrite them with lines that cannot be parsed
+    let checkpoint = fs::read_to_string(result_dir.join("checkpoint.jsonl")).unwrap();
+    let mut offsets = BTreeMap::new();
+    for line in checkpoint.lines() {
+        let entry: serde_json::Value = serde_json::from_str(line).unwrap();
+        if let Some(offset) = entry["position"]["offset"].as_u64() {
+            offsets.insert(entry["identifier"].as_str().unwrap().to_owned(), offset);
+        }
+    }
+    assert!(!offsets.is_empty());
+    for (decade, offset) in offsets {
+        let path = corpus
+            .0
+            .join(format!("db/wlp_{decade}/coha_db_{decade}.txt"));
+        let mut contents = fs::read(&path).unwrap();
+        let offset = offset as usize;
+        contents[..offset - 1].fill(b'x');
+        fs::write(&path, contents).unwrap();
+    }
+
+    let report = coha.search_with(&result_dir, &searches, &options).unwrap();
+    assert_eq!(report.searches[0].hits, expected.searches[0].hits);
+    assert_eq!(report.searches[0].by_decade, expected.searches[0].by_decade);
+    let strip = |files: BTreeMap<PathBuf, Vec<u8>>, dir: &Path| -> BTreeMap<PathBuf, Vec<u8>> {
+        files
+            .into_iter()
+            .map(|(p, c)| (p.strip_prefix(dir).unwrap().to_owned(), c))
+            .collect()
+    };
+    assert_eq!(
+        strip(read_files(&result_dir.join("q")), &result_dir),
+        strip(read_files(&expected_dir.join("q")), &expected_dir),
+    );
+}
+
+#[test]
+fn split_by_genre() {
+    let corpus = TempCorpus::new("split-by-genre");
+    let coha = Coha::load(&corpus.0).unwrap();
+    for merge_decades in [false, true] {
+        let options = SearchOptions {
+            split_by_genre: true,
+            merge_decades,
+            ..Default::default()
+        };
+        let (report, files) = search(&coha, &corpus.0, "going to *", &options);
+        let mut by_genre = BTreeMap::new();
+        for (path, contents) in &files {
+            let name = path.file_name().unwrap().to_string_lossy();
+            let (header, rows) = read_csv(contents);
+            let column = |name: &str| header.iter().position(|h| h == name).unwrap();
+            let (genre, year) = (column("genre"), column("year"));
+            for row in &rows {
+                let decade = format!("{}0s", &row[year][..3]);
+                let expected = match merge_decades {
+                    false => format!("q-{decade}-{}.csv", &row[genre]),
+                    true => format!("q-{}.csv", &row[genre]),
+                };
+                assert_eq!(name, expected);
+            }
+            if let Some(row) = rows.first() {
+                *by_genre.entry(row[genre].to_owned()).or_default() += rows.len();
+            }
+        }
+        assert!(by_genre.len() > 1);
+        assert_eq!(by_genre, report.searches[0].by_genre);
+    }
+}
+
+#[test]
+fn file_template() {
+    let corpus = TempCorpus::new("file-template");
+    let coha = Coha::load(&corpus.0).unwrap();
+    let options = SearchOptions {
+        file_template: Some("{label}/{genre}/{decade}.jsonl".to_owned()),
+        ..Default::default()
+    };
+    let (report, files) = search(&coha, &corpus.0, "going to *", &options);
+    let mut rows = 0;
+    for (path, contents) in &files {
+        for line in std::str::from_utf8(contents).unwrap().lines() {
+            let row: serde_json::Value = serde_json::from_str(line).unwrap();
+            let decade = format!("{}0s", &row["year"].as_str().unwrap()[..3]);
+            let genre = row["genre"].as_str().unwrap();
+            assert_eq!(
+                path,
+                &Path::new("q").join(genre).join(format!("{decade}.jsonl"))
+            );
+            rows += 1;
+        }
+    }
+    assert_eq!(rows, report.searches[0].hits);
+
+    let spec = SearchSpec::parse_query("q", "going to").unwrap();
+    let compiled = spec.compile(&coha).unwrap();
+    for template in ["{label}/{nope}.csv", "../{label}.csv", "{label}.xml"] {
+        let options = SearchOptions {
+            file_template: Some(template.to_owned()),
+            ..Default::default()
+        };
+        let sink = Arc::new(MemorySink::new());
+        let result = coha.search_to(
+            &corpus.0.join("results"),
+            &[&compiled.search()],
+            &options,
+            sink,
+        );
+        assert!(result.is_err(), "{template}");
+    }
+}
+
+/// The text ID, the context before, and the context after of each hit in
+/// the default result files, by decade.
+fn full_contexts(coha: &Coha, root_dir: &Path) -> BTreeMap<String, Vec<[String; 3]>> {
+    let (_, files) = search(coha, root_dir, "going to", &SearchOptions::default());
+    let mut contexts = BTreeMap::new();
+    for (path, contents) in &files {
+        let name = path.file_stem().unwrap().to_string_lossy();
+        let (header, rows) = read_csv(contents);
+        let column = |name: &str| header.iter().position(|h| h == name).unwrap();
+        let (text_id, before, after) = (column("text ID"), column("before"), column("after"));
+        let rows = rows
+            .iter()
+            .map(|row| [&row[text_id], &row[before], &row[after]].map(|x| x.to_owned()))
+            .collect();
+        contexts.insert(name[2..].to_owned(), rows);
+    }
+    contexts
+}
+
+#[test]
+fn antconc_layout() {
+    let corpus = TempCorpus::new("antconc");
+    let coha = Coha::load(&corpus.0).unwrap();
+    let expected = full_contexts(&coha, &corpus.0);
+    let options = SearchOptions {
+        layout: ResultLayout::AntConc,
+        ..Default::default()
+    };
+    let (_, files) = search(&coha, &corpus.0, "going to", &options);
+    assert_eq!(files.len(), expected.len());
+    for (decade, contexts) in &expected {
+        let path = Path::new("q").join(format!("q-{decade}.txt"));
+        let lines: Vec<Vec<&str>> = std::str::from_utf8(&files[&path])
+            .unwrap()
+            .lines()
+            .map(|line| line.split('\t').collect())
+            .collect();
+        assert_eq!(lines.len(), contexts.len());
+        for (line, [text_id, before, after]) in lines.iter().zip(contexts) {
+            let file = format!("{text_id}.txt");
+            assert_eq!(line, &[before, "going to", after, &file]);
+        }
+    }
+}
+
+#[test]
+fn cqpweb_and_lancsbox_layouts() {
+    let corpus = TempCorpus::new("cqpweb-lancsbox");
+    let coha = Coha::load(&corpus.0).unwrap();
+    let expected = full_contexts(&coha, &corpus.0);
+    let layouts = [
+        (ResultLayout::CqpWeb, "tsv", b'\t', "Text ID"),
+        (ResultLayout::LancsBox, "csv", b',', "File"),
+    ];
+    for (layout, extension, delimiter, first_column) in layouts {
+        let options = SearchOptions {
+            layout,
+            ..Default::default()
+        };
+        let (_, files) = search(&coha, &corpus.0, "going to", &options);
+        assert_eq!(files.len(), expected.len());
+        for (decade, contexts) in &expected {
+            let path = Path::new("q").join(format!("q-{decade}.{extension}"));
+            let mut reader = csv::ReaderBuilder::new()
+                .delimiter(delimiter)
+                .from_reader(&files[&path][..]);
+            assert_eq!(&reader.headers().unwrap()[0], first_column);
+            let rows: Vec<csv::StringRecord> = reader.records().map(|r| r.unwrap()).collect();
+            assert_eq!(rows.len(), contexts.len());
+            for (row, [text_id, before, after]) in rows.iter().zip(contexts) {
+                assert_eq!(row, vec![text_id, before, "going to", after]);
+            }
+        }
+    }
+}
+
+#[test]
+fn line_markers() {
+    let corpus = TempCorpus::new("line-markers");
+    let coha = Coha::load(&corpus.0).unwrap();
+    let options = SearchOptions {
+        line_markers: Some(("<<".to_owned(), ">>".to_owned())),
+        ..Default::default()
+    };
+    let (report, files) = search(&coha, &corpus.0, "going to", &options);
+    let mut hits = 0;
+    for contents in files.values() {
+        let (header, rows) = read_csv(contents);
+        let column = |name: &str| header.iter().position(|h| h == name).unwrap();
+        let (line, before, after) = (column("line"), column("before"), column("after"));
+        for row in &rows {
+            let expected = [&row[before], "<<going to>>", &row[after]]
+                .into_iter()
+                .filter(|x| !x.is_empty())
+                .collect::<Vec<_>>()
+                .join(" ");
+            assert_eq!(&row[line], expected);
+        }
+        hits += rows.len();
+    }
+    assert!(hits > 0);
+    assert_eq!(hits, report.searches[0].hits);
+}
+
+#[test]
+fn context_chars() {
+    const CHARS: usize = 20;
+    let corpus = TempCorpus::new("context-chars");
+    let coha = Coha::load(&corpus.0).unwrap();
+    let full = full_contexts(&coha, &corpus.0);
+    let options = SearchOptions {
+        context_chars: Some(CHARS),
+        ..Default::default()
+    };
+    let (_, files) = search(&coha, &corpus.0, "going to", &options);
+    // the context is the run of whole words closest to CHARS characters
+    let check = |context: &str, words: Vec<&str>| {
+        let len = |n: usize| {
+            let words = &words[..n];
+            words.iter().map(|w| w.chars().count()).sum::<usize>() + n.saturating_sub(1)
+        };
+        let n = (0..=words.len()).find(|&n| len(n) == context.chars().count());
+        let n = n.unwrap_or_else(|| panic!("{context:?}"));
+        if n < words.len() {
+            assert!(
+                len(n) >= CHARS || len(n + 1) - CHARS > CHARS - len(n),
+                "{context:?}"
+            );
+        }
+        if n > 0 && len(n) > CHARS {
+            assert!(len(n) - CHARS <= CHARS - len(n - 1), "{context:?}");
+        }
+    };
+    let mut hits = 0;
+    for (decade, contexts) in &full {
+        let path = Path::new("q").join(format!("q-{decade}.csv"));
+        let (header, rows) = read_csv(&files[&path]);
+        let column = |name: &str| header.iter().position(|h| h == name).unwrap();
+        let (before, after) = (column("before"), column("after"));
+        assert_eq!(rows.len(), contexts.len());
+        for (row, [_, full_before, full_after]) in rows.iter().zip(contexts) {
+            assert!(full_before.ends_with(&row[before]));
+            assert!(full_after.starts_with(&row[after]));
+            check(&row[before], full_before.split(' ').rev().collect());
+            check(&row[after], full_after.split(' ').collect());
+            hits += 1;
+        }
+    }
+    assert!(hits > 0);
+}
+
+#[test]
+fn sentence_numbers() {
+    let corpus = TempCorpus::new("sentence-numbers");
+    let coha = Coha::load(&corpus.0).unwrap();
+    let options = SearchOptions {
+        sentence_numbers: true,
+        ..Default::default()
+    };
+    let (_, files) = search(&coha, &corpus.0, "going to", &options);
+    let boundary = |word: &str| matches!(word, "." | "!" | "?" | "..." | "<p>");
+    let mut hits = BTreeMap::new();
+    for contents in files.values() {
+        let (header, rows) = read_csv(contents);
+        let column = |name: &str| header.iter().position(|h| h == name).unwrap();
+        let text_id = column("text ID");
+        let (position, sentence) = (column("position"), column("sentence"));
+        let (in_sentence, before) = (column("sentence position"), column("before"));
+        for row in &rows {
+            let p: usize = row[in_sentence].parse().unwrap();
+            assert!(p >= 1);
+            // the sentence starts p - 1 words before the match
+            let words: Vec<&str> = row[before].split(' ').filter(|w| !w.is_empty()).collect();
+            assert!(words.iter().rev().take(p - 1).all(|w| !boundary(w)));
+            if let Some(w) = words.len().checked_sub(p).map(|i| words[i]) {
+                assert!(boundary(w), "{w:?}");
+            }
+            let hit: (usize, usize, usize) = (
+                row[position].parse().unwrap(),
+                row[sentence].parse().unwrap(),
+                p,
+            );
+            hits.entry(row[text_id].to_owned())
+                .or_insert_with(Vec::new)
+                .push(hit);
+        }
+    }
+    assert!(!hits.is_empty());
+    for mut hits in hits.into_values() {
+        hits.sort();
+        for w in hits.windows(2) {
+            let ((pos_a, sentence_a, p_a), (pos_b, sentence_b, p_b)) = (w[0], w[1]);
+            assert!(sentence_a <= sentence_b);
+            if sentence_a == sentence_b {
+                assert_eq!(pos_b - pos_a, p_b - p_a);
+            }
+        }
+    }
+}
+
+#[test]
+fn decade_frequencies() {
+    let corpus = TempCorpus::new("decade-frequencies");
+    let mut decades: Vec<PathBuf> = fs::read_dir(corpus.0.join("db"))
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .collect();
+    decades.sort();
+    // a decade in the sources table without a corpus file
+    fs::remove_dir_all(&decades[0]).unwrap();
+    let expected = count_going_to(&corpus.0);
+    let coha = Coha::load(&corpus.0).unwrap();
+    let (report, _) = search(&coha, &corpus.0, "going to", &SearchOptions::default());
+    let frequencies = coha
+        .decade_frequencies(&report, "q", &Restrictions::default(), None)
+        .unwrap();
+    let hits: BTreeMap<String, usize> = frequencies
+        .iter()
+        .map(|f| (f.decade.clone(), f.hits))
+        .collect();
+    assert_eq!(hits, expected);
+    for f in &frequencies {
+        assert!(f.words > 0);
+        assert_eq!(f.per_million, f.hits as f64 * 1e6 / f.words as f64);
+    }
+
+    let options = SearchOptions {
+        max_hits: Some(1),
+        ..Default::default()
+    };
+    let (report, _) = search(&coha, &corpus.0, "going to", &options);
+    assert!(coha
+        .decade_frequencies(&report, "q", &Restrictions::default(), None)
+        .is_err());
+}
+
+#[test]
+fn collocates_leave_out_markers() {
+    let corpus = TempCorpus::new("collocates");
+    let mut coha = Coha::load(&corpus.0).unwrap();
+    coha.compute_frequencies().unwrap();
+    let spec = SearchSpec::parse_query("q", "going to").unwrap();
+    let compiled = spec.compile(&coha).unwrap();
+    let options = CollocationOptions {
+        left: 10,
+        right: 10,
+        min_count: 1,
+        ..Default::default()
+    };
+    let collocates = coha.collocates(&compiled.search(), &options).unwrap();
+    assert!(!collocates.is_empty());
+    for c in &collocates {
+        assert!(c.word != "<p>" && c.word != "@", "{}", c.word);
+    }
+}
+
+#[test]
+fn text_index_notices_change_within_a_second() {
+    let corpus = TempCorpus::new("text-index");
+    let db = corpus.0.join("db");
+    let decade = fs::read_dir(&db).unwrap().next().unwrap().unwrap().path();
+    let name = decade.file_name().unwrap().to_string_lossy()[4..].to_owned();
+    let file = decade.join(format!("coha_db_{name}.txt"));
+    let base = std::time::UNIX_EPOCH + std::time::Duration::from_secs(1_600_000_000);
+    let set_modified = |nanos| {
+        let f = fs::File::options().write(true).open(&file).unwrap();
+        f.set_modified(base + std::time::Duration::from_nanos(nanos))
+            .unwrap();
+    };
+    set_modified(100);
+    let index_path = corpus.0.join("text-index.json");
+    Coha::load(&corpus.0)
+        .unwrap()
+        .text_index_cached(&index_path)
+        .unwrap();
+
+    // swap the first two texts: the same size, but different byte ranges
+    let contents = fs::read_to_string(&file).unwrap();
+    let lines: Vec<&str> = contents.lines().collect();
+    let text_id = |line: &str| line.split('\t').next().unwrap().to_owned();
+    let first = lines
+        .iter()
+        .take_while(|l| text_id(l) == text_id(lines[0]))
+        .count();
+    let second_len = lines[first..]
+        .iter()
+        .take_while(|l| text_id(l) == text_id(lines[first]))
+        .count();
+    let mut swapped: Vec<&str> = lines[first..first + second_len].to_vec();
+    swapped.extend(&lines[..first]);
+    swapped.extend(&lines[first + second_len..]);
+    let mut new_contents = swapped.join("\n");
+    new_contents.push('\n');
+    assert_eq!(new_contents.len(), contents.len());
+    fs::write(&file, new_contents).unwrap();
+    set_modified(200);
+
+    let coha = Coha::load(&corpus.0).unwrap();
+    let cached = coha.text_index_cached(&index_path).unwrap().clone();
+    let coha = Coha::load(&corpus.0).unwrap();
+    let fresh = coha.text_index().unwrap();
+    let ranges = |index: &TextIndex| {
+        let file = index.files.iter().find(|f| f.identifier == name).unwrap();
+        file.texts
+            .iter()
+            .map(|t| (t.text_id, t.start, t.end))
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(ranges(&cached), ranges(fresh));
+}
+
+#[test]
+fn two_pass_gives_the_same_results() {
+    let corpus = TempCorpus::new("two-pass");
+    let coha = Coha::load(&corpus.0).unwrap();
+    for query in ["going to", "going to *", "* to see"] {
+        let (report, files) = search(&coha, &corpus.0, query, &SearchOptions::default());
+        let options = SearchOptions {
+            two_pass: true,
+            ..Default::default()
+        };
+        let (two_pass_report, two_pass_files) = search(&coha, &corpus.0, query, &options);
+        assert!(report.searches[0].hits > 0, "{query}");
+        assert_eq!(
+            serde_json::to_value(&two_pass_report.searches).unwrap(),
+            serde_json::to_value(&report.searches).unwrap(),
+            "{query}"
+        );
+        assert!(two_pass_files == files, "{query}");
+    }
+}
+
+#[test]
+fn partial_files_are_renamed_when_complete() {
+    let corpus = TempCorpus::new("partial-files");
+    let coha = Coha::load(&corpus.0).unwrap();
+    let spec = SearchSpec::parse_query("q", "going to").unwrap();
+    let compiled = spec.compile(&coha).unwrap();
+    let searches = [&compiled.search()];
+    let options = SearchOptions::default();
+
+    let result_dir = corpus.0.join("results");
+    let report = coha.search_with(&result_dir, &searches, &options).unwrap();
+    let names: BTreeSet<String> = read_files(&result_dir.join("q"))
+        .keys()
+        .map(|p| p.file_name().unwrap().to_string_lossy().into_owned())
+        .collect();
+    let expected: BTreeSet<String> = report.searches[0]
+        .by_decade
+        .keys()
+        .map(|decade| format!("q-{decade}.csv"))
+        .collect();
+    assert_eq!(names, expected);
+
+    // files that are never completed keep their partial names
+    let crashed_dir = corpus.0.join("crashed");
+    let sink = Arc::new(CrashingSink(FileSink::new(&crashed_dir)));
+    assert!(coha
+        .search_to(&crashed_dir, &searches, &options, sink)
+        .is_err());
+    let names: Vec<PathBuf> = read_files(&crashed_dir.join("q")).into_keys().collect();
+    assert!(!names.is_empty());
+    for name in names {
+        let name = name.to_string_lossy();
+        let complete = name.strip_suffix(".partial").expect(&name);
+        assert!(!Path::new(complete).exists());
+    }
+}
+
+#[test]
+fn one_file_per_text() {
+    let corpus = TempCorpus::new("per-text");
+    let coha = Coha::load(&corpus.0).unwrap();
+    let (expected, expected_files) =
+        search(&coha, &corpus.0, "going to", &SearchOptions::default());
+
+    // split the corpus files into files with one text each, half of them
+    // without the text ID column
+    let db = corpus.0.join("db");
+    let texts_dir = corpus.0.join("texts");
+    fs::create_dir(&texts_dir).unwrap();
+    for entry in fs::read_dir(&db).unwrap() {
+        let dir = entry.unwrap().path();
+        for file in fs::read_dir(&dir).unwrap() {
+            let contents = fs::read_to_string(file.unwrap().path()).unwrap();
+            let mut texts: BTreeMap<usize, String> = BTreeMap::new();
+            for line in contents.lines() {
+                let (text_id, rest) = line.split_once('\t').unwrap();
+                let text_id: usize = text_id.parse().unwrap();
+                let text = texts.entry(text_id).or_default();
+                text.push_str(if text_id.is_multiple_of(2) {
+                    line
+                } else {
+                    rest
+                });
+                text.push('\n');
+            }
+            for (text_id, text) in texts {
+                fs::write(texts_dir.join(format!("text_{text_id}.txt")), text).unwrap();
+            }
+        }
+        fs::remove_dir_all(dir).unwrap();
+    }
+    fs::rename(&texts_dir, db.join("texts")).unwrap();
+
+    let options = LoadOptions {
+        text_file_pattern: Some(r"text_(\d+)\.txt$".to_owned()),
+        ..Default::default()
+    };
+    let coha = Coha::load_with(&corpus.0, &options).unwrap();
+    let (report, files) = search(&coha, &corpus.0, "going to", &SearchOptions::default());
+    assert!(expected.searches[0].hits > 0);
+    assert_eq!(
+        serde_json::to_value(&report.searches).unwrap(),
+        serde_json::to_value(&expected.searches).unwrap(),
+    );
+    assert!(files == expected_files);
+}
+
+#[test]
+fn thinning() {
+    let corpus = TempCorpus::new("thinning");
+    let coha = Coha::load(&corpus.0).unwrap();
+    let (full, _) = search(&coha, &corpus.0, "going to *", &SearchOptions::default());
+    let options = SearchOptions {
+        thinning: Some(Thinning {
+            tokens_per_hit: 300,
+            seed: 7,
+        }),
+        ..Default::default()
+    };
+    let (report, files) = search(&coha, &corpus.0, "going to *", &options);
+    let thinned: usize = report.files.iter().map(|f| f.thinned).sum();
+    assert!(thinned > 0);
+    assert_eq!(report.searches[0].hits + thinned, full.searches[0].hits);
+    let rows: usize = files.values().map(|c| read_csv(c).1.len()).sum();
+    assert_eq!(rows, report.searches[0].hits);
+    for (text_id, hits) in &report.searches[0].by_text {
+        assert!(*hits <= full.searches[0].by_text[text_id]);
+    }
+
+    // the same seed gives the same sample
+    let (_, again) = search(&coha, &corpus.0, "going to *", &options);
+    assert!(again == files);
+}
+
+#[test]
+fn estimate_hits() {
+    let corpus = TempCorpus::new("estimate");
+    let coha = Coha::load(&corpus.0).unwrap();
+    let expected = count_going_to(&corpus.0);
+    let spec = SearchSpec::parse_query("q", "going to").unwrap();
+    let compiled = spec.compile(&coha).unwrap();
+    let searches = [&compiled.search()];
+
+    // with all texts, the estimate is exact
+    let all = Estimate {
+        fraction: 1.0,
+        ..Default::default()
+    };
+    let estimates = coha.estimate_hits(&searches, &all).unwrap();
+    assert_eq!(estimates.len(), expected.len() + 1);
+    for e in &estimates {
+        let hits = match expected.get(&e.decade) {
+            Some(&hits) => hits,
+            None => expected.values().sum(),
+        };
+        assert_eq!(e.sampled_texts, e.texts);
+        assert_eq!(e.sampled_hits, hits);
+        assert!((e.hits - hits as f64).abs() < 1e-6, "{}", e.decade);
+        assert!(e.standard_error.abs() < 1e-6, "{}", e.decade);
+    }
+
+    let sample = Estimate {
+        fraction: 0.3,
+        min_texts: 2,
+        seed: 3,
+    };
+    let estimates = coha.estimate_hits(&searches, &sample).unwrap();
+    for e in &estimates {
+        assert!(e.sampled_texts < e.texts, "{}", e.decade);
+        assert!(e.interval.0 >= e.sampled_hits as f64);
+        assert!(e.interval.0 <= e.hits && e.hits <= e.interval.1);
+    }
+    let again = coha.estimate_hits(&searches, &sample).unwrap();
+    let hits = |estimates: &[HitEstimate]| estimates.iter().map(|e| e.hits).collect::<Vec<_>>();
+    assert_eq!(hits(&again), hits(&estimates));
+}
+
+/// A writer whose bytes can be read while it is owned by a sink.
+#[derive(Clone, Default)]
+struct SharedBuffer(Arc<std::sync::Mutex<Vec<u8>>>);
+
+impl Write for SharedBuffer {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.lock().unwrap().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[test]
+fn writer_sink_frames() {
+    let corpus = TempCorpus::new("writer-sink");
+    let coha = Coha::load(&corpus.0).unwrap();
+    let options = SearchOptions::default();
+    let (_, expected) = search(&coha, &corpus.0, "going to", &options);
+
+    let spec = SearchSpec::parse_query("q", "going to").unwrap();
+    let compiled = spec.compile(&coha).unwrap();
+    let result_dir = corpus.0.join("results");
+    let buffer = SharedBuffer::default();
+    let sink = Arc::new(WriterSink::new(buffer.clone()));
+    coha.search_to(&result_dir, &[&compiled.search()], &options, sink)
+        .unwrap();
+    let stream = buffer.0.lock().unwrap().clone();
+    let mut rest = &stream[..];
+    let mut open: BTreeMap<PathBuf, Vec<u8>> = BTreeMap::new();
+    let mut files = BTreeMap::new();
+    while !rest.is_empty() {
+        let end = rest.iter().position(|&b| b == b'\n').unwrap();
+        let header = std::str::from_utf8(&rest[..end]).unwrap();
+        let (path, len) = header.split_once('\t').unwrap();
+        let len: usize = len.parse().unwrap();
+        let path = PathBuf::from(path);
+        let bytes = &rest[end + 1..end + 1 + len];
+        rest = &rest[end + 1 + len..];
+        if len == 0 {
+            files.insert(path.clone(), open.remove(&path).unwrap_or_default());
+        } else {
+            open.entry(path).or_default().extend_from_slice(bytes);
+        }
+    }
+    assert!(open.is_empty());
+    assert!(files == expected);
+
+    // a tab in a label would break the frame headers
+    let spec = SearchSpec::parse_query("q\t1", "going to").unwrap();
+    let compiled = spec.compile(&coha).unwrap();
+    let sink = Arc::new(WriterSink::new(SharedBuffer::default()));
+    let error = coha
+        .search_to(&result_dir, &[&compiled.search()], &options, sink.clone())
+        .unwrap_err();
+    assert!(error.to_string().contains("label"), "{error}");
+    assert!(sink.create(Path::new("q/q\n1.csv")).is_err());
+}